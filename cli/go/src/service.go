@@ -3,8 +3,10 @@ package main
 import "net/http"
 import "encoding/json"
 //import "io/ioutil"
+import "io"
 import "bytes"
 import "fmt"
+import "os"
 
 type NfcCard struct {
   id int
@@ -20,16 +22,37 @@ type RestService struct {
 }
 
 func createRestService(hostname string, port int) *RestService {
-  service := RestService {hostname: hostname, port: port, protocol: "http",}
+  service := RestService {hostname: hostname, port: port, protocol: "http", api_key: os.Getenv("ACONTROL_API_KEY"),}
+  if os.Getenv("ACONTROL_HTTPS") != "" {
+    service.protocol = "https"
+  }
   return &service
 }
 
+func (service RestService) request(method string, path string, body io.Reader) (*http.Response, error) {
+  req, err := http.NewRequest(method, service.createUri(path), body)
+
+  if err != nil {
+    return nil, err
+  }
+
+  if body != nil {
+    req.Header.Set("Content-Type", "application/json")
+  }
+
+  if service.api_key != "" {
+    req.Header.Set("Authorization", "Bearer " + service.api_key)
+  }
+
+  return http.DefaultClient.Do(req)
+}
+
 func (service RestService) createUri(path string) string {
   return fmt.Sprintf("%s://%s:%d/%s", service.protocol, service.hostname, service.port, path)
 }
 
 func (service RestService) nfcListCards() ([]NfcCard,error) {
-  resp, err := http.Get(service.createUri("nfc/card"))
+  resp, err := service.request("GET", "nfc/card", nil)
 
   if err != nil {
     return nil, err
@@ -82,7 +105,7 @@ func (service RestService) nfcAuthorizeCard(card NfcCard) error {
     return err
   }
 
-  resp, err := http.Post(service.createUri("nfc/card/authorize"), "application/json", bytes.NewBuffer(buf))
+  resp, err := service.request("POST", "nfc/card/authorize", bytes.NewBuffer(buf))
 
   if err != nil {
     return err
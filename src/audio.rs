@@ -27,6 +27,8 @@
  */
 mod buzzer;
 
+use std::collections::HashMap;
+
 pub trait Audio {
   fn init(&mut self) -> Result<(),String>;
   fn play_new(&mut self) -> Result<(), String>;
@@ -39,9 +41,9 @@ pub trait Audio {
   fn signature(&self) -> String;
 }
 
-pub fn audio_by_name(name: &str) -> Option<Box<dyn Audio+Sync+Send>> {
+pub fn audio_by_name(name: &str, params: &HashMap<String, String>) -> Option<Box<dyn Audio+Sync+Send>> {
     match name {
-      "buzzer" => return Some(Box::new(buzzer::Buzzer::new())),
+      "buzzer" => return Some(Box::new(buzzer::Buzzer::new(params))),
       _ => return None
     }
 }
@@ -33,6 +33,7 @@ use crate::log::LogType;
 
 use super::{Audio};
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
@@ -109,16 +110,20 @@ unsafe impl Sync for BuzzerThreadSafe {}
 
 unsafe impl Send for BuzzerThreadSafe {}
 
+const BUZZER_DEFAULT_DEVICE: &str = "/dev/buzzer";
+
 pub struct Buzzer {
+  device: String,
   devfile: Option<std::fs::File>,
   buzzer: Arc<Mutex<BuzzerThreadSafe>>,
   sound_worker: Option<std::thread::JoinHandle<Result<(), String>>>,
 }
 
 impl Buzzer {
-  pub fn new() -> Self {
+  pub fn new(params: &HashMap<String, String>) -> Self {
     let (tx,rx):(mpsc::Sender<AudioThreadCommand>, mpsc::Receiver<AudioThreadCommand>) = mpsc::channel::<AudioThreadCommand>();
-    return Buzzer {sound_worker: None, devfile: None, buzzer: Arc::new(Mutex::new(BuzzerThreadSafe {sound_worker_rx: Mutex::new(rx), sound_worker_tx: Mutex::new(tx), driver_fd: Mutex::new(None)}))};
+    let device = params.get("device").cloned().unwrap_or(String::from(BUZZER_DEFAULT_DEVICE));
+    return Buzzer {device: device, sound_worker: None, devfile: None, buzzer: Arc::new(Mutex::new(BuzzerThreadSafe {sound_worker_rx: Mutex::new(rx), sound_worker_tx: Mutex::new(tx), driver_fd: Mutex::new(None)}))};
   }
 }
 
@@ -138,12 +143,12 @@ impl Audio for Buzzer {
   				.read(true)
   				.write(true)
  				.create(false)
-				.open("/dev/buzzer") {
+				.open(&self.device) {
         Ok(file) => {
           buzzer_locked.set_driver_fd(Some(file.as_raw_fd()));
          Some(file)
         }, 
-        Err(err) => return Err(format!("Error opening buzzer kernel driver: {}", err))
+        Err(err) => return Err(format!("Error opening buzzer kernel driver {}: {}", self.device, err))
       };
 
       let mut version:[u8;6] = [0;6];
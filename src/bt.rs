@@ -29,6 +29,7 @@
 mod bluez;
 
 use async_trait::async_trait;
+use std::collections::HashMap;
 use std::time::Instant;
 
 #[derive(Eq, Hash, PartialEq)]
@@ -111,7 +112,7 @@ pub trait Bluetooth {
     fn signature(&self) -> String;
   }
   
-pub fn bluetooth_by_name(name: &str) -> Option<Box<dyn Bluetooth+Sync+Send>> {
+pub fn bluetooth_by_name(name: &str, _params: &HashMap<String, String>) -> Option<Box<dyn Bluetooth+Sync+Send>> {
     match name {
         "bluez" => return Some(Box::new(bluez::BlueZ::new())),
         _ => return None
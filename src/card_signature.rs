@@ -0,0 +1,274 @@
+/**
+ * @file   card_signature.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  Signed NFC card payload
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use crate::config::CardSigningConfig;
+use crate::crypto;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use std::collections::BTreeMap;
+
+/* Payload layout, written across card blocks 1 and 2:
+ *
+ * | 0..2  | 2      | 3           | 4..8                | 8..32                |
+ * | "AC"  | format | key version | credential id (BE)  | HMAC-SHA256, truncated | */
+pub const CARD_PAYLOAD_BLOCK: u8 = 1;
+pub const CARD_PAYLOAD_BLOCKS: u8 = 2;
+pub const CARD_PAYLOAD_LEN: usize = 32;
+
+const CARD_PAYLOAD_MAGIC: &[u8] = b"AC";
+const CARD_PAYLOAD_FORMAT: u8 = 1;
+const CARD_PAYLOAD_MAC_LEN: usize = 24;
+
+const CARD_LEGACY_SIGNATURE: &[u8] = b"ACONTROL_CARD\0\0\0";
+const CARD_KEY_FILE: &str = "card-key";
+const CARD_KEY_LEN: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/* What a valid card payload says about the card. */
+pub struct CardPayload {
+  pub credential_id: i32,
+  pub key_version: u8,
+  pub legacy: bool,
+}
+
+/* Site secrets used to sign and verify card payloads.
+ *
+ * New cards are always signed with the current version. Older versions
+ * keep verifying until `retire_at`, so cards can be re-signed as they
+ * are used instead of all at once. */
+pub struct CardKeys {
+  keys: BTreeMap<u8, Vec<u8>>,
+  current: u8,
+  retire_at: Option<i64>,
+  accept_legacy: bool,
+}
+
+impl CardKeys {
+  /* Builds the key set from the `[card_signing]` config section.
+   *
+   * When no key is configured a random version 1 secret is created
+   * under `data_path` on first run and reused afterwards. */
+  pub fn load(config: &CardSigningConfig, data_path: &str) -> Result<CardKeys, String> {
+    let mut keys: BTreeMap<u8, Vec<u8>> = BTreeMap::new();
+
+    for (version, secret) in config.keys.iter() {
+      let version = match version.parse::<u8>() {
+        Ok(version) if version > 0 => version,
+        _ => return Err(format!("Invalid card key version {}", version))
+      };
+
+      match hex::decode(secret.trim()) {
+        Ok(secret) if secret.len() >= 16 => { keys.insert(version, secret); },
+        _ => return Err(format!("Card key version {} must be at least 16 hex encoded bytes", version))
+      }
+    }
+
+    if keys.len() == 0 {
+      keys.insert(1, crypto::load_or_create_secret(data_path, CARD_KEY_FILE, CARD_KEY_LEN, "card key")?);
+    }
+
+    let current = match config.current_version {
+      Some(version) => version,
+      None => *keys.keys().last().unwrap()
+    };
+
+    if !keys.contains_key(&current) {
+      return Err(format!("Current card key version {} is not configured", current));
+    }
+
+    if keys.len() > 1 && config.rotated_at.is_none() {
+      return Err(String::from("rotated_at is required when more than one card key is configured"));
+    }
+
+    let retire_at = config.rotated_at.map(|rotated_at| rotated_at + (config.rotation_window_days as i64) * 86400);
+
+    Ok(CardKeys { keys: keys, current: current, retire_at: retire_at, accept_legacy: config.accept_legacy })
+  }
+
+  pub fn current_version(&self) -> u8 {
+    self.current
+  }
+
+  fn mac(&self, version: u8, uid: &[u8], header: &[u8]) -> Result<HmacSha256, String> {
+    let secret = match self.keys.get(&version) {
+      Some(secret) => secret,
+      None => return Err(format!("Unknown card key version {}", version))
+    };
+
+    let mut mac = HmacSha256::new_from_slice(secret).map_err(|err| format!("{}", err))?;
+    mac.update(header);
+    mac.update(uid);
+    Ok(mac)
+  }
+
+  /* Builds the payload binding `credential_id` to the card `uid`. */
+  pub fn sign(&self, uid: &[u8], credential_id: i32) -> Result<Vec<u8>, String> {
+    let mut payload: Vec<u8> = Vec::with_capacity(CARD_PAYLOAD_LEN);
+    payload.extend(CARD_PAYLOAD_MAGIC);
+    payload.push(CARD_PAYLOAD_FORMAT);
+    payload.push(self.current);
+    payload.extend(&credential_id.to_be_bytes());
+
+    let tag = self.mac(self.current, uid, &payload)?.finalize().into_bytes();
+    payload.extend(&tag[..CARD_PAYLOAD_MAC_LEN]);
+
+    Ok(payload)
+  }
+
+  /* Checks a payload read from the card with the given `uid`. */
+  pub fn verify(&self, uid: &[u8], payload: &[u8], now: i64) -> Result<CardPayload, String> {
+    if payload.len() >= CARD_LEGACY_SIGNATURE.len() && &payload[..CARD_LEGACY_SIGNATURE.len()] == CARD_LEGACY_SIGNATURE {
+      if self.accept_legacy {
+        return Ok(CardPayload { credential_id: 0, key_version: 0, legacy: true });
+      }
+      return Err(String::from("Legacy card signature not accepted"));
+    }
+
+    if payload.len() < CARD_PAYLOAD_LEN || &payload[..2] != CARD_PAYLOAD_MAGIC || payload[2] != CARD_PAYLOAD_FORMAT {
+      return Err(String::from("Invalid card signature"));
+    }
+
+    let key_version = payload[3];
+    if key_version != self.current {
+      match self.retire_at {
+        Some(retire_at) if now < retire_at => {},
+        _ => return Err(format!("Card key version {} retired", key_version))
+      }
+    }
+
+    let mac = self.mac(key_version, uid, &payload[..8])?;
+    if mac.verify_truncated_left(&payload[8..CARD_PAYLOAD_LEN]).is_err() {
+      return Err(String::from("Invalid card signature"));
+    }
+
+    let mut credential_id = [0u8; 4];
+    credential_id.copy_from_slice(&payload[4..8]);
+
+    Ok(CardPayload { credential_id: i32::from_be_bytes(credential_id), key_version: key_version, legacy: false })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::collections::HashMap;
+
+  const UID: [u8; 4] = [0xDE, 0xAD, 0xBE, 0xEF];
+  const ROTATED_AT: i64 = 1_760_659_200;
+
+  fn config(versions: &[u8], current: Option<u8>) -> CardSigningConfig {
+    let mut keys: HashMap<String, String> = HashMap::new();
+    for version in versions {
+      keys.insert(version.to_string(), hex::encode([*version; 32]));
+    }
+
+    CardSigningConfig { current_version: current, rotated_at: Some(ROTATED_AT), rotation_window_days: 30, accept_legacy: true, keys: keys }
+  }
+
+  fn keys(versions: &[u8], current: Option<u8>) -> CardKeys {
+    CardKeys::load(&config(versions, current), "/nonexistent").unwrap()
+  }
+
+  #[test]
+  fn sign_verify_round_trip() {
+    let keys = keys(&[1], None);
+    let payload = keys.sign(&UID, 42).unwrap();
+
+    assert_eq!(payload.len(), CARD_PAYLOAD_LEN);
+
+    let card = keys.verify(&UID, &payload, ROTATED_AT).unwrap();
+    assert_eq!(card.credential_id, 42);
+    assert_eq!(card.key_version, 1);
+    assert!(!card.legacy);
+  }
+
+  #[test]
+  fn verify_rejects_tampered_payload() {
+    let keys = keys(&[1], None);
+    let payload = keys.sign(&UID, 42).unwrap();
+
+    /* Credential id and MAC bytes. */
+    for i in [4, 7, 8, CARD_PAYLOAD_LEN - 1].iter() {
+      let mut tampered = payload.clone();
+      tampered[*i] ^= 0x01;
+      assert!(keys.verify(&UID, &tampered, ROTATED_AT).is_err());
+    }
+  }
+
+  #[test]
+  fn verify_rejects_other_uid() {
+    let keys = keys(&[1], None);
+    let payload = keys.sign(&UID, 42).unwrap();
+
+    assert!(keys.verify(&[0xDE, 0xAD, 0xBE, 0xEE], &payload, ROTATED_AT).is_err());
+  }
+
+  #[test]
+  fn previous_key_retires_after_the_window() {
+    let old = keys(&[1], None).sign(&UID, 42).unwrap();
+    let keys = keys(&[1, 2], None);
+    let retire_at = ROTATED_AT + 30 * 86400;
+
+    assert_eq!(keys.current_version(), 2);
+    assert_eq!(keys.verify(&UID, &old, retire_at - 1).unwrap().key_version, 1);
+    assert!(keys.verify(&UID, &old, retire_at).is_err());
+
+    let new = keys.sign(&UID, 42).unwrap();
+    assert_eq!(keys.verify(&UID, &new, retire_at).unwrap().key_version, 2);
+  }
+
+  #[test]
+  fn rotation_requires_rotated_at() {
+    let mut rotation = config(&[1, 2], None);
+    rotation.rotated_at = None;
+
+    assert!(CardKeys::load(&rotation, "/nonexistent").is_err());
+    assert!(CardKeys::load(&config(&[1], Some(2)), "/nonexistent").is_err());
+  }
+
+  #[test]
+  fn legacy_signature() {
+    let mut payload = CARD_LEGACY_SIGNATURE.to_vec();
+    payload.resize(CARD_PAYLOAD_LEN, 0);
+
+    assert!(keys(&[1], None).verify(&UID, &payload, ROTATED_AT).unwrap().legacy);
+
+    let mut strict = config(&[1], None);
+    strict.accept_legacy = false;
+    assert!(CardKeys::load(&strict, "/nonexistent").unwrap().verify(&UID, &payload, ROTATED_AT).is_err());
+  }
+
+  #[test]
+  fn legacy_is_rejected_by_default() {
+    assert!(!CardSigningConfig::default().accept_legacy);
+  }
+}
@@ -0,0 +1,351 @@
+/**
+ * @file   config.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  Configuration file parsing
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+
+use crate::nfc;
+
+pub const DEFAULT_CONFIG_FILE: &str = "/etc/acontrol/acontrol.toml";
+
+const DEFAULT_LOGS_PATH: &str = "/var/log/acontrol";
+const DEFAULT_DATA_PATH: &str = "/var/lib/acontrol";
+
+const DEFAULT_LOG_MODULE: &str = "file";
+const DEFAULT_LOG_LEVEL: &str = "info";
+const DEFAULT_DISPLAY_MODULE: &str = "neopixel";
+const DEFAULT_PERSIST_MODULE: &str = "sqlite";
+
+const HTTP_DEFAULT_HOST: &str = "localhost";
+const HTTP_DEFAULT_PORT: u32 = 8088;
+const MIFARE_DEFAULT_KEY: &str = "0xFF,0xFF,0xFF,0xFF,0xFF,0xFF";
+const NFC_DEFAULT_READER_NAME: &str = "main";
+const DEFAULT_ROTATION_WINDOW_DAYS: u32 = 30;
+
+/* [nfc] options that describe the cards rather than the reader hardware.
+ * Only these are inherited by the [[nfc.readers]] tables. */
+const NFC_SHARED_OPTIONS: &[&str] = &["ntag_originality", "card_hold_off_ms", "desfire_aid", "desfire_key", "desfire_key_no", "desfire_file"];
+/* Gpios a single reader owns. */
+const NFC_EXCLUSIVE_PINS: &[&str] = &["ss_pin", "irq_pin"];
+
+/* Whole daemon configuration, as read from the toml file.
+ *
+ * Every section is optional. Anything left out falls back to the
+ * same defaults the daemon used before the file existed. */
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  pub paths: PathsConfig,
+  pub log: LogConfig,
+  pub server: ServerConfig,
+  pub access: AccessConfig,
+  pub nfc: NfcConfig,
+  pub fingerprint: DriverConfig,
+  pub bluetooth: DriverConfig,
+  pub audio: DriverConfig,
+  pub display: DriverConfig,
+  pub lock: DriverConfig,
+  pub door: DriverConfig,
+  pub persist: DriverConfig,
+  pub card_signing: CardSigningConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+  pub logs: String,
+  pub data: String,
+}
+
+impl Default for PathsConfig {
+  fn default() -> Self {
+    PathsConfig { logs: String::from(DEFAULT_LOGS_PATH), data: String::from(DEFAULT_DATA_PATH) }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+  pub module: String,
+  pub level: String,
+  #[serde(flatten)]
+  pub options: HashMap<String, toml::Value>,
+}
+
+impl Default for LogConfig {
+  fn default() -> Self {
+    LogConfig { module: String::from(DEFAULT_LOG_MODULE), level: String::from(DEFAULT_LOG_LEVEL), options: HashMap::new() }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+  pub host: String,
+  pub port: u32,
+  pub https_cert: Option<String>,
+  pub https_key: Option<String>,
+}
+
+impl Default for ServerConfig {
+  fn default() -> Self {
+    ServerConfig { host: String::from(HTTP_DEFAULT_HOST), port: HTTP_DEFAULT_PORT, https_cert: None, https_key: None }
+  }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessConfig {
+  pub auth_policy: Option<String>,
+  pub mfa_timeout: Option<u64>,
+}
+
+/* Secrets used to sign the payload written to NFC cards.
+ *
+ * `keys` maps a key version to its hex encoded secret. Versions older
+ * than `current_version` keep being accepted for `rotation_window_days`
+ * after `rotated_at` (unix seconds). `accept_legacy` lets cards with the
+ * old static signature in so they can be upgraded; it is off by default
+ * because that signature is trivially cloned. */
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CardSigningConfig {
+  pub current_version: Option<u8>,
+  pub rotated_at: Option<i64>,
+  pub rotation_window_days: u32,
+  pub accept_legacy: bool,
+  pub keys: HashMap<String, String>,
+}
+
+impl Default for CardSigningConfig {
+  fn default() -> Self {
+    CardSigningConfig { current_version: None, rotated_at: None, rotation_window_days: DEFAULT_ROTATION_WINDOW_DAYS, accept_legacy: false, keys: HashMap::new() }
+  }
+}
+
+/* The `[nfc]` section describes the first reader, named `name`.
+ *
+ * Additional readers go in `[[nfc.readers]]` tables. They share the
+ * mifare keys, the module and the card options of the `[nfc]` section,
+ * but not its device, address or pins. */
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct NfcConfig {
+  pub name: String,
+  pub module: Option<String>,
+  pub mifare_key: String,
+  pub master_key: Option<String>,
+  pub readers: Vec<NfcReaderConfig>,
+  #[serde(flatten)]
+  pub options: HashMap<String, toml::Value>,
+}
+
+#[derive(Deserialize)]
+pub struct NfcReaderConfig {
+  pub name: String,
+  pub module: Option<String>,
+  #[serde(flatten)]
+  pub options: HashMap<String, toml::Value>,
+}
+
+impl Default for NfcConfig {
+  fn default() -> Self {
+    NfcConfig { name: String::from(NFC_DEFAULT_READER_NAME), module: None, mifare_key: String::from(MIFARE_DEFAULT_KEY), master_key: None, readers: Vec::new(), options: HashMap::new() }
+  }
+}
+
+impl NfcConfig {
+  pub fn params(&self) -> HashMap<String, String> {
+    options_to_params(&self.options)
+  }
+
+  /* Every configured reader as `(name, module, params)`, the `[nfc]`
+   * one first. Fails on duplicated names or pins. */
+  pub fn readers(&self) -> Result<Vec<(String, Option<String>, HashMap<String, String>)>, String> {
+    let mut readers = vec![(self.name.clone(), self.module.clone(), self.params())];
+
+    for reader in self.readers.iter() {
+      if readers.iter().any(|(name, _, _)| *name == reader.name) {
+        return Err(format!("Duplicated nfc reader name: {}", reader.name));
+      }
+
+      let mut params: HashMap<String, String> = self.params().into_iter()
+        .filter(|(key, _)| NFC_SHARED_OPTIONS.contains(&key.as_str()))
+        .collect();
+      params.extend(options_to_params(&reader.options));
+
+      let module = reader.module.clone().or(self.module.clone());
+
+      for pin in NFC_EXCLUSIVE_PINS.iter() {
+        if let Some(value) = effective_pin(&module, &params, pin) {
+          if let Some((name, _, _)) = readers.iter().find(|(_, other_module, other)| effective_pin(other_module, other, pin) == Some(value.clone())) {
+            return Err(format!("nfc readers {} and {} share {} {}", name, reader.name, pin, value));
+          }
+        }
+      }
+
+      readers.push((reader.name.clone(), module, params));
+    }
+
+    Ok(readers)
+  }
+}
+
+/* A driver section: which module to load and the module's own options.
+ *
+ * Options are handed untyped to the driver through the same
+ * `params` map the log driver already used; each driver parses the
+ * keys it knows about and falls back to its defaults. */
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct DriverConfig {
+  pub module: Option<String>,
+  #[serde(flatten)]
+  pub options: HashMap<String, toml::Value>,
+}
+
+impl DriverConfig {
+  pub fn params(&self) -> HashMap<String, String> {
+    options_to_params(&self.options)
+  }
+}
+
+/* The pin a reader ends up using: the configured one, or the driver
+ * default for the slave select of SPI readers. */
+fn effective_pin(module: &Option<String>, params: &HashMap<String, String>, pin: &str) -> Option<String> {
+  match params.get(pin) {
+    Some(value) => Some(value.clone()),
+    None if pin == "ss_pin" => module.as_ref().and_then(|module| nfc::default_ss_pin(module)).map(|pin| pin.to_string()),
+    None => None
+  }
+}
+
+fn options_to_params(options: &HashMap<String, toml::Value>) -> HashMap<String, String> {
+  options.iter().map(|(key, value)| {
+    let value = match value {
+      toml::Value::String(value) => value.clone(),
+      value => value.to_string()
+    };
+    (key.clone(), value)
+  }).collect()
+}
+
+impl Config {
+  /* Loads the configuration from `path`.
+   *
+   * A missing file is only an error when it was asked for explicitly;
+   * otherwise the built-in defaults are used. */
+  pub fn load(path: &str, required: bool) -> Result<Config, String> {
+    let content = match fs::read_to_string(path) {
+      Ok(content) => content,
+      Err(ref err) if err.kind() == ErrorKind::NotFound && !required => String::new(),
+      Err(err) => return Err(format!("Error reading config file {}: {}", path, err))
+    };
+
+    let mut config: Config = match toml::from_str(&content) {
+      Ok(config) => config,
+      Err(err) => return Err(format!("Invalid config file {}: {}", path, err))
+    };
+
+    if config.display.module.is_none() {
+      config.display.module = Some(String::from(DEFAULT_DISPLAY_MODULE));
+    }
+
+    if config.persist.module.is_none() {
+      config.persist.module = Some(String::from(DEFAULT_PERSIST_MODULE));
+    }
+
+    Ok(config)
+  }
+
+  /* System wide parameters shared by the core and the persistence driver. */
+  pub fn system_params(&self) -> HashMap<String, String> {
+    let mut params: HashMap<String, String> = HashMap::new();
+    params.insert("LOGS_PATH".to_string(), self.paths.logs.clone());
+    params.insert("DATA_PATH".to_string(), self.paths.data.clone());
+
+    if let Some(ref policy) = self.access.auth_policy {
+      params.insert("AUTH_POLICY".to_string(), policy.clone());
+    }
+
+    if let Some(timeout) = self.access.mfa_timeout {
+      params.insert("MFA_TIMEOUT".to_string(), timeout.to_string());
+    }
+
+    params
+  }
+
+  /* Parameters for the log driver: the system wide ones plus its own section. */
+  pub fn log_params(&self) -> HashMap<String, String> {
+    let mut params = self.system_params();
+    params.extend(options_to_params(&self.log.options));
+    params
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn nfc(readers: &str) -> NfcConfig {
+    let content = format!("[nfc]\nmodule = \"pn532_spi\"\ndevice = \"/dev/spidev0.0\"\nss_pin = 17\nirq_pin = 25\ncard_hold_off_ms = 1000\n{}", readers);
+    toml::from_str::<Config>(&content).unwrap().nfc
+  }
+
+  #[test]
+  fn readers_inherit_card_options_only() {
+    let readers = nfc("[[nfc.readers]]\nname = \"inside\"\nss_pin = 24\n").readers().unwrap();
+
+    assert_eq!(readers.len(), 2);
+    let (ref name, ref module, ref params) = readers[1];
+    assert_eq!(name, "inside");
+    assert_eq!(module.as_ref().map(|module| module.as_str()), Some("pn532_spi"));
+    assert_eq!(params.get("card_hold_off_ms").map(|value| value.as_str()), Some("1000"));
+    assert_eq!(params.get("ss_pin").map(|value| value.as_str()), Some("24"));
+    assert!(params.get("device").is_none());
+    assert!(params.get("irq_pin").is_none());
+  }
+
+  #[test]
+  fn readers_can_not_share_pins() {
+    assert!(nfc("[[nfc.readers]]\nname = \"inside\"\nss_pin = 17\n").readers().is_err());
+    assert!(nfc("[[nfc.readers]]\nname = \"inside\"\nss_pin = 24\nirq_pin = 25\n").readers().is_err());
+    assert!(nfc("[[nfc.readers]]\nname = \"main\"\nss_pin = 24\n").readers().is_err());
+  }
+
+  #[test]
+  fn readers_can_not_share_default_ss_pin() {
+    assert!(nfc("[[nfc.readers]]\nname = \"inside\"\n").readers().is_err());
+    assert!(nfc("[[nfc.readers]]\nname = \"inside\"\nmodule = \"mfrc522\"\n").readers().is_err());
+    assert!(nfc("[[nfc.readers]]\nname = \"inside\"\nmodule = \"pn532_i2c\"\n").readers().is_ok());
+
+    let content = "[nfc]\nmodule = \"mfrc522\"\n[[nfc.readers]]\nname = \"inside\"\nmodule = \"pn532_spi\"\n";
+    assert!(toml::from_str::<Config>(content).unwrap().nfc.readers().is_err());
+  }
+}
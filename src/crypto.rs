@@ -0,0 +1,92 @@
+/**
+ * @file   crypto.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  Shared key file and block cipher helpers
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use aes::cipher::{Block, BlockDecrypt, BlockEncrypt};
+use rand::RngCore;
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+/* Reads the hex encoded secret kept in `file_name` under `data_path`. On
+ * first run a random `len` bytes secret is created there, readable by the
+ * owner only. `what` names the secret in error messages. */
+pub fn load_or_create_secret(data_path: &str, file_name: &str, len: usize, what: &str) -> Result<Vec<u8>, String> {
+  let path = Path::new(data_path).join(file_name);
+
+  match fs::read_to_string(&path) {
+    Ok(content) => return hex::decode(content.trim()).map_err(|err| format!("Invalid {} in {}: {}", what, path.display(), err)),
+    Err(ref err) if err.kind() == ErrorKind::NotFound => {},
+    Err(err) => return Err(format!("Error reading {}: {}", path.display(), err))
+  }
+
+  let mut secret = vec![0u8; len];
+  rand::thread_rng().fill_bytes(&mut secret);
+
+  match OpenOptions::new().create_new(true).write(true).mode(0o600).open(&path) {
+    Ok(mut file) => {
+      if let Err(err) = file.write_all(format!("{}\n", hex::encode(&secret)).as_bytes()) {
+        return Err(format!("Error writing {}: {}", path.display(), err));
+      }
+    },
+    Err(err) => return Err(format!("Error creating {}: {}", path.display(), err))
+  }
+
+  Ok(secret)
+}
+
+/* CBC over whole blocks, no padding. `iv` is left holding the last
+ * ciphertext block so callers can keep chaining. */
+pub fn cbc_encrypt<C: BlockEncrypt>(cipher: &C, iv: &mut [u8], data: &[u8]) -> Vec<u8> {
+  let mut out: Vec<u8> = Vec::with_capacity(data.len());
+  for chunk in data.chunks(iv.len()) {
+    let mut block = Block::<C>::clone_from_slice(chunk);
+    for (byte, prev) in block.iter_mut().zip(iv.iter()) {
+      *byte ^= *prev;
+    }
+    cipher.encrypt_block(&mut block);
+    iv.copy_from_slice(&block);
+    out.extend_from_slice(&block);
+  }
+  out
+}
+
+pub fn cbc_decrypt<C: BlockDecrypt>(cipher: &C, iv: &mut [u8], data: &[u8]) -> Vec<u8> {
+  let mut out: Vec<u8> = Vec::with_capacity(data.len());
+  for chunk in data.chunks(iv.len()) {
+    let mut block = Block::<C>::clone_from_slice(chunk);
+    cipher.decrypt_block(&mut block);
+    for (byte, prev) in block.iter_mut().zip(iv.iter()) {
+      *byte ^= *prev;
+    }
+    iv.copy_from_slice(chunk);
+    out.extend_from_slice(&block);
+  }
+  out
+}
@@ -27,6 +27,8 @@
  */
 mod neopixel;
 
+use std::collections::HashMap;
+
 //#[derive(Clone, Copy)]
 //#[allow(dead_code)]
 //pub enum DisplayState {
@@ -88,9 +90,9 @@ pub trait Display : Sync + Send {
   fn signature(&self) -> String;
 }
 
-pub fn display_by_name(name: &str) -> Option<Box<dyn Display+Sync+Send>> {
+pub fn display_by_name(name: &str, params: &HashMap<String, String>) -> Option<Box<dyn Display+Sync+Send>> {
   match name {
-    "neopixel" => return Some(Box::new(neopixel::NeoPixel::new())),
+    "neopixel" => return Some(Box::new(neopixel::NeoPixel::new(params))),
     _ => return None
   }
 }
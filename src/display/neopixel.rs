@@ -29,6 +29,7 @@ use crate::display::{Display, Animation, AnimationType, AnimationColor};
 use crate::acontrol_system_log;
 use crate::log::LogType;
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -89,7 +90,10 @@ pub struct NeoPixelInterface {
   num_leds: Mutex<Option<i32>>,
 }
 
+const NEOPIXEL_DEFAULT_DEVICE: &str = "/dev/neopixel";
+
 pub struct NeoPixel {
+  device: String,
   devfile: Option<std::fs::File>,
   interface: Arc<NeoPixelInterface>,
 }
@@ -235,10 +239,11 @@ impl NeoPixelInterface {
 
 impl NeoPixel {
 
-  pub fn new() -> Self {
+  pub fn new(params: &HashMap<String, String>) -> Self {
     let (tx,rx):(mpsc::Sender<NeoPixelThreadCommand>, mpsc::Receiver<NeoPixelThreadCommand>) = mpsc::channel::<NeoPixelThreadCommand>();
     let (tx1,rx1):(mpsc::Sender<NeoPixelThreadState>, mpsc::Receiver<NeoPixelThreadState>) = mpsc::channel::<NeoPixelThreadState>();
-    return NeoPixel { devfile:  None, interface: Arc::new( NeoPixelInterface { animation: Mutex::new(None), driver_fd: Mutex::new(None), animation_tx: Mutex::new(tx), animation_rx: Mutex::new(rx), animation_ends_tx: Mutex::new(tx1), animation_ends_rx: Mutex::new(rx1), num_leds: Mutex::new(None) } ) };
+    let device = params.get("device").cloned().unwrap_or(String::from(NEOPIXEL_DEFAULT_DEVICE));
+    return NeoPixel { device: device, devfile:  None, interface: Arc::new( NeoPixelInterface { animation: Mutex::new(None), driver_fd: Mutex::new(None), animation_tx: Mutex::new(tx), animation_rx: Mutex::new(rx), animation_ends_tx: Mutex::new(tx1), animation_ends_rx: Mutex::new(rx1), num_leds: Mutex::new(None) } ) };
   }
 
   fn stop_animation(&mut self) -> Result<(), String> {
@@ -481,12 +486,12 @@ impl Display for NeoPixel {
                           .read(true)
                           .write(true)
                           .create(false)
-                          .open("/dev/neopixel") {
+                          .open(&self.device) {
       Ok(file) => {
         self.interface.set_driver_fd(Some(file.as_raw_fd()));
         Some(file)
       },
-      Err(err) => return Err(format!("Error opening neopixel kernel driver: {}", err))
+      Err(err) => return Err(format!("Error opening neopixel kernel driver {}: {}", self.device, err))
     };
 
     let mut version:[u8;6] = [0;6];
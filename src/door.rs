@@ -0,0 +1,63 @@
+/**
+ * @file   door.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  Door sensors global interface
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+mod gpio;
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(dead_code)]
+pub enum DoorEvent {
+  Opened,
+  Closed,
+  ExitRequest
+}
+
+impl DoorEvent {
+  pub fn name(&self) -> &'static str {
+    match *self {
+      DoorEvent::Opened => "Opened",
+      DoorEvent::Closed => "Closed",
+      DoorEvent::ExitRequest => "ExitRequest",
+    }
+  }
+}
+
+pub trait Door {
+  fn init(&mut self) -> Result<(), String>;
+  fn watch(&mut self, func: fn(DoorEvent) -> bool) -> Result<(), String>;
+  fn is_open(&mut self) -> Result<bool, String>;
+  fn unload(&mut self) -> Result<(), String>;
+  fn signature(&self) -> String;
+}
+
+pub fn door_by_name(name: &str, params: &HashMap<String, String>) -> Option<Box<dyn Door+Sync+Send>> {
+    match name {
+      "gpio" => return Some(Box::new(gpio::GpioDoor::new(params))),
+      _ => return None
+    }
+}
@@ -0,0 +1,215 @@
+/**
+ * @file   door/gpio.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  Door contact and request-to-exit button over sysfs gpio
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+use crate::door::{Door, DoorEvent};
+use crate::acontrol_system_log;
+use crate::log::LogType;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use std::thread;
+use std::time::Duration;
+
+use sysfs_gpio::{Direction, Pin};
+
+const DOOR_CONTACT_DEFAULT_PIN: u64 = 22;
+const DOOR_REX_DEFAULT_PIN: u64 = 23;
+
+//reed switch pulled up: the contact opens together with the door
+const DOOR_CONTACT_OPEN_VALUE: u8 = 1;
+//push button pulled up: pressing it shorts the pin to ground
+const DOOR_REX_PRESSED_VALUE: u8 = 0;
+
+//consecutive equal readings needed before accepting a new input value
+const DOOR_DEBOUNCE_READINGS: u32 = 3;
+
+struct GpioDoorThreadSafe {
+  contact: Option<Pin>,
+  rex: Option<Pin>,
+}
+
+impl GpioDoorThreadSafe {
+  fn contact_open(&self) -> Result<bool, String> {
+    if let Some(pin) = self.contact {
+      match pin.get_value() {
+        Ok(value) => return Ok(value == DOOR_CONTACT_OPEN_VALUE),
+        Err(err) => return Err(format!("Error reading door contact: {}", err))
+      }
+    }
+    Err(format!("{}", "Door contact pin not configured"))
+  }
+
+  fn rex_pressed(&self) -> Result<bool, String> {
+    if let Some(pin) = self.rex {
+      match pin.get_value() {
+        Ok(value) => return Ok(value == DOOR_REX_PRESSED_VALUE),
+        Err(err) => return Err(format!("Error reading exit button: {}", err))
+      }
+    }
+    Err(format!("{}", "Exit button pin not configured"))
+  }
+}
+
+unsafe impl Send for GpioDoorThreadSafe {}
+unsafe impl Sync for GpioDoorThreadSafe {}
+
+pub struct GpioDoor {
+  door: Arc<Mutex<GpioDoorThreadSafe>>,
+  contact_pin: u64,
+  rex_pin: u64,
+}
+
+impl GpioDoor {
+  pub fn new(params: &HashMap<String, String>) -> Self {
+    let contact_pin = params.get("contact_pin").and_then(|pin| pin.parse::<u64>().ok()).unwrap_or(DOOR_CONTACT_DEFAULT_PIN);
+    let rex_pin = params.get("rex_pin").and_then(|pin| pin.parse::<u64>().ok()).unwrap_or(DOOR_REX_DEFAULT_PIN);
+
+    return GpioDoor { contact_pin: contact_pin, rex_pin: rex_pin,
+      door: Arc::new(Mutex::new(GpioDoorThreadSafe { contact: None, rex: None }))
+    };
+  }
+
+  fn input_pin(pin_num: u64) -> Result<Pin, String> {
+    let pin = Pin::new(pin_num);
+    if let Err(err) = pin.export() {
+      return Err(format!("Error initializing gpio port {}: {}", pin_num, err));
+    }
+
+    //for non root users, exporting a pin could have a delay to show up at sysfs
+    thread::sleep(Duration::from_millis(100));
+
+    if let Err(err) = pin.set_direction(Direction::In) {
+      return Err(format!("Error configuring gpio port {}: {}", pin_num, err));
+    }
+    Ok(pin)
+  }
+}
+
+impl Door for GpioDoor {
+  fn init(&mut self) -> Result<(), String> {
+    let door = self.door.clone();
+    let mut door_locked = door.lock().unwrap();
+
+    door_locked.contact = Some(GpioDoor::input_pin(self.contact_pin)?);
+    door_locked.rex = Some(GpioDoor::input_pin(self.rex_pin)?);
+
+    acontrol_system_log!(LogType::Info, "Door sensors initialized (contact gpio {}, exit button gpio {})", self.contact_pin, self.rex_pin);
+
+    Ok(())
+  }
+
+  fn watch(&mut self, func: fn(DoorEvent) -> bool) -> Result<(), String> {
+    let door = self.door.clone();
+
+    let mut door_open = door.lock().unwrap().contact_open()?;
+    let mut rex_pressed = false;
+
+    let _handler = thread::spawn(move || {
+      let mut contact_counter = 0;
+      let mut rex_counter = 0;
+
+      loop {
+        let mut events: Vec<DoorEvent> = Vec::new();
+
+        if let Ok(ref door_locked) = door.lock() {
+          match door_locked.contact_open() {
+            Ok(value) => {
+              if value != door_open {
+                contact_counter += 1;
+                if contact_counter >= DOOR_DEBOUNCE_READINGS {
+                  contact_counter = 0;
+                  door_open = value;
+                  events.push(if door_open { DoorEvent::Opened } else { DoorEvent::Closed });
+                }
+              } else {
+                contact_counter = 0;
+              }
+            },
+            Err(err) => acontrol_system_log!(LogType::Error, "{}", err)
+          }
+
+          match door_locked.rex_pressed() {
+            Ok(value) => {
+              if value != rex_pressed {
+                rex_counter += 1;
+                if rex_counter >= DOOR_DEBOUNCE_READINGS {
+                  rex_counter = 0;
+                  rex_pressed = value;
+                  //only the press edge matters, holding the button does not repeat
+                  if rex_pressed {
+                    events.push(DoorEvent::ExitRequest);
+                  }
+                }
+              } else {
+                rex_counter = 0;
+              }
+            },
+            Err(err) => acontrol_system_log!(LogType::Error, "{}", err)
+          }
+        }
+
+        for event in events {
+          func(event);
+        }
+
+        thread::sleep(Duration::from_millis(50));
+      }
+    });
+
+    Ok(())
+  }
+
+  fn is_open(&mut self) -> Result<bool, String> {
+    let door = self.door.clone();
+    let door_locked = door.lock().unwrap();
+    door_locked.contact_open()
+  }
+
+  fn unload(&mut self) -> Result<(), String> {
+    acontrol_system_log!(LogType::Info, "Door driver unloading");
+    let door = self.door.clone();
+    let door_locked = door.lock().unwrap();
+
+    for pin in [door_locked.contact, door_locked.rex].iter() {
+      if let Some(pin) = pin {
+        if let Err(err) = pin.unexport() {
+          return Err(format!("{}(=>{})", "Door driver error",err));
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn signature(&self) -> String {
+    return String::from("GPIO Door Sensors Module");
+  }
+}
+
+unsafe impl Send for GpioDoor {}
+unsafe impl Sync for GpioDoor {}
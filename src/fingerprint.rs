@@ -27,6 +27,8 @@
  */
 mod gt521fx;
 
+use std::collections::HashMap;
+
 #[derive(Clone, Copy, PartialEq)]
 #[allow(dead_code)]
 #[allow(non_camel_case_types)]
@@ -37,6 +39,9 @@ pub enum FingerprintState {
   NOT_AUTHORIZED,
   AUTHORIZED,
   ENROLL,
+  ENROLL_PLACE,
+  ENROLL_LIFT,
+  ENROLL_TIMEOUT,
   ERROR,
   SUCCESS,
 }
@@ -50,6 +55,9 @@ impl FingerprintState {
       FingerprintState::NOT_AUTHORIZED => "NOT_AUTHORIZED",
       FingerprintState::AUTHORIZED => "AUTHORIZED",
       FingerprintState::ENROLL => "ENROLL",
+      FingerprintState::ENROLL_PLACE => "ENROLL_PLACE",
+      FingerprintState::ENROLL_LIFT => "ENROLL_LIFT",
+      FingerprintState::ENROLL_TIMEOUT => "ENROLL_TIMEOUT",
       FingerprintState::ERROR => "ERROR",
       FingerprintState::SUCCESS => "SUCCESS"
     }
@@ -60,33 +68,51 @@ impl FingerprintState {
   }
 }
 
+/* Without a pos the driver picks the first free slot. An enrolled pos is
+ * refused, it must be deleted first. */
 pub struct FingerprintData {
   pub pos: Option<u16>,
-  pub name: Option<String>
+  pub name: Option<String>,
+  pub user_id: Option<i32>
 }
 
 impl FingerprintData {
   pub fn new(pos: u16, name: &str) -> Self {
-    FingerprintData { pos: Some(pos), name: Some(String::from(name)) }
+    FingerprintData { pos: Some(pos), name: Some(String::from(name)), user_id: None }
   }
 
   pub fn empty() -> Self {
-    FingerprintData { pos: None, name: None }
+    FingerprintData { pos: None, name: None, user_id: None }
   }
 }
 
+pub struct FingerprintDeviceInfo {
+  pub firmware_version: u32,
+  pub iso_area_max_size: u32,
+  pub serial_number: Vec<u8>,
+  pub security_level: u8,
+  pub enrolled: u32,
+  pub slots: u16
+}
+
 pub trait Fingerprint {
   fn init(&mut self) -> Result<(), String>;
   fn wait_for_finger(&mut self, func: fn(state: &FingerprintState, value: Option<&str>) -> bool) -> Result<(),String>;
   fn unload(&mut self) -> Result<(), String>;
   fn signature(&self) -> String;
   fn delete_all(&mut self) -> bool;
-  fn start_enroll(&mut self, data: &FingerprintData) -> bool;
+  fn delete(&mut self, pos: u16) -> Result<(), String>;
+  fn is_enrolled(&mut self, pos: u16) -> Result<bool, String>;
+  fn export_template(&mut self, pos: u16) -> Result<Vec<u8>, String>;
+  fn import_template(&mut self, pos: u16, template: &[u8]) -> Result<(), String>;
+  fn start_enroll(&mut self, data: &FingerprintData) -> Result<u16, String>;
+  fn cancel_enroll(&mut self) -> Result<(), String>;
+  fn device_info(&mut self) -> Result<FingerprintDeviceInfo, String>;
 }
 
-pub fn fingerprint_by_name(name: &str) -> Option<Box<dyn Fingerprint+Sync+Send>> {
+pub fn fingerprint_by_name(name: &str, params: &HashMap<String, String>) -> Option<Box<dyn Fingerprint+Sync+Send>> {
     match name {
-      "gt521fx" => return Some(Box::new(gt521fx::Gt521fx::new())),
+      "gt521fx" => return Some(Box::new(gt521fx::Gt521fx::new(params))),
       _ => return None
     }
 }
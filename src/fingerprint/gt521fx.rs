@@ -28,10 +28,11 @@ use crate::log::LogType;
  * THE SOFTWARE.
  *
  */
-use super::{Fingerprint,FingerprintState, FingerprintData};
+use super::{Fingerprint,FingerprintState, FingerprintData, FingerprintDeviceInfo};
 
 use std::time::{Duration,Instant};
 use std::thread;
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -76,6 +77,14 @@ impl FingerprintDriverState {
     return (*self) as u16;
   }
 
+  fn is_enrolling(&self) -> bool {
+    match *self {
+      FingerprintDriverState::ENROLL1 | FingerprintDriverState::ENROLL2 | FingerprintDriverState::ENROLL3 |
+      FingerprintDriverState::ENROLL1_WAIT | FingerprintDriverState::ENROLL2_WAIT => true,
+      _ => false
+    }
+  }
+
   fn set(&mut self, next: FingerprintDriverState) -> bool {
     *self = next;
     acontrol_system_log!(LogType::Debug, "FingerprintDriverState changed to {}",(*self).name());
@@ -267,11 +276,12 @@ impl Parser for Response {
 
 /* Data Packet Parsers */
 
+const TEMPLATE_SIZE: usize = 498;
+
 struct FingerprintTemplatePacket {
   template: Option<Vec<u8>>
 }
 
-#[allow(dead_code)]
 impl FingerprintTemplatePacket {
   fn new() -> Self {
     FingerprintTemplatePacket {template: None }
@@ -280,7 +290,7 @@ impl FingerprintTemplatePacket {
 
 impl Parser for FingerprintTemplatePacket {
   fn size(&self) -> u32 {
-    return 498+6;
+    return (TEMPLATE_SIZE as u32)+6;
   }
 
   fn parser(&mut self, data: &mut Vec<u8>) -> Result<bool,std::io::Error> {
@@ -291,15 +301,15 @@ impl Parser for FingerprintTemplatePacket {
       return Err(std::io::Error::new(ErrorKind::InvalidData, "Invalid response signature"));
     }
 
-    let calc_checksum = Gt521fxThreadSafe::calc_crc(&response_data[0..502]);
-    let mut checksum: u16 = (response_data[503] as u16) << 8;
-    checksum |= response_data[502] as u16;
+    let calc_checksum = Gt521fxThreadSafe::calc_crc(&response_data[0..TEMPLATE_SIZE+4]);
+    let mut checksum: u16 = (response_data[TEMPLATE_SIZE+5] as u16) << 8;
+    checksum |= response_data[TEMPLATE_SIZE+4] as u16;
 
     if checksum != calc_checksum {
       return Err(std::io::Error::new(ErrorKind::InvalidData, format!("Invalid checksum 0x{:X} - 0x{:X}", checksum, calc_checksum)));
     }
 
-    self.template = Some(response_data[4..498].to_vec());
+    self.template = Some(response_data[4..TEMPLATE_SIZE+4].to_vec());
 
     Ok(true)
   }
@@ -348,7 +358,7 @@ impl Parser for OpenDataPacket {
     self.iso_area_max_size |= (response_data[9] as u32) << 8;
     self.iso_area_max_size |= response_data[8] as u32;
 
-    for i in 0..15{
+    for i in 0..16{
       self.device_serial_num[i] = response_data[i+12];
     }
 
@@ -423,12 +433,37 @@ impl Gt521fxThreadSafe {
     data.push( (crc & 0xFF) as u8 );
     data.push( ((crc >> 8) & 0xFF) as u8 );
 
+    self.transfer(&data, parser)
+  }
+
+  /* Data packets follow a command that was acked, like SetTemplate. */
+  fn send_data(&mut self, payload: &[u8]) -> Result<Response, std::io::Error> {
+    let mut data: Vec<u8>= Vec::new();
+
+    data.push(0x5A);
+    data.push(0xA5);
+
+    //Fixed device id = 0x0001
+    data.push(0x01);
+    data.push(0x00);
+
+    data.extend_from_slice(payload);
+
+    let crc:u16 = Gt521fxThreadSafe::calc_crc(&data[..]);
+
+    data.push( (crc & 0xFF) as u8 );
+    data.push( ((crc >> 8) & 0xFF) as u8 );
+
+    self.transfer(&data, None)
+  }
+
+  fn transfer(&mut self, data: &[u8], parser: Option<&mut dyn Parser>) -> Result<Response, std::io::Error> {
     let mut response = Response::new();
 
     if let Some(ref mut port) = self.port {
       let _ret = (*port).clear(ClearBuffer::All);
 
-      if let Err(err) = (*port).write(&data[..]) {
+      if let Err(err) = (*port).write(data) {
         return Err(std::io::Error::new(ErrorKind::InvalidData, format!("Error sending data: {}", err)));
       }
       let now = Instant::now();
@@ -480,20 +515,73 @@ impl Gt521fxThreadSafe {
 
     Ok(response)
   }
+
+  fn is_enrolled(&mut self, pos: u16) -> Result<bool, String> {
+    match self.send_command(Command::CheckEnrolled, u32::from(pos), None) {
+      Ok(ref response) if response.response == Command::Ack.value() => Ok(true),
+      Ok(ref response) if response.parameter == Error::NackIsNotUsed.value() as u32 => Ok(false),
+      Ok(response) => Err(format!("Error checking position {}: {}", pos, (Error::from(response.parameter)).name())),
+      Err(err) => Err(format!("Error checking position {}: {}", pos, err))
+    }
+  }
+
+  /* With n fingers enrolled one of the first n+1 slots is free, so the scan
+   * stops early even on the 3000 slot modules. */
+  fn free_slot(&mut self, slots: u16) -> Result<u16, String> {
+    let count = match self.send_command(Command::GetEnrollCount, 0, None) {
+      Ok(ref response) if response.response == Command::Ack.value() => response.parameter,
+      Ok(response) => return Err(format!("GetEnrollCount error: {}", (Error::from(response.parameter)).name())),
+      Err(err) => return Err(format!("GetEnrollCount error: {}", err))
+    };
+
+    if count >= u32::from(slots) {
+      return Err(String::from("Fingerprint database is full"));
+    }
+
+    for pos in 0..slots {
+      if !self.is_enrolled(pos)? {
+        return Ok(pos);
+      }
+    }
+
+    Err(String::from("Fingerprint database is full"))
+  }
 }
 
 unsafe impl Send for Gt521fxThreadSafe {}
 unsafe impl Sync for Gt521fxThreadSafe {}
 
+const GT521FX_DEFAULT_DEVICE: &str = "/dev/serial0";
+const GT521FX_DEFAULT_TOUCH_PIN: u64 = 16;
+//GT-521F52. The GT-521F32 only has 200 slots
+const GT521FX_DEFAULT_SLOTS: u16 = 3000;
+//Seconds to wait on each enrolment step before giving up
+const GT521FX_DEFAULT_ENROLL_TIMEOUT: u64 = 30;
+//1 accepts the most fingers (highest FAR), 5 the fewest. The sensor defaults to 3
+const GT521FX_MIN_SECURITY_LEVEL: u8 = 1;
+const GT521FX_MAX_SECURITY_LEVEL: u8 = 5;
+
 pub struct Gt521fx {
+  device: String,
+  touch_pin: u64,
+  slots: u16,
+  security_level: Option<String>,
+  enroll_timeout: Duration,
+  open_data: Option<OpenDataPacket>,
   gt521fx: Arc<Mutex<Gt521fxThreadSafe>>,
   state: Arc<Mutex<FingerprintDriverState>>,
   expires: Arc<Mutex<Option<Instant>>>,
 }
 
 impl Gt521fx {
-  pub fn new() -> Self {
-    return Gt521fx { expires: Arc::new(Mutex::new(None)), state: Arc::new(Mutex::new(FingerprintDriverState::IDLE)), gt521fx: Arc::new(Mutex::new(Gt521fxThreadSafe { port: None, pin: None } ))};
+  pub fn new(params: &HashMap<String, String>) -> Self {
+    let device = params.get("device").cloned().unwrap_or(String::from(GT521FX_DEFAULT_DEVICE));
+    let touch_pin = params.get("touch_pin").and_then(|pin| pin.parse::<u64>().ok()).unwrap_or(GT521FX_DEFAULT_TOUCH_PIN);
+    let slots = params.get("slots").and_then(|slots| slots.parse::<u16>().ok()).unwrap_or(GT521FX_DEFAULT_SLOTS);
+    let security_level = params.get("security_level").cloned();
+    let enroll_timeout = params.get("enroll_timeout").and_then(|timeout| timeout.parse::<u64>().ok()).unwrap_or(GT521FX_DEFAULT_ENROLL_TIMEOUT);
+
+    return Gt521fx { device: device, touch_pin: touch_pin, slots: slots, security_level: security_level, enroll_timeout: Duration::from_secs(enroll_timeout), open_data: None, expires: Arc::new(Mutex::new(None)), state: Arc::new(Mutex::new(FingerprintDriverState::IDLE)), gt521fx: Arc::new(Mutex::new(Gt521fxThreadSafe { port: None, pin: None } ))};
   }
 }
 
@@ -505,11 +593,11 @@ impl Fingerprint for Gt521fx {
 
     let mut open_data = OpenDataPacket::new();
 
-    if let Err(_err) = gt521fx_locked.open("/dev/serial0") {
-      return Err(format!("{}","Error openning serial port."));
+    if let Err(_err) = gt521fx_locked.open(&self.device) {
+      return Err(format!("Error openning serial port {}.", self.device));
     }
 
-    if let Err(_err) = gt521fx_locked.pin_config(16) {
+    if let Err(_err) = gt521fx_locked.pin_config(self.touch_pin) {
       return Err(format!("{}","Error configuring fingerprint touch sensor pin."));
     }
 
@@ -524,6 +612,23 @@ impl Fingerprint for Gt521fx {
       }
     }
 
+    if let Some(ref level) = self.security_level {
+      let level = match level.parse::<u8>() {
+        Ok(level) if level >= GT521FX_MIN_SECURITY_LEVEL && level <= GT521FX_MAX_SECURITY_LEVEL => level,
+        _ => return Err(format!("Invalid fingerprint security level {}, expected {} to {}", level, GT521FX_MIN_SECURITY_LEVEL, GT521FX_MAX_SECURITY_LEVEL))
+      };
+
+      match gt521fx_locked.send_command(Command::SetSecurityLevel, u32::from(level), None) {
+        Ok(ref response) if response.response == Command::Ack.value() => {
+          acontrol_system_log!(LogType::Info, "Fingerprint security level set to {}", level);
+        },
+        Ok(response) => return Err(format!("Error setting fingerprint security level: {}", (Error::from(response.parameter)).name())),
+        Err(err) => return Err(format!("Error setting fingerprint security level: {}", err))
+      }
+    }
+
+    self.open_data = Some(open_data);
+
     //if let Err(err) = gt521fx_locked.send_command(Command::CmosLed, 0x1, None) {
     //  println!("Error initializing fingerprint device: {}",err);
     //} else {
@@ -537,6 +642,8 @@ impl Fingerprint for Gt521fx {
     let gt521fx = self.gt521fx.clone();
     let state = self.state.clone();
     let expires = self.expires.clone();
+    let enroll_timeout = self.enroll_timeout.as_secs_f64();
+    let slots = self.slots;
 
     let _handler = thread::spawn( move || {
       let mut fingerpress_counter = 0;
@@ -545,16 +652,20 @@ impl Fingerprint for Gt521fx {
           if let Ok(ref mut state_locked) = state.lock() {
             if let Ok(ref mut expires_locked) = expires.lock() {
 
+              /* Enrolment steps carry the step number, 1 to 3, as value. */
               let fingerprint_state = match **state_locked {
-                FingerprintDriverState::IDLE => Some(FingerprintState::IDLE),
-                FingerprintDriverState::READ => Some(FingerprintState::READING),
-                FingerprintDriverState::ENROLL1 | FingerprintDriverState::ENROLL2 | FingerprintDriverState::ENROLL3 => Some(FingerprintState::WAITING),
-                FingerprintDriverState::ENROLL1_WAIT | FingerprintDriverState::ENROLL2_WAIT => Some(FingerprintState::SUCCESS),
-                FingerprintDriverState::ENROLL_ERROR => Some(FingerprintState::ERROR),
+                FingerprintDriverState::IDLE => Some((FingerprintState::IDLE, None)),
+                FingerprintDriverState::READ => Some((FingerprintState::READING, None)),
+                FingerprintDriverState::ENROLL1 => Some((FingerprintState::ENROLL_PLACE, Some("1"))),
+                FingerprintDriverState::ENROLL2 => Some((FingerprintState::ENROLL_PLACE, Some("2"))),
+                FingerprintDriverState::ENROLL3 => Some((FingerprintState::ENROLL_PLACE, Some("3"))),
+                FingerprintDriverState::ENROLL1_WAIT => Some((FingerprintState::ENROLL_LIFT, Some("1"))),
+                FingerprintDriverState::ENROLL2_WAIT => Some((FingerprintState::ENROLL_LIFT, Some("2"))),
+                FingerprintDriverState::ENROLL_ERROR => Some((FingerprintState::ERROR, None)),
               };
 
-              if let Some(ref state) = fingerprint_state {
-                func(state, None);
+              if let Some((ref state, value)) = fingerprint_state {
+                func(state, value);
               }
 
               let mut sec = 0.0;
@@ -564,7 +675,17 @@ impl Fingerprint for Gt521fx {
 
               //println!("Current State Time: {}", sec);
 
-              if sec > 120.0 {
+              if (**state_locked).is_enrolling() && sec > enroll_timeout {
+                acontrol_system_log!(LogType::Warning, "Fingerprint enrolment timed out at {}", (**state_locked).name());
+                if let Ok(ref mut gt521fx_locked) = gt521fx.lock() {
+                  if let Err(err) = gt521fx_locked.send_command(Command::CmosLed, 0x0, None) {
+                    acontrol_system_log!(LogType::Error, "Error turning off fingerprint led: {}", err);
+                  }
+                }
+                func(&FingerprintState::ENROLL_TIMEOUT, None);
+                state_locked.set(FingerprintDriverState::IDLE);
+                (**expires_locked) = None;
+              } else if sec > 120.0 {
                 state_locked.set(FingerprintDriverState::IDLE);
                 (**expires_locked) = None;
               }
@@ -621,12 +742,13 @@ impl Fingerprint for Gt521fx {
                                 (**expires_locked) = None;
                               }
                             } else {
-                              if response.parameter <= 2999 {
-                                acontrol_system_log!(LogType::Error, "Enrollment error: ID duplicated - {}", response.parameter);
+                              let reason = if response.parameter < u32::from(slots) {
+                                format!("Finger already enrolled at position {}", response.parameter)
                               } else {
-                                acontrol_system_log!(LogType::Error, "Enroll error: {}", (Error::from(response.parameter)).name());
-                              }
-                              func(&FingerprintState::ERROR, None);
+                                format!("Enroll error: {}", (Error::from(response.parameter)).name())
+                              };
+                              acontrol_system_log!(LogType::Error, "Enrollment error: {}", reason);
+                              func(&FingerprintState::ERROR, Some(&reason));
                               state_locked.set(FingerprintDriverState::IDLE);
                               (**expires_locked) = None;
                             }
@@ -740,7 +862,7 @@ impl Fingerprint for Gt521fx {
                                     Ok(ref response) => {
                                       if response.response == Command::Ack.value() {
                                         acontrol_system_log!(LogType::Debug, "============>Fingerprint IS Registered<=============");
-                                        func(&FingerprintState::AUTHORIZED, None);
+                                        func(&FingerprintState::AUTHORIZED, Some(&response.parameter.to_string()));
                                         state_locked.set(FingerprintDriverState::IDLE);
                                         (**expires_locked) = None;
                                       } else {
@@ -815,46 +937,175 @@ impl Fingerprint for Gt521fx {
       return false;
   }
 
-  fn start_enroll(&mut self, data: &FingerprintData) -> bool {
+  fn delete(&mut self, pos: u16) -> Result<(), String> {
+      let gt521fx = self.gt521fx.clone();
+      if let Ok(mut gt521fx_locked) = gt521fx.lock() {
+          match gt521fx_locked.send_command(Command::DeleteID, u32::from(pos), None){
+            Ok(response) => {
+              if response.response == Command::Ack.value() {
+                  return Ok(());
+              } else {
+                return Err(format!("Delete error at position {}: 0x{:X}", pos, response.parameter));
+              }
+            },
+            Err(err) => {
+              return Err(format!("Delete error at position {}: {}", pos, err));
+            }
+          }
+      }
+      Err(String::from("Fingerprint device busy"))
+  }
+
+  fn is_enrolled(&mut self, pos: u16) -> Result<bool, String> {
+      match self.gt521fx.lock() {
+        Ok(mut gt521fx_locked) => gt521fx_locked.is_enrolled(pos),
+        Err(_) => Err(String::from("Fingerprint device lock poisoned"))
+      }
+  }
+
+  fn export_template(&mut self, pos: u16) -> Result<Vec<u8>, String> {
+      let gt521fx = self.gt521fx.clone();
+      let mut gt521fx_locked = match gt521fx.lock() {
+        Ok(gt521fx_locked) => gt521fx_locked,
+        Err(_) => return Err(String::from("Fingerprint device lock poisoned"))
+      };
+
+      /* An empty slot answers GetTemplate with a bare Nack and no data
+       * packet, which would only show up as a read timeout. */
+      if !gt521fx_locked.is_enrolled(pos)? {
+        return Err(format!("Position {} is not enrolled", pos));
+      }
+
+      let mut packet = FingerprintTemplatePacket::new();
+      match gt521fx_locked.send_command(Command::GetTemplate, u32::from(pos), Some(&mut packet)) {
+        Ok(ref response) if response.response == Command::Ack.value() => {},
+        Ok(response) => return Err(format!("GetTemplate error at position {}: {}", pos, (Error::from(response.parameter)).name())),
+        Err(err) => return Err(format!("GetTemplate error at position {}: {}", pos, err))
+      }
+
+      packet.template.ok_or(format!("No template received for position {}", pos))
+  }
+
+  fn import_template(&mut self, pos: u16, template: &[u8]) -> Result<(), String> {
+      if template.len() != TEMPLATE_SIZE {
+        return Err(format!("Invalid template size {}, expected {}", template.len(), TEMPLATE_SIZE));
+      }
+
+      let gt521fx = self.gt521fx.clone();
+      let mut gt521fx_locked = match gt521fx.lock() {
+        Ok(gt521fx_locked) => gt521fx_locked,
+        Err(_) => return Err(String::from("Fingerprint device lock poisoned"))
+      };
+
+      match gt521fx_locked.send_command(Command::SetTemplate, u32::from(pos), None) {
+        Ok(ref response) if response.response == Command::Ack.value() => {},
+        Ok(response) => return Err(format!("SetTemplate error at position {}: {}", pos, (Error::from(response.parameter)).name())),
+        Err(err) => return Err(format!("SetTemplate error at position {}: {}", pos, err))
+      }
+
+      match gt521fx_locked.send_data(template) {
+        Ok(ref response) if response.response == Command::Ack.value() => Ok(()),
+        Ok(response) => Err(format!("Template upload error at position {}: {}", pos, (Error::from(response.parameter)).name())),
+        Err(err) => Err(format!("Template upload error at position {}: {}", pos, err))
+      }
+  }
+
+  fn start_enroll(&mut self, data: &FingerprintData) -> Result<u16, String> {
     acontrol_system_log!(LogType:: Debug, "start enroll");
     let gt521fx = self.gt521fx.clone();
     let state_cloned = self.state.clone();
     let expires_cloned = self.expires.clone();
 
-    if let Ok(mut state_locked) = state_cloned.lock() {
-        if let Ok(mut expires_locked) = expires_cloned.lock(){
-            if let Ok(mut gt521fx_locked) = gt521fx.lock() {
-
-              if let Some(pos)  = data.pos {
-                match gt521fx_locked.send_command(Command::EnrollStart, u32::from(pos), None){
-                  Ok(response) => {
-                    if response.response == Command::Ack.value() {
-
-                      if let Err(err) = gt521fx_locked.send_command(Command::CmosLed, 0x1, None) {
-                        acontrol_system_log!(LogType::Error, "Error turning on fingerprint led: {}", err);
-                        return false;
-                      } else {
-                        (*expires_locked) = Some(Instant::now());
-                        (*state_locked).set(FingerprintDriverState::ENROLL1);
-                      }
+    let mut state_locked = state_cloned.lock().map_err(|_| String::from("Fingerprint device lock poisoned"))?;
+    let mut expires_locked = expires_cloned.lock().map_err(|_| String::from("Fingerprint device lock poisoned"))?;
+    let mut gt521fx_locked = gt521fx.lock().map_err(|_| String::from("Fingerprint device lock poisoned"))?;
 
-                    } else {
-                      acontrol_system_log!(LogType::Error, "EnrollStart error: {}", response.parameter);
-                      (*state_locked).set(FingerprintDriverState::ENROLL_ERROR);
-                    }
-                  },
-                  Err(_err) => {
-                      return false;
-                  }
-                }
-              } else {
-                return false;
-              }
+    let pos = match data.pos {
+      Some(pos) if pos >= self.slots => return Err(format!("Invalid fingerprint position: {}", pos)),
+      Some(pos) => {
+        if gt521fx_locked.is_enrolled(pos)? {
+          return Err(format!("Position {} is already enrolled", pos));
+        }
+        pos
+      },
+      None => gt521fx_locked.free_slot(self.slots)?
+    };
+
+    match gt521fx_locked.send_command(Command::EnrollStart, u32::from(pos), None){
+      Ok(response) => {
+        if response.response == Command::Ack.value() {
+
+          if let Err(err) = gt521fx_locked.send_command(Command::CmosLed, 0x1, None) {
+            acontrol_system_log!(LogType::Error, "Error turning on fingerprint led: {}", err);
+            return Err(format!("Error turning on fingerprint led: {}", err));
+          } else {
+            (*expires_locked) = Some(Instant::now());
+            (*state_locked).set(FingerprintDriverState::ENROLL1);
           }
+
+        } else {
+          acontrol_system_log!(LogType::Error, "EnrollStart error: {}", response.parameter);
+          (*state_locked).set(FingerprintDriverState::ENROLL_ERROR);
+          return Err(format!("EnrollStart error at position {}: {}", pos, (Error::from(response.parameter)).name()));
+        }
+      },
+      Err(err) => {
+          return Err(format!("EnrollStart error at position {}: {}", pos, err));
       }
     }
 
-    return true;
+    Ok(pos)
+  }
+
+  fn cancel_enroll(&mut self) -> Result<(), String> {
+    let mut state_locked = self.state.lock().map_err(|_| String::from("Fingerprint device lock poisoned"))?;
+    let mut expires_locked = self.expires.lock().map_err(|_| String::from("Fingerprint device lock poisoned"))?;
+
+    if !(*state_locked).is_enrolling() {
+      return Err(String::from("No enrolment in progress"));
+    }
+
+    if let Ok(ref mut gt521fx_locked) = self.gt521fx.lock() {
+      if let Err(err) = gt521fx_locked.send_command(Command::CmosLed, 0x0, None) {
+        acontrol_system_log!(LogType::Error, "Error turning off fingerprint led: {}", err);
+      }
+    }
+
+    (*state_locked).set(FingerprintDriverState::IDLE);
+    (*expires_locked) = None;
+
+    Ok(())
+  }
+
+  fn device_info(&mut self) -> Result<FingerprintDeviceInfo, String> {
+    let open_data = match self.open_data {
+      Some(ref open_data) => open_data,
+      None => return Err(String::from("Fingerprint device not initialized"))
+    };
+
+    let gt521fx = self.gt521fx.clone();
+    let mut gt521fx_locked = gt521fx.lock().map_err(|_| String::from("Fingerprint device lock poisoned"))?;
+
+    let security_level = match gt521fx_locked.send_command(Command::GetSecurityLevel, 0, None) {
+      Ok(ref response) if response.response == Command::Ack.value() => response.parameter as u8,
+      Ok(response) => return Err(format!("GetSecurityLevel error: {}", (Error::from(response.parameter)).name())),
+      Err(err) => return Err(format!("GetSecurityLevel error: {}", err))
+    };
+
+    let enrolled = match gt521fx_locked.send_command(Command::GetEnrollCount, 0, None) {
+      Ok(ref response) if response.response == Command::Ack.value() => response.parameter,
+      Ok(response) => return Err(format!("GetEnrollCount error: {}", (Error::from(response.parameter)).name())),
+      Err(err) => return Err(format!("GetEnrollCount error: {}", err))
+    };
+
+    Ok(FingerprintDeviceInfo {
+      firmware_version: open_data.firmware_version,
+      iso_area_max_size: open_data.iso_area_max_size,
+      serial_number: open_data.device_serial_num.to_vec(),
+      security_level: security_level,
+      enrolled: enrolled,
+      slots: self.slots
+    })
   }
 }
 
@@ -0,0 +1,68 @@
+/**
+ * @file   lock.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  Door lock global interface
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+mod relay;
+mod script;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(dead_code)]
+pub enum LockState {
+  Open,
+  Closed
+}
+
+#[allow(dead_code)]
+impl LockState {
+  pub fn name(&self) -> &'static str {
+    match *self {
+      LockState::Open => "Open",
+      LockState::Closed => "Closed",
+    }
+  }
+}
+
+pub trait Lock {
+  fn init(&mut self) -> Result<(), String>;
+  fn pulse(&mut self, duration: Duration) -> Result<(), String>;
+  fn hold(&mut self) -> Result<(), String>;
+  fn release(&mut self) -> Result<(), String>;
+  fn denied(&mut self) -> Result<(), String>;
+  fn state(&mut self) -> Result<LockState, String>;
+  fn unload(&mut self) -> Result<(), String>;
+  fn signature(&self) -> String;
+}
+
+pub fn lock_by_name(name: &str, params: &HashMap<String, String>) -> Option<Box<dyn Lock+Sync+Send>> {
+    match name {
+      "relay" => return Some(Box::new(relay::Relay::new(params))),
+      "script" => return Some(Box::new(script::ScriptLock::new(params))),
+      _ => return None
+    }
+}
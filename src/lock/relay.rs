@@ -0,0 +1,205 @@
+/**
+ * @file   lock/relay.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  Relay door lock over sysfs gpio
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+use crate::lock::{Lock, LockState};
+use crate::acontrol_system_log;
+use crate::log::LogType;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use std::thread;
+use std::time::Duration;
+
+use sysfs_gpio::{Direction, Pin};
+
+const RELAY_DEFAULT_PIN: u64 = 27;
+const RELAY_DEFAULT_ACTIVE_LOW: bool = false;
+
+struct RelayThreadSafe {
+  pin: Option<Pin>,
+  active_low: bool,
+  held: bool,
+  pulse_id: u64,
+}
+
+impl RelayThreadSafe {
+  fn set_active(&mut self, active: bool) -> Result<(), String> {
+    let value = if active != self.active_low { 1 } else { 0 };
+
+    if let Some(pin) = self.pin {
+      if let Err(err) = pin.set_value(value) {
+        return Err(format!("Error setting relay pin: {}", err));
+      }
+      return Ok(());
+    }
+    Err(format!("{}", "Relay pin not configured"))
+  }
+
+  fn is_active(&self) -> Result<bool, String> {
+    if let Some(pin) = self.pin {
+      match pin.get_value() {
+        Ok(value) => return Ok((value != 0) != self.active_low),
+        Err(err) => return Err(format!("Error reading relay pin: {}", err))
+      }
+    }
+    Err(format!("{}", "Relay pin not configured"))
+  }
+}
+
+unsafe impl Send for RelayThreadSafe {}
+unsafe impl Sync for RelayThreadSafe {}
+
+pub struct Relay {
+  relay: Arc<Mutex<RelayThreadSafe>>,
+  pin_num: u64,
+}
+
+impl Relay {
+  pub fn new(params: &HashMap<String, String>) -> Self {
+    let pin_num = params.get("pin").and_then(|pin| pin.parse::<u64>().ok()).unwrap_or(RELAY_DEFAULT_PIN);
+    let active_low = params.get("active_low").and_then(|active_low| active_low.parse::<bool>().ok()).unwrap_or(RELAY_DEFAULT_ACTIVE_LOW);
+
+    return Relay { pin_num: pin_num, relay: Arc::new(Mutex::new(RelayThreadSafe {
+        pin: None,
+        active_low: active_low,
+        held: false,
+        pulse_id: 0
+      }
+    ))};
+  }
+}
+
+impl Lock for Relay {
+  fn init(&mut self) -> Result<(), String> {
+    let relay = self.relay.clone();
+    let mut relay_locked = relay.lock().unwrap();
+
+    let pin = Pin::new(self.pin_num);
+    if let Err(err) = pin.export() {
+      return Err(format!("{}: {}","Error initializing relay gpio port",err));
+    }
+
+    //for non root users, exporting a pin could have a delay to show up at sysfs
+    thread::sleep(Duration::from_millis(100));
+
+    let direction = if relay_locked.active_low { Direction::High } else { Direction::Low };
+    if let Err(err) = pin.set_direction(direction) {
+      return Err(format!("{}: {}","Error configuring relay gpio port",err));
+    }
+
+    relay_locked.pin = Some(pin);
+
+    acontrol_system_log!(LogType::Info, "Relay lock initialized at gpio {}", self.pin_num);
+
+    Ok(())
+  }
+
+  fn pulse(&mut self, duration: Duration) -> Result<(), String> {
+    let relay = self.relay.clone();
+    let pulse_id;
+
+    {
+      let mut relay_locked = relay.lock().unwrap();
+      relay_locked.set_active(true)?;
+      relay_locked.pulse_id = relay_locked.pulse_id.wrapping_add(1);
+      pulse_id = relay_locked.pulse_id;
+    }
+
+    acontrol_system_log!(LogType::Debug, "Relay pulse for {} ms", duration.as_millis());
+
+    let _handler = thread::spawn(move || {
+      thread::sleep(duration);
+      if let Ok(ref mut relay_locked) = relay.lock() {
+        //a newer pulse or a hold owns the relay now, leave it alone
+        if relay_locked.held || relay_locked.pulse_id != pulse_id {
+          return;
+        }
+        if let Err(err) = relay_locked.set_active(false) {
+          acontrol_system_log!(LogType::Error, "Error ending relay pulse: {}", err);
+        }
+      }
+    });
+
+    Ok(())
+  }
+
+  fn hold(&mut self) -> Result<(), String> {
+    let relay = self.relay.clone();
+    let mut relay_locked = relay.lock().unwrap();
+
+    relay_locked.held = true;
+    relay_locked.set_active(true)
+  }
+
+  fn release(&mut self) -> Result<(), String> {
+    let relay = self.relay.clone();
+    let mut relay_locked = relay.lock().unwrap();
+
+    relay_locked.held = false;
+    relay_locked.pulse_id = relay_locked.pulse_id.wrapping_add(1);
+    relay_locked.set_active(false)
+  }
+
+  fn denied(&mut self) -> Result<(), String> {
+    Ok(())
+  }
+
+  fn state(&mut self) -> Result<LockState, String> {
+    let relay = self.relay.clone();
+    let relay_locked = relay.lock().unwrap();
+
+    match relay_locked.is_active() {
+      Ok(true) => Ok(LockState::Open),
+      Ok(false) => Ok(LockState::Closed),
+      Err(err) => Err(err)
+    }
+  }
+
+  fn unload(&mut self) -> Result<(), String> {
+    acontrol_system_log!(LogType::Info, "Lock driver unloading");
+    let relay = self.relay.clone();
+    let mut relay_locked = relay.lock().unwrap();
+
+    if let Some(pin) = relay_locked.pin {
+      let _ = relay_locked.set_active(false);
+      if let Err(err) = pin.unexport() {
+        return Err(format!("{}(=>{})", "Lock driver error",err));
+      }
+    }
+    relay_locked.pin = None;
+    Ok(())
+  }
+
+  fn signature(&self) -> String {
+    return String::from("Relay Door Lock Module");
+  }
+}
+
+unsafe impl Send for Relay {}
+unsafe impl Sync for Relay {}
@@ -0,0 +1,123 @@
+/**
+ * @file   lock/script.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  Door lock driven by external scripts
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+use crate::lock::{Lock, LockState};
+use crate::acontrol_system_log;
+use crate::log::LogType;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+const SCRIPT_GRANTED: &str = "/acontrol/granted";
+const SCRIPT_DENIED: &str = "/acontrol/denieded";
+const SCRIPT_QUERY: &str = "/acontrol/query";
+
+pub struct ScriptLock {
+  granted: String,
+  denied: String,
+  query: String,
+}
+
+impl ScriptLock {
+  pub fn new(params: &HashMap<String, String>) -> Self {
+    return ScriptLock {
+      granted: params.get("granted").cloned().unwrap_or(String::from(SCRIPT_GRANTED)),
+      denied: params.get("denied").cloned().unwrap_or(String::from(SCRIPT_DENIED)),
+      query: params.get("query").cloned().unwrap_or(String::from(SCRIPT_QUERY)),
+    };
+  }
+
+  fn run(&self, script: &str, args: &[&str]) -> Result<String, String> {
+    match Command::new(script).args(args).output() {
+      Ok(output) => {
+        if !output.status.success() {
+          return Err(format!("{} exited with {}", script, output.status));
+        }
+        Ok(String::from_utf8_lossy(output.stdout.as_slice()).to_string())
+      },
+      Err(err) => Err(format!("Error executing {}: {}", script, err))
+    }
+  }
+}
+
+impl Lock for ScriptLock {
+  fn init(&mut self) -> Result<(), String> {
+    for script in [&self.granted, &self.denied, &self.query].iter() {
+      if !Path::new(script).exists() {
+        acontrol_system_log!(LogType::Warning, "Lock script {} not found", script);
+      }
+    }
+    Ok(())
+  }
+
+  fn pulse(&mut self, _duration: Duration) -> Result<(), String> {
+    let messages = self.run(&self.granted, &["-f"])?;
+    for message in messages.lines() {
+      acontrol_system_log!(LogType::Info, "granted: {}", message);
+    }
+    Ok(())
+  }
+
+  fn hold(&mut self) -> Result<(), String> {
+    Err(String::from("Not Implemented"))
+  }
+
+  fn release(&mut self) -> Result<(), String> {
+    Err(String::from("Not Implemented"))
+  }
+
+  fn denied(&mut self) -> Result<(), String> {
+    let messages = self.run(&self.denied, &["-f"])?;
+    for message in messages.lines() {
+      acontrol_system_log!(LogType::Info, "denieded: {}", message);
+    }
+    Ok(())
+  }
+
+  fn state(&mut self) -> Result<LockState, String> {
+    let query = self.run(&self.query, &[])?;
+    if query.trim_end().to_lowercase().eq("close") {
+      Ok(LockState::Closed)
+    } else {
+      Ok(LockState::Open)
+    }
+  }
+
+  fn unload(&mut self) -> Result<(), String> {
+    acontrol_system_log!(LogType::Info, "Lock driver unloading");
+    Ok(())
+  }
+
+  fn signature(&self) -> String {
+    return String::from("Script Door Lock Module");
+  }
+}
+
+unsafe impl Send for ScriptLock {}
+unsafe impl Sync for ScriptLock {}
@@ -53,6 +53,17 @@ impl LogType {
     }
   }
 
+  pub fn from_name(name: &str) -> Option<LogType> {
+    match name.to_lowercase().as_str() {
+      "debug" => Some(LogType::Debug),
+      "info" => Some(LogType::Info),
+      "warning" => Some(LogType::Warning),
+      "error" => Some(LogType::Error),
+      "fatal" => Some(LogType::Fatal),
+      _ => None
+    }
+  }
+
   fn value(&self) -> u16 {
     return (*self) as u16;
   }
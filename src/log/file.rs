@@ -37,10 +37,12 @@ pub struct FileLog {
 
 
 impl FileLog {
-  pub fn new(_params: &HashMap<String, String>) -> Self {
-      let mut path = PathBuf::from("/var/log/acontrol");
+  pub fn new(params: &HashMap<String, String>) -> Self {
+      let mut path = match params.get("LOGS_PATH") {
+        Some(logs_path) => PathBuf::from(logs_path),
+        None => PathBuf::from("/var/log/acontrol")
+      };
       path.push("acontrol.log");
-      let _ = std::fs::remove_file(path.as_os_str());
       return FileLog {log_file: path };
   }
 }
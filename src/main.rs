@@ -34,7 +34,13 @@ pub mod server;
 pub mod persist;
 pub mod system;
 pub mod display;
+pub mod lock;
+pub mod door;
 pub mod log;
+pub mod config;
+pub mod crypto;
+pub mod card_signature;
+pub mod template_vault;
 
 #[macro_use]
 extern crate nix;
@@ -50,14 +56,17 @@ use log::LogType;
 use nix::sys::signal;
 use std::process;
 use clap::{Arg,App};
-use std::collections::HashMap;
+use config::Config;
 
-const DEFAULT_LOGS_PATH:&str = "/var/log/acontrol";
-const DEFAULT_DATA_PATH:&str = "/var/lib/acontrol";
-
-const HTTP_DEFAULT_HOST:&str = "localhost";
-const HTTP_DEFAULT_PORT:u32 = 8088;
-const MIFARE_DEFAULT_KEY:&str = "0xFF,0xFF,0xFF,0xFF,0xFF,0xFF";
+fn required_module(section: &str, module: &Option<String>) -> String {
+  match module {
+    Some(module) => module.clone(),
+    None => {
+      eprintln!("{} module not configured (use --{}-module or the [{}] section of the config file)", section, section, section);
+      process::exit(-1);
+    }
+  }
+}
 
 extern "C" fn handle_sigint(_:i32) {
   println!("Exiting...");
@@ -80,18 +89,24 @@ async fn main(){
 	.version("0.0.1")
 	.author("Otávio Ribeiro <otavio.ribeiro@gmail.com>")
 	.about("FingerPrint + NFC Card Access Control Software")
+	.arg(Arg::with_name("config")
+		.required(false)
+		.takes_value(true)
+		.short("c")
+		.long("config")
+		.help("Configuration file. Default /etc/acontrol/acontrol.toml"))
 	.arg(Arg::with_name("fingerprint-module")
-		.required(true)
+		.required(false)
 		.takes_value(true)
 		.short("f")
 		.long("fingerprint-module")
 		.help("Available modules: gt521fx"))
   .arg(Arg::with_name("nfc-module")
-          .required(true)
+          .required(false)
           .takes_value(true)
           .short("n")
           .long("nfc-module")
-          .help("Available modules: mfrc522"))
+          .help("Available modules: mfrc522, pn532_spi, pn532_i2c, pn532_uart"))
   .arg(Arg::with_name("mifare-key")
           .required(false)
           .takes_value(true)
@@ -99,17 +114,44 @@ async fn main(){
           .long("mifare-key")
           .help("Mifare key used to format/read/write card. Default (0xFF, 0xFF, 0XFF, 0xFF, 0xFF, 0xFF)"))
   .arg(Arg::with_name("audio-module")
-          .required(true)
+          .required(false)
           .takes_value(true)
           .short("a")
           .long("audio-module")
           .help("Available modules: buzzer"))
   .arg(Arg::with_name("bluetooth-module")
-          .required(true)
+          .required(false)
           .takes_value(true)
           .short("b")
           .long("bluetooth-module")
           .help("Available modules: bluez"))  
+  .arg(Arg::with_name("lock-module")
+          .required(false)
+          .takes_value(true)
+          .short("l")
+          .long("lock-module")
+          .help("Available modules: relay, script"))
+  .arg(Arg::with_name("door-module")
+          .required(false)
+          .takes_value(true)
+          .short("d")
+          .long("door-module")
+          .help("Available modules: gpio"))
+  .arg(Arg::with_name("log-level")
+          .required(false)
+          .takes_value(true)
+          .long("log-level")
+          .help("Minimum log level: debug, info, warning, error, fatal. Default info"))
+  .arg(Arg::with_name("auth-policy")
+          .required(false)
+          .takes_value(true)
+          .long("auth-policy")
+          .help("Factors required to open the door: any-one, card+finger, ble+finger. Default any-one"))
+  .arg(Arg::with_name("mfa-timeout")
+          .required(false)
+          .takes_value(true)
+          .long("mfa-timeout")
+          .help("Seconds to wait for the second factor. Default 10"))
   .arg(Arg::with_name("http-server-port")
           .required(false)
           .takes_value(true)
@@ -122,18 +164,98 @@ async fn main(){
           .short("h")
           .long("http-server-host")
           .help("http server host to bind to"))
+  .arg(Arg::with_name("https-cert")
+          .required(false)
+          .takes_value(true)
+          .requires("https-key")
+          .long("https-cert")
+          .help("PEM certificate used to serve the management api over https"))
+  .arg(Arg::with_name("https-key")
+          .required(false)
+          .takes_value(true)
+          .requires("https-cert")
+          .long("https-key")
+          .help("PEM (PKCS#8) private key of the https certificate"))
 	.get_matches();
 
-  let http_port:u32 = value_t!(matches, "http-server-port",u32).unwrap_or(HTTP_DEFAULT_PORT);
-  let http_host:&str = matches.value_of("http-server-host").unwrap_or(HTTP_DEFAULT_HOST);
+  let mut config = match matches.value_of("config") {
+    Some(path) => Config::load(path, true),
+    None => Config::load(config::DEFAULT_CONFIG_FILE, false)
+  }.unwrap_or_else(|err| {
+    eprintln!("{}",err);
+    process::exit(-1);
+  });
+
+  if let Some(module) = matches.value_of("bluetooth-module") {
+    config.bluetooth.module = Some(module.to_string());
+  }
+
+  if let Some(module) = matches.value_of("fingerprint-module") {
+    config.fingerprint.module = Some(module.to_string());
+  }
+
+  if let Some(module) = matches.value_of("nfc-module") {
+    config.nfc.module = Some(module.to_string());
+  }
+
+  if let Some(module) = matches.value_of("audio-module") {
+    config.audio.module = Some(module.to_string());
+  }
+
+  if let Some(module) = matches.value_of("lock-module") {
+    config.lock.module = Some(module.to_string());
+  }
+
+  if let Some(module) = matches.value_of("door-module") {
+    config.door.module = Some(module.to_string());
+  }
+
+  if let Some(key) = matches.value_of("mifare-key") {
+    config.nfc.mifare_key = key.to_string();
+  }
+
+  if let Some(level) = matches.value_of("log-level") {
+    config.log.level = level.to_string();
+  }
+
+  if let Some(policy) = matches.value_of("auth-policy") {
+    config.access.auth_policy = Some(policy.to_string());
+  }
+
+  if let Some(timeout) = matches.value_of("mfa-timeout") {
+    match timeout.parse::<u64>() {
+      Ok(timeout) => config.access.mfa_timeout = Some(timeout),
+      Err(_) => {
+        eprintln!("invalid mfa timeout");
+        process::exit(-1);
+      }
+    }
+  }
+
+  if let Ok(port) = value_t!(matches, "http-server-port",u32) {
+    config.server.port = port;
+  }
+
+  if let Some(host) = matches.value_of("http-server-host") {
+    config.server.host = host.to_string();
+  }
+
+  if let (Some(cert), Some(key)) = (matches.value_of("https-cert"), matches.value_of("https-key")) {
+    config.server.https_cert = Some(cert.to_string());
+    config.server.https_key = Some(key.to_string());
+  }
+
+  if config.server.https_cert.is_some() != config.server.https_key.is_some() {
+    eprintln!("https needs both a certificate and a key");
+    process::exit(-1);
+  }
 
   let p:&[_] = &['0','x','X'];
-  let mifare_key= matches.value_of("mifare-key").unwrap_or(MIFARE_DEFAULT_KEY);
-  let mifare_vec=mifare_key.split(",");
+  let mifare_vec=config.nfc.mifare_key.split(",");
   
   let mut mifare_key_bytes: Vec<u8> = Vec::new();
   for key in mifare_vec {
-    if let Ok(byte) = u8::from_str_radix(key.trim_matches(p),16) {
+    if let Ok(byte) = u8::from_str_radix(key.trim().trim_matches(p),16) {
       mifare_key_bytes.push(byte);
     } else {
       eprintln!("invalid mifare key");
@@ -146,23 +268,57 @@ async fn main(){
     process::exit(-1);
   }
 
-  let mut params: HashMap<String,String> = HashMap::new();
-  params.insert("LOGS_PATH".to_string(), DEFAULT_LOGS_PATH.to_string());
-  params.insert("DATA_PATH".to_string(), DEFAULT_DATA_PATH.to_string());
+  if let Some(ref policy) = config.access.auth_policy {
+    if system::AuthPolicy::from_name(policy).is_none() {
+      eprintln!("invalid auth policy");
+      process::exit(-1);
+    }
+  }
+
+  let log_level = match LogType::from_name(&config.log.level) {
+    Some(level) => level,
+    None => {
+      eprintln!("invalid log level");
+      process::exit(-1);
+    }
+  };
+
+  let params = config.system_params();
 
-  let bluetooth = matches.value_of("bluetooth-module").unwrap();
-  let fingerprint = matches.value_of("fingerprint-module").unwrap();
-  let nfc = matches.value_of("nfc-module").unwrap();
-  let audio = matches.value_of("audio-module").unwrap();
+  let bluetooth = required_module("bluetooth", &config.bluetooth.module);
+  let fingerprint = required_module("fingerprint", &config.fingerprint.module);
+  let nfc_readers = config.nfc.readers().unwrap_or_else(|err| {
+    eprintln!("{}", err);
+    process::exit(-1);
+  });
+  let audio = required_module("audio", &config.audio.module);
+  let lock = required_module("lock", &config.lock.module);
 
-  let bt_drv = bt::bluetooth_by_name(bluetooth);
-  let fingerprint_drv = fingerprint::fingerprint_by_name(fingerprint);
-  let nfcreader_drv = nfc::nfcreader_by_name(nfc);
-  let audio_drv = audio::audio_by_name(audio);
-  let display_drv = display::display_by_name("neopixel");
-  let persist_drv = persist::persist_by_name("sqlite");
+  let bt_drv = bt::bluetooth_by_name(&bluetooth, &config.bluetooth.params());
+  let fingerprint_drv = fingerprint::fingerprint_by_name(&fingerprint, &config.fingerprint.params());
+  let mut nfcreader_drvs = Vec::new();
+  for (name, module, params) in nfc_readers {
+    let module = required_module("nfc", &module);
+    if let Some(drv) = nfc::nfcreader_by_name(&module, &params) {
+      nfcreader_drvs.push((name, drv));
+    }
+  }
+  let audio_drv = audio::audio_by_name(&audio, &config.audio.params());
+  let display_drv = match config.display.module {
+    Some(ref display) => display::display_by_name(display, &config.display.params()),
+    None => None
+  };
+  let lock_drv = lock::lock_by_name(&lock, &config.lock.params());
+  let door_drv = match config.door.module {
+    Some(ref door) => door::door_by_name(door, &config.door.params()),
+    None => None
+  };
+  let persist_drv = match config.persist.module {
+    Some(ref persist) => persist::persist_by_name(persist),
+    None => None
+  };
 
-  let log_drv = log::log_by_name("file", LogType::Debug, &params);
+  let log_drv = log::log_by_name(&config.log.module, log_level, &config.log_params());
 
   if let Some(ref drv) = bt_drv
   {
@@ -173,8 +329,8 @@ async fn main(){
     println!("Fingerprint driver: {}",drv.signature());
   }
 
-  if let Some(ref drv) = nfcreader_drv {
-    println!("Nfc driver: {}",drv.signature());
+  for (name, drv) in nfcreader_drvs.iter() {
+    println!("Nfc driver ({}): {}", name, drv.signature());
   }
 
   if let Some(ref drv) = audio_drv {
@@ -185,19 +341,64 @@ async fn main(){
     println!("Display driver: {}", drv.signature());
   }
 
+  if let Some(ref drv) = lock_drv {
+    println!("Lock driver: {}", drv.signature());
+  }
+
+  if let Some(ref drv) = door_drv {
+    println!("Door driver: {}", drv.signature());
+  }
+
   if let Some(ref drv) = log_drv {
     println!("Log driver: {}", drv.signature());
   }
 
   {
     if !system::acontrol_system_init(&params, bt_drv, fingerprint_drv, 
-      nfcreader_drv, audio_drv, persist_drv, display_drv, log_drv).await {
+      nfcreader_drvs, audio_drv, persist_drv, display_drv, lock_drv, door_drv, log_drv).await {
       process::exit(-1);
     }
 
-    if !system::acontrol_system_set_mifare_keys(&mifare_key_bytes, &mifare_key_bytes) {
+    let mifare_keys = match config.nfc.master_key {
+      Some(ref master_key) => match hex::decode(master_key.trim()).map_err(|err| format!("{}", err))
+          .and_then(|master_key| nfc::MifareKeys::diversified(&master_key)) {
+        Ok(keys) => keys,
+        Err(err) => {
+          eprintln!("invalid mifare master key: {}", err);
+          process::exit(-1);
+        }
+      },
+      None => nfc::MifareKeys::fixed(&mifare_key_bytes, &mifare_key_bytes)
+    };
+
+    if !system::acontrol_system_set_mifare_keys(mifare_keys) {
       process::exit(-1);
     }
+
+    match card_signature::CardKeys::load(&config.card_signing, &config.paths.data) {
+      Ok(keys) => {
+        system::acontrol_system_set_card_keys(keys);
+      },
+      Err(err) => {
+        eprintln!("{}",err);
+        process::exit(-1);
+      }
+    }
+
+    match template_vault::TemplateVault::load(config.fingerprint.params().get("template_key"), &config.paths.data) {
+      Ok(vault) => {
+        system::acontrol_system_set_template_vault(vault);
+      },
+      Err(err) => {
+        eprintln!("{}",err);
+        process::exit(-1);
+      }
+    }
+  }
+
+  if let Err(err) = server::bootstrap_admin(&params) {
+    eprintln!("{}",err);
+    process::exit(-1);
   }
 
   let server_b = server::create_server_by_name("generic");
@@ -210,7 +411,11 @@ async fn main(){
     server = server_b.unwrap();
   }
 
-  if let Err(err) = server.host(http_host).port(http_port).init() {
+  if let (Some(ref cert), Some(ref key)) = (&config.server.https_cert, &config.server.https_key) {
+    server.tls(cert, key);
+  }
+
+  if let Err(err) = server.host(&config.server.host).port(config.server.port).init() {
     eprintln!("{}",err);
   }
   system::acontrol_system_end();
@@ -26,7 +26,20 @@
  *
  */
 mod mfrc522;
+mod pn532;
 mod pn532_spi;
+mod pn532_i2c;
+mod pn532_uart;
+mod mifare_keys;
+mod desfire;
+mod ntag;
+mod presence;
+mod irq;
+
+pub use self::mifare_keys::MifareKeys;
+
+use std::collections::HashMap;
+use std::fmt;
 
 #[derive(Debug, Clone, Copy)]
 #[allow(dead_code)]
@@ -34,7 +47,12 @@ pub enum CardType{
   Mifare,
   FelicaA,
   FelicaB,
-  Jewel
+  Jewel,
+  Desfire,
+  Ntag213,
+  Ntag215,
+  Ntag216,
+  Ultralight
 }
 
 #[allow(dead_code)]
@@ -44,12 +62,62 @@ impl CardType {
     value
   }
 
-  fn name(&mut self) -> &str {
+  pub fn name(&self) -> &'static str {
     match *self {
       CardType::Mifare => "Mifare",
       CardType::FelicaA => "FelicaA",
       CardType::FelicaB => "FelicaB",
       CardType::Jewel => "Jewel",
+      CardType::Desfire => "Desfire",
+      CardType::Ntag213 => "Ntag213",
+      CardType::Ntag215 => "Ntag215",
+      CardType::Ntag216 => "Ntag216",
+      CardType::Ultralight => "Ultralight",
+    }
+  }
+}
+
+/* Everything a card told the reader while being selected. */
+#[derive(Debug, Clone)]
+pub struct CardInfo {
+  pub uid: Vec<u8>,
+  /* ATQA (SENS_RES), as received. */
+  pub atqa: Vec<u8>,
+  /* SAK (SEL_RES) of the last cascade level. */
+  pub sak: u8,
+  /* Answer to select, only for ISO 14443-4 cards the reader activated. */
+  pub ats: Vec<u8>,
+  pub technology: CardType
+}
+
+impl CardInfo {
+  pub fn new(uid: Vec<u8>, atqa: Vec<u8>, sak: u8, ats: Vec<u8>, technology: CardType) -> CardInfo {
+    CardInfo { uid: uid, atqa: atqa, sak: sak, ats: ats, technology: technology }
+  }
+}
+
+impl fmt::Display for CardInfo {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} uid={} atqa={} sak={:02X}", self.technology.name(), hex::encode_upper(&self.uid), hex::encode_upper(&self.atqa), self.sak)?;
+    if self.ats.len() > 0 {
+      write!(f, " ats={}", hex::encode_upper(&self.ats))?;
+    }
+    Ok(())
+  }
+}
+
+/* What a reader's polling loop reports: a card arriving, or the card
+ * that was on the reader leaving it. */
+pub enum TagEvent {
+  Presented(CardInfo),
+  Removed(CardInfo)
+}
+
+impl TagEvent {
+  pub fn name(&self) -> &'static str {
+    match *self {
+      TagEvent::Presented(_) => "Presented",
+      TagEvent::Removed(_) => "Removed",
     }
   }
 }
@@ -64,8 +132,10 @@ pub enum WriteSecMode {
 pub trait NfcReader {
   fn init(&mut self) -> Result<(), String>;
   fn unload(&mut self) -> Result<(), String>;
-  fn find_tag(&mut self, func: fn(CardType, Vec<u8>) -> bool) -> Result<(), String>;
-  fn set_auth_keys(&mut self, key_a: &Vec<u8>, key_b: &Vec<u8>) -> Result<(), String>;
+  /* Starts polling for cards. `reader` is the name this instance was
+   * configured with and is handed back to `func` with every event. */
+  fn find_tag(&mut self, reader: &str, func: fn(&str, TagEvent) -> bool) -> Result<(), String>;
+  fn set_auth_keys(&mut self, keys: MifareKeys) -> Result<(), String>;
   fn set_auth_bits(&mut self, access_bits: Vec<u8>) -> Result<(), String>;
   fn format(&mut self, uuid: &Vec<u8>) -> Result<(), String>;
   fn restore(&mut self, uuid: &Vec<u8>) -> Result<(), String>;
@@ -74,10 +144,21 @@ pub trait NfcReader {
   fn signature(&self) -> String;
 }
 
-pub fn nfcreader_by_name(name: &str) -> Option<Box<dyn NfcReader+Sync+Send>> {
+pub fn nfcreader_by_name(name: &str, params: &HashMap<String, String>) -> Option<Box<dyn NfcReader+Sync+Send>> {
     match name {
-      "mfrc522" => return Some(Box::new(mfrc522::Mfrc522::new())),
-      "pn532_spi" => return Some(Box::new(pn532_spi::Pn532Spi::new())),
+      "mfrc522" => return Some(Box::new(mfrc522::Mfrc522::new(params))),
+      "pn532_spi" => return Some(Box::new(pn532::Pn532::new(Box::new(pn532_spi::Pn532Spi::new(params)), params))),
+      "pn532_i2c" => return Some(Box::new(pn532::Pn532::new(Box::new(pn532_i2c::Pn532I2c::new(params)), params))),
+      "pn532_uart" => return Some(Box::new(pn532::Pn532::new(Box::new(pn532_uart::Pn532Uart::new(params)), params))),
       _ => return None
     }
 }
+
+/* Slave select gpio the SPI drivers use when `ss_pin` is not set. */
+pub fn default_ss_pin(name: &str) -> Option<u64> {
+    match name {
+      "mfrc522" => Some(mfrc522::MFRC522_DEFAULT_SS_PIN),
+      "pn532_spi" => Some(pn532_spi::PN532_DEFAULT_SS_PIN),
+      _ => None
+    }
+}
@@ -0,0 +1,74 @@
+/**
+ * @file   irq.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  IRQ line of an NFC reader
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use sysfs_gpio::{Direction, Edge, Pin, PinPoller};
+
+/* Gpio wired to the reader's IRQ output (`irq_pin`). Without one the
+ * drivers keep polling. */
+pub fn irq_pin_param(params: &HashMap<String, String>) -> Option<u64> {
+  params.get("irq_pin").and_then(|pin| pin.parse::<u64>().ok())
+}
+
+/* An active low interrupt line, watched through sysfs edge events. */
+pub struct IrqLine {
+  pin: Pin,
+}
+
+impl IrqLine {
+  pub fn open(pin_num: u64) -> Result<IrqLine, String> {
+    let pin = Pin::new(pin_num);
+    if let Err(err) = pin.export() {
+      return Err(format!("Error initializing irq gpio port {}: {}", pin_num, err));
+    }
+
+    //for non root users, exporting a pin could have a delay to show up at sysfs
+    thread::sleep(Duration::from_millis(100));
+
+    if let Err(err) = pin.set_direction(Direction::In) {
+      return Err(format!("Error configuring irq gpio port {}: {}", pin_num, err));
+    }
+
+    if let Err(err) = pin.set_edge(Edge::FallingEdge) {
+      return Err(format!("Error configuring irq gpio port {} edge: {}", pin_num, err));
+    }
+
+    Ok(IrqLine { pin: pin })
+  }
+
+  pub fn poller(&self) -> Result<PinPoller, String> {
+    self.pin.get_poller().map_err(|err| format!("Error watching irq gpio port {}: {}", self.pin.get_pin_num(), err))
+  }
+
+  pub fn close(&self) -> Result<(), String> {
+    self.pin.unexport().map_err(|err| format!("{}", err))
+  }
+}
@@ -25,11 +25,14 @@
  * THE SOFTWARE.
  *
  */
-use crate::nfc::{NfcReader, WriteSecMode, CardType};
+use crate::nfc::{NfcReader, WriteSecMode, CardType, CardInfo, TagEvent, MifareKeys};
+use crate::nfc::presence::{self, CardPresence};
+use crate::nfc::irq::{self, IrqLine};
+use crate::nfc::ntag::{self, Ntag, NtagModel, NfcA};
 use crate::acontrol_system_log;
 use crate::log::LogType;
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -38,7 +41,16 @@ use std::time::{Duration,Instant};
 
 use std::io::prelude::*;
 use spidev::{Spidev, SpidevOptions, SpidevTransfer, SPI_MODE_0};
-use sysfs_gpio::{Direction, Pin};
+use sysfs_gpio::{Direction, Pin, PinPoller};
+
+const MFRC522_DEFAULT_SPI_DEVICE: &str = "/dev/spidev0.0";
+pub(crate) const MFRC522_DEFAULT_SS_PIN: u64 = 17;
+
+//with an irq line the field is only switched on to probe for a card
+const MFRC522_IRQ_PROBE_INTERVAL: Duration = Duration::from_millis(100);
+//time a card needs to power up once the field is on
+const MFRC522_FIELD_SETTLE: Duration = Duration::from_millis(5);
+const MFRC522_IRQ_WAIT_MS: isize = 10;
 
 #[derive(Clone, Copy)]
 #[allow(dead_code)]
@@ -71,6 +83,7 @@ enum Register {
     BitFraming =  0x0d,
     Coll =        0x0e,
     ComIEn =      0x02,
+    DivIEn =      0x03,
     ComIrq =      0x04,
     Command =     0x01,
     CrcResultH =  0x21,
@@ -208,6 +221,10 @@ impl Error {
   }
 }
 
+const CASCADE_TAG: u8 = 0x88;
+/* SAK bit telling the UID continues on the next cascade level. */
+const SAK_CASCADE_BIT: u8 = 0x04;
+
 pub trait MiFare {
   fn send_req_a(&mut self) -> Result<Vec<u8>, String>;
   fn select(&mut self, cascade: u8, uuid: &Vec<u8>) -> Result<Vec<u8>, String>;
@@ -222,9 +239,11 @@ pub trait MiFare {
 struct Mfrc522ThreadSafe {
   spidev: Option<Spidev>,
   ss: Option<Pin>,
-  mifare_key_a: Vec<u8>,
-  mifare_key_b: Vec<u8>,
-  mifare_access_bits: Vec<u8>
+  mifare_keys: MifareKeys,
+  mifare_access_bits: Vec<u8>,
+  card_type: CardType,
+  ntag: Option<NtagModel>,
+  ntag_originality: bool
 }
 
 impl Mfrc522ThreadSafe {
@@ -495,6 +514,35 @@ impl Mfrc522ThreadSafe {
     Ok(())
   }
 
+  /* Sends a REQA with only the receive interrupt routed to the IRQ pin,
+   * push-pull and active low. A card in the field answers right away. */
+  fn arm_card_irq(&mut self) -> Result<(), std::io::Error> {
+    self.command(Command::Idle)?;
+    self.set_bit_mask(Register::TxControl, 0b11)?;
+    thread::sleep(MFRC522_FIELD_SETTLE);
+
+    self.write(Register::DivIEn, 0x80)?;
+    self.write(Register::ComIEn, 0x80 | 0x20)?;
+    self.write(Register::ComIrq, 0x7f)?;
+    self.flush_fifo()?;
+    self.write(Register::FifoData, PICC::REQIDL.value())?;
+    self.command(Command::Transceive)?;
+    self.write(Register::BitFraming, (1 << 7) | 0x07)
+  }
+
+  /* Whether the probe got an answer. The field is switched off until the
+   * next one. */
+  fn card_irq(&mut self) -> Result<bool, std::io::Error> {
+    let irq = self.read(Register::ComIrq)?;
+
+    self.write(Register::ComIEn, 0x80)?;
+    self.write(Register::ComIrq, 0x7f)?;
+    self.command(Command::Idle)?;
+    self.clear_bit_mask(Register::TxControl, 0b11)?;
+
+    Ok(irq & 0x20 != 0)
+  }
+
   fn reset(&mut self) -> Result<(), String> {
     if let Err(_err) = self.initialize() {
       return Err(format!("{}", "NFC error. Error reseting device"));
@@ -502,6 +550,80 @@ impl Mfrc522ThreadSafe {
     Ok(())
   }
 
+  /* ISO 14443-3 anticollision and selection, cascade levels 1 to 3 (4, 7
+   * and 10 byte UIDs). */
+  fn activate(&mut self) -> Result<CardInfo, String> {
+    let atqa = self.send_req_a().map_err(|err| format!("REQA => {}", err))?;
+    let mut uid:Vec<u8> = Vec::new();
+
+    for (level, cascade) in [PICC::ANTICOLL1, PICC::ANTICOLL2, PICC::ANTICOLL3].iter().enumerate() {
+      let answer = self.anticoll(cascade.value(), &Vec::new())
+        .map_err(|err| format!("ANTICOLL CASCADE {} => {}", level + 1, err))?;
+
+      if answer.len() != 5 || answer[..4].iter().fold(0, |bcc, byte| bcc ^ byte) != answer[4] {
+        return Err(format!("ANTICOLL CASCADE {} => invalid answer {:X?}", level + 1, answer));
+      }
+
+      let level_uid = answer[..4].to_vec();
+      let sak = match self.select(cascade.value(), &level_uid) {
+        Ok(ref val) if val.len() > 0 => val[0],
+        Ok(_) => return Err(format!("SELECT CASCADE {} => empty answer", level + 1)),
+        Err(err) => return Err(format!("SELECT CASCADE {} => {}", level + 1, err))
+      };
+
+      if sak & SAK_CASCADE_BIT == 0 {
+        uid.extend_from_slice(&level_uid);
+        return Ok(CardInfo::new(uid, atqa, sak, Vec::new(), CardType::Mifare));
+      }
+
+      if level_uid[0] != CASCADE_TAG {
+        return Err(format!("SELECT CASCADE {} => missing cascade tag", level + 1));
+      }
+      uid.extend_from_slice(&level_uid[1..]);
+    }
+
+    Err(String::from("UID not complete after cascade level 3"))
+  }
+
+  fn ntag_identify(&mut self, uuid: &Vec<u8>) -> Result<(), String> {
+    let check_originality = self.ntag_originality;
+    let model = Ntag::new(self).identify(uuid, check_originality)?;
+
+    acontrol_system_log!(LogType::Debug, "Type 2 tag identified as {:?}", model.card_type);
+
+    self.card_type = model.card_type;
+    self.ntag = Some(model);
+    Ok(())
+  }
+
+}
+
+impl NfcA for Mfrc522ThreadSafe {
+  fn transceive_frame(&mut self, frame: &[u8]) -> Result<Vec<u8>, String> {
+    let mut tx_buf = frame.to_vec();
+
+    match self.calc_crc(&tx_buf) {
+      Ok(crc) => tx_buf.extend(&crc),
+      Err(_err) => return Err(format!("NFC crc calc error sending command 0x{:X}", frame[0]))
+    }
+
+    let answer = match self.transceive(&tx_buf, 0) {
+      Ok(val) => val,
+      Err(ref mut err) => return Err(format!("{} 0x{:X} => {}", "NFC error sending command", frame[0], err.name()))
+    };
+
+    /* ACK/NAK answers are 4 bits long and carry no CRC. */
+    if answer.len() < 3 {
+      return Ok(answer);
+    }
+
+    let data = answer[..answer.len()-2].to_vec();
+    match self.calc_crc(&data) {
+      Ok(crc) if crc[0] == answer[answer.len()-2] && crc[1] == answer[answer.len()-1] => Ok(data),
+      Ok(_) => Err(format!("NFC crc error on answer to command 0x{:X}", frame[0])),
+      Err(_err) => Err(format!("NFC crc calc error on answer to command 0x{:X}", frame[0]))
+    }
+  }
 }
 
 unsafe impl Send for Mfrc522ThreadSafe {}
@@ -575,10 +697,11 @@ impl MiFare for Mfrc522ThreadSafe {
     match key {
       MifareAuthKey::DefaultKeyA => tx_buf.extend(MIFARE_DEFAULT_KEY_A),
       MifareAuthKey::DefaultKeyB => tx_buf.extend(MIFARE_DEFAULT_KEY_B),
-      MifareAuthKey::CustomKeyA => tx_buf.extend(&self.mifare_key_a),
-      MifareAuthKey::CustomKeyB => tx_buf.extend(&self.mifare_key_b)
+      MifareAuthKey::CustomKeyA => tx_buf.extend(self.mifare_keys.key_a(uuid, MifareKeys::sector(addr))),
+      MifareAuthKey::CustomKeyB => tx_buf.extend(self.mifare_keys.key_b(uuid, MifareKeys::sector(addr)))
     }
-    tx_buf.extend(uuid);
+    /* Cards with 7 and 10 byte UIDs authenticate with the last 4 bytes. */
+    tx_buf.extend(&uuid[uuid.len().saturating_sub(4)..]);
 
     match self.authent(&tx_buf, 0) {
       Ok(_val) => Ok(()),
@@ -655,24 +778,29 @@ impl MiFare for Mfrc522ThreadSafe {
   fn write_sec(&mut self, uuid: &Vec<u8>, mode: WriteSecMode) -> Result<(), String> {
     let mut addr:u8 = 3;
     let mut packet:Vec<u8> = Vec::new();
-    let key:MifareAuthKey;
-
-    match mode {
-      WriteSecMode::Format => {
-        packet.extend(&self.mifare_key_a);
-        packet.extend(MIFARE_DEFAULT_ACCESS_BITS);
-        packet.extend(&self.mifare_key_b);
-        key = MifareAuthKey::DefaultKeyA;
-      },
-      WriteSecMode::Restore => {
-        packet.extend(MIFARE_DEFAULT_KEY_A);
-        packet.extend(MIFARE_DEFAULT_ACCESS_BITS);
-        packet.extend(MIFARE_DEFAULT_KEY_B);
-        key = MifareAuthKey::CustomKeyA;
-      }
-    }
+
+    let key:MifareAuthKey = match mode {
+      WriteSecMode::Format => MifareAuthKey::DefaultKeyA,
+      WriteSecMode::Restore => MifareAuthKey::CustomKeyA
+    };
 
     loop {
+      let sector = MifareKeys::sector(addr);
+
+      packet.clear();
+      match mode {
+        WriteSecMode::Format => {
+          packet.extend(self.mifare_keys.key_a(uuid, sector));
+          packet.extend(MIFARE_DEFAULT_ACCESS_BITS);
+          packet.extend(self.mifare_keys.key_b(uuid, sector));
+        },
+        WriteSecMode::Restore => {
+          packet.extend(MIFARE_DEFAULT_KEY_A);
+          packet.extend(MIFARE_DEFAULT_ACCESS_BITS);
+          packet.extend(MIFARE_DEFAULT_KEY_B);
+        }
+      }
+
       match self.auth(PICC::AUTH1A.value(), addr, uuid, key) {
         Ok(_val) => {
           if let Err(err) = self.write_data(addr, &packet) {
@@ -692,27 +820,52 @@ impl MiFare for Mfrc522ThreadSafe {
 }
 
 pub struct Mfrc522 {
+  device: String,
+  ss_pin: u64,
+  irq_pin: Option<u64>,
+  irq: Option<IrqLine>,
+  card_hold_off: Duration,
   mfrc522: Arc<Mutex<Mfrc522ThreadSafe>>
 }
 
 impl Mfrc522 {
-  pub fn new() -> Self {
-    return Mfrc522 {mfrc522: Arc::new(Mutex::new(Mfrc522ThreadSafe
+  pub fn new(params: &HashMap<String, String>) -> Self {
+    let device = params.get("device").cloned().unwrap_or(String::from(MFRC522_DEFAULT_SPI_DEVICE));
+    let ss_pin = params.get("ss_pin").and_then(|pin| pin.parse::<u64>().ok()).unwrap_or(MFRC522_DEFAULT_SS_PIN);
+
+    return Mfrc522 {device: device, ss_pin: ss_pin, irq_pin: irq::irq_pin_param(params), irq: None, card_hold_off: presence::hold_off_param(params), mfrc522: Arc::new(Mutex::new(Mfrc522ThreadSafe
       {
         spidev: None,
         ss: None,
-        mifare_key_a: vec![0xff,0xff,0xff,0xff,0xff,0xff],
-        mifare_key_b: vec![0x00,0x00,0x00,0x00,0x00,0x00],
-        mifare_access_bits: vec![0xff,0x07,0x80,0x69]
+        mifare_keys: MifareKeys::fixed(MIFARE_DEFAULT_KEY_A, MIFARE_DEFAULT_KEY_B),
+        mifare_access_bits: vec![0xff,0x07,0x80,0x69],
+        card_type: CardType::Mifare,
+        ntag: None,
+        ntag_originality: ntag::originality_param(params)
       }
     ))};
   }
+
+  /* Probes for a card until one answers, with the field off in between. The
+   * lock is only held for the probe itself. */
+  fn wait_for_card(mfrc522: &Arc<Mutex<Mfrc522ThreadSafe>>, poller: &mut PinPoller) -> Result<(), std::io::Error> {
+    loop {
+      mfrc522.lock().unwrap().arm_card_irq()?;
+      poller.poll(MFRC522_IRQ_WAIT_MS)?;
+
+      if mfrc522.lock().unwrap().card_irq()? {
+        return Ok(());
+      }
+
+      thread::sleep(MFRC522_IRQ_PROBE_INTERVAL);
+    }
+  }
 }
 
 impl NfcReader for Mfrc522 {
   fn init(&mut self) -> Result<(), String> {
     let mfrc522 = self.mfrc522.clone();
-    mfrc522.lock().unwrap().spidev = match Spidev::open("/dev/spidev0.0") {
+    mfrc522.lock().unwrap().spidev = match Spidev::open(&self.device) {
       Ok(mut spidev) => {
         let options = SpidevOptions::new()
           .bits_per_word(8)
@@ -726,10 +879,10 @@ impl NfcReader for Mfrc522 {
 
         Some(spidev)
       },
-      Err(err) => return Err(format!("{} - {}", String::from("Error initializing spi port"), err)),
+      Err(err) => return Err(format!("{} {} - {}", String::from("Error initializing spi port"), self.device, err)),
     };
 
-    let pin = Pin::new(17);
+    let pin = Pin::new(self.ss_pin);
     if let Err(err) = pin.export() {
       return Err(format!("{}: {}","Error initializing gpio port",err));
     }
@@ -765,17 +918,37 @@ impl NfcReader for Mfrc522 {
       acontrol_system_log!(LogType::Info, "NFC device initialized successfully");
     }
 
+    if let Some(irq_pin) = self.irq_pin {
+      self.irq = Some(IrqLine::open(irq_pin)?);
+      acontrol_system_log!(LogType::Info, "NFC card detection on irq gpio {}", irq_pin);
+    }
+
     Ok(())
   }
 
-  fn find_tag(&mut self, func: fn(CardType, Vec<u8>) -> bool) -> Result<(),String> {
+  fn find_tag(&mut self, reader: &str, func: fn(&str, TagEvent) -> bool) -> Result<(),String> {
+    let reader = String::from(reader);
+    let mut presence = CardPresence::new(self.card_hold_off);
     let mfrc522 = self.mfrc522.clone();
+    let mut poller = match self.irq {
+      Some(ref irq) => Some(irq.poller()?),
+      None => None
+    };
 
     let _handler = thread::spawn(move || {
       loop {
-        let ret: Result<(), String>;
-        let mut uuid:Vec<u8> = Vec::new();
-        let mut complete:bool;
+        let mut card:Option<CardInfo> = None;
+
+        /* A card already on the reader is still polled to notice its removal. */
+        if let Some(ref mut poller) = poller {
+          if !presence.is_present() {
+            if let Err(err) = Mfrc522::wait_for_card(&mfrc522, poller) {
+              acontrol_system_log!(LogType::Error, "Card detection error: {}", err);
+              thread::sleep(Duration::from_millis(500));
+              continue;
+            }
+          }
+        }
 
         {
           let mut mfrc522_inner = mfrc522.lock().unwrap();
@@ -785,73 +958,48 @@ impl NfcReader for Mfrc522 {
             break;
           }
 
-          //println!("Searching tag...");
-          ret = match mfrc522_inner.send_req_a() {
-            Ok(val) => {
-              acontrol_system_log!(LogType::Debug, "Card Answer {:?}", val);
-              let ret: Result<(), String> = match mfrc522_inner.anticoll(PICC::ANTICOLL1.value(), &Vec::new()){
-                Ok(val) => {
-                  acontrol_system_log!(LogType::Debug, "ANTICOLL CASCADE 1 value: {:?}", val);
-                  if val[0] == 0x88 { complete = false; uuid.extend_from_slice(&val[1..val.len()-1]); } else { complete = true; uuid.extend_from_slice(&val[..val.len()-1]); }
-                  let ret:Result<(), String> = match mfrc522_inner.select(PICC::ANTICOLL1.value(),&uuid) {
-                    Ok(val) => {
-                      let ret1: Result<(), String>;
-                      acontrol_system_log!(LogType::Debug, "SELECT CASCADE 1 answer: {:?}",val);
-                      if complete == false {
-                        ret1 = match mfrc522_inner.anticoll(PICC::ANTICOLL2.value(), &uuid){
-                          Ok(val) => {
-                            acontrol_system_log!(LogType::Debug, "ANTICOLL CASCADE 2 value: {:?}", val);
-                            if val[0] == 0x88 { complete = false; uuid.extend_from_slice(&val[1..val.len()-1]); } else { complete = true; uuid.extend_from_slice(&val[..val.len()-1]); }
-                            let ret: Result<(), String> = match mfrc522_inner.select(PICC::ANTICOLL2.value(),&uuid) {
-                              Ok(val) => {
-                                let ret2:Result<(),String>;
-                                acontrol_system_log!(LogType::Debug, "SELECT CASCADE 2 answer: {:?}",val);
-                                if complete == false {
-                                  ret2 = Err(format!("{}","Tripple byte card not implemented yet!"));
-                                } else {
-                                  ret2 = Ok(());
-                                }
-                                ret2
-                              },
-                              Err(ref mut err) => Err(format!("SELECT CASCADE 2 => {}", err))
-                            };
-                            ret
-                          },
-                          Err(ref mut err) => Err(format!("ANTICOLL 2 => {}", err))
-                        };
-                      } else {
-                        ret1 = Ok(());
-                      }
-                      ret1
-                    },
-                    Err(ref mut err) => Err(format!("SELECT CASCADE 1 => {}", err))
-                  };
-                  ret
-                },
-                Err(ref mut err) => Err(format!("ANTICOLL 1 => {}", err))
-              };
-              ret
-            },
-            Err(ref mut err) => Err(format!("REQA => {}", err))
+          mfrc522_inner.card_type = CardType::Mifare;
+          mfrc522_inner.ntag = None;
+
+          if let Ok(mut info) = mfrc522_inner.activate() {
+            acontrol_system_log!(LogType::Debug, "Card selected: {}", info);
+
+            /* SAK 0x00 is a Type 2 tag (Ultralight / NTAG), not a Classic card. */
+            let identified = if info.sak == 0x00 { mfrc522_inner.ntag_identify(&info.uid) } else { Ok(()) };
+
+            match identified {
+              Ok(_) => {
+                info.technology = mfrc522_inner.card_type;
+                card = Some(info);
+              },
+              Err(err) => acontrol_system_log!(LogType::Error, "Rejecting tag {:X?}: {}", info.uid, err)
+            }
           }
         };
 
-        if let Ok(_val) = ret {
-          func(CardType::Mifare, uuid);
+        /* The probe also fires for a tag we rejected or could not select,
+         * which stays in the field: back off instead of spinning on it. */
+        let missed = card.is_none();
+
+        for event in presence.update(card) {
+          func(&reader, event);
         }
 
-        thread::sleep(Duration::from_millis(500));
+        if poller.is_none() || presence.is_present() {
+          thread::sleep(Duration::from_millis(500));
+        } else if missed {
+          thread::sleep(MFRC522_IRQ_PROBE_INTERVAL);
+        }
       }
     });
     Ok(())
   }
 
-  fn set_auth_keys(&mut self, key_a: &Vec<u8>, key_b: &Vec<u8>) -> Result<(), String> {
+  fn set_auth_keys(&mut self, keys: MifareKeys) -> Result<(), String> {
     let mfrc522 = self.mfrc522.clone();
     let mut mfrc522_inner = mfrc522.lock().unwrap();
 
-    mfrc522_inner.mifare_key_a = key_a.to_vec();
-    mfrc522_inner.mifare_key_b = key_b.to_vec();
+    mfrc522_inner.mifare_keys = keys;
 
     Ok(())
   }
@@ -864,6 +1012,11 @@ impl NfcReader for Mfrc522 {
     let mfrc522 = self.mfrc522.clone();
     let mut mfrc522_inner = mfrc522.lock().unwrap();
 
+    if let Some(model) = mfrc522_inner.ntag {
+      let (pwd, pack) = mfrc522_inner.mifare_keys.ntag_protect_password(uuid)?;
+      return Ntag::new(&mut *mfrc522_inner).protect(&model, &pwd, &pack);
+    }
+
     mfrc522_inner.write_sec(uuid, WriteSecMode::Format)
   }
 
@@ -871,6 +1024,11 @@ impl NfcReader for Mfrc522 {
     let mfrc522 = self.mfrc522.clone();
     let mut mfrc522_inner = mfrc522.lock().unwrap();
 
+    if let Some(model) = mfrc522_inner.ntag {
+      let (pwd, pack) = mfrc522_inner.mifare_keys.ntag_password(uuid);
+      return Ntag::new(&mut *mfrc522_inner).unprotect(&model, &pwd, &pack);
+    }
+
     mfrc522_inner.write_sec(uuid, WriteSecMode::Restore)
   }
 
@@ -878,12 +1036,17 @@ impl NfcReader for Mfrc522 {
     let mfrc522 = self.mfrc522.clone();
     let mut mfrc522_inner = mfrc522.lock().unwrap();
 
+    if let Some(model) = mfrc522_inner.ntag {
+      let (pwd, pack) = mfrc522_inner.mifare_keys.ntag_password(uuid);
+      return Ntag::new(&mut *mfrc522_inner).read_blocks(&model, &pwd, &pack, addr, blocks + 1);
+    }
+
     let mut cur_addr:u8 = addr;
     let mut buffer: Vec<u8> = Vec::new();
 
     loop {
 
-      if cur_addr+1 % 4 == 0 { cur_addr += 1; }
+      if MifareKeys::is_trailer(cur_addr) { cur_addr += 1; }
 
       match mfrc522_inner.auth(PICC::AUTH1A.value(), cur_addr, uuid, MifareAuthKey::CustomKeyA) {
         Ok(_val) => {
@@ -910,6 +1073,11 @@ impl NfcReader for Mfrc522 {
     let mfrc522 = self.mfrc522.clone();
     let mut mfrc522_inner = mfrc522.lock().unwrap();
 
+    if let Some(model) = mfrc522_inner.ntag {
+      let (pwd, pack) = mfrc522_inner.mifare_keys.ntag_password(uuid);
+      return Ntag::new(&mut *mfrc522_inner).write_blocks(&model, &pwd, &pack, addr, data);
+    }
+
     let mut cur_addr:u8 = addr;
     let mut buffer:VecDeque<u8> = VecDeque::new();
     let mut packet:Vec<u8> = Vec::new();
@@ -918,7 +1086,7 @@ impl NfcReader for Mfrc522 {
 
     loop {
 
-      if cur_addr+1 % 4 == 0 { cur_addr += 1; }
+      if MifareKeys::is_trailer(cur_addr) { cur_addr += 1; }
 
       match mfrc522_inner.auth(PICC::AUTH1A.value(), cur_addr, uuid, MifareAuthKey::CustomKeyA) {
         Ok(_val) => {
@@ -955,6 +1123,11 @@ impl NfcReader for Mfrc522 {
     if let Err(err) = pin.unexport() {
       return Err(format!("{}(=>{})", "NFC driver error",err));
     }
+    if let Some(ref irq) = self.irq {
+      if let Err(err) = irq.close() {
+        return Err(format!("{}(=>{})", "NFC driver error",err));
+      }
+    }
     Ok(())
   }
 
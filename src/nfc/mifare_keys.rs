@@ -0,0 +1,195 @@
+/**
+ * @file   mifare_keys.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  MIFARE Classic sector keys
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+const MIFARE_KEY_LEN: usize = 6;
+const MIFARE_MASTER_KEY_MIN_LEN: usize = 16;
+const MIFARE_DIVERSIFY_LABEL: &[u8] = b"acontrol-mifare-v1";
+const NTAG_DIVERSIFY_LABEL: &[u8] = b"acontrol-ntag-v1";
+const NTAG_PWD_LEN: usize = 4;
+const NTAG_PACK_LEN: usize = 2;
+
+/* Keys used to protect the sectors of MIFARE Classic cards.
+ *
+ * With a master key every card, and every sector of it, gets its own
+ * key A and key B derived from the card UID, so reading the keys out
+ * of one card tells nothing about the others. Without it the same
+ * fixed pair is used everywhere, which is how cards used to be written. */
+#[derive(Clone)]
+pub struct MifareKeys {
+  master: Option<Vec<u8>>,
+  key_a: Vec<u8>,
+  key_b: Vec<u8>,
+}
+
+impl MifareKeys {
+  pub fn fixed(key_a: &[u8], key_b: &[u8]) -> MifareKeys {
+    MifareKeys { master: None, key_a: key_a.to_vec(), key_b: key_b.to_vec() }
+  }
+
+  pub fn diversified(master: &[u8]) -> Result<MifareKeys, String> {
+    if master.len() < MIFARE_MASTER_KEY_MIN_LEN {
+      return Err(format!("Mifare master key must have at least {} bytes", MIFARE_MASTER_KEY_MIN_LEN));
+    }
+    Ok(MifareKeys { master: Some(master.to_vec()), key_a: Vec::new(), key_b: Vec::new() })
+  }
+
+  pub fn is_diversified(&self) -> bool {
+    self.master.is_some()
+  }
+
+  /* Sector holding block `addr`: 4 blocks per sector up to block 127,
+   * 16 blocks per sector above it (MIFARE Classic 4K). */
+  pub fn sector(addr: u8) -> u8 {
+    if addr < 128 { addr / 4 } else { 32 + (addr - 128) / 16 }
+  }
+
+  /* Whether block `addr` is a sector trailer (keys and access bits). */
+  pub fn is_trailer(addr: u8) -> bool {
+    if addr < 128 { addr % 4 == 3 } else { (addr - 128) % 16 == 15 }
+  }
+
+  fn derive(master: &[u8], label: &[u8], uid: &[u8], sector: u8) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(master).unwrap();
+    mac.update(label);
+    mac.update(&[uid.len() as u8]);
+    mac.update(uid);
+    mac.update(&[sector]);
+    mac.finalize().into_bytes()[..MIFARE_KEY_LEN * 2].to_vec()
+  }
+
+  pub fn key_a(&self, uid: &[u8], sector: u8) -> Vec<u8> {
+    match self.master {
+      Some(ref master) => MifareKeys::derive(master, MIFARE_DIVERSIFY_LABEL, uid, sector)[..MIFARE_KEY_LEN].to_vec(),
+      None => self.key_a.clone()
+    }
+  }
+
+  pub fn key_b(&self, uid: &[u8], sector: u8) -> Vec<u8> {
+    match self.master {
+      Some(ref master) => MifareKeys::derive(master, MIFARE_DIVERSIFY_LABEL, uid, sector)[MIFARE_KEY_LEN..].to_vec(),
+      None => self.key_b.clone()
+    }
+  }
+
+  /* 32 bit password and 16 bit password acknowledge of an NTAG21x /
+   * Ultralight EV1 tag. Derived from the UID with a master key, taken
+   * from the start of key A otherwise. */
+  pub fn ntag_password(&self, uid: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let material = match self.master {
+      Some(ref master) => MifareKeys::derive(master, NTAG_DIVERSIFY_LABEL, uid, 0),
+      None => self.key_a.clone()
+    };
+    (material[..NTAG_PWD_LEN].to_vec(), material[NTAG_PWD_LEN..NTAG_PWD_LEN + NTAG_PACK_LEN].to_vec())
+  }
+
+  /* Password to protect a tag with. A fixed key would put the same password
+   * on every tag, readable from any of them, so a master key is required. */
+  pub fn ntag_protect_password(&self, uid: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+    if self.master.is_none() {
+      return Err(String::from("NTAG password protection requires an nfc master_key"));
+    }
+    Ok(self.ntag_password(uid))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const MASTER: &[u8] = b"0123456789abcdef";
+  const UID: &[u8] = &[0x04, 0x11, 0x22, 0x33];
+  const OTHER_UID: &[u8] = &[0x04, 0x11, 0x22, 0x34];
+
+  #[test]
+  fn keys_differ_per_card_and_sector() {
+    let keys = MifareKeys::diversified(MASTER).unwrap();
+
+    assert!(keys.is_diversified());
+    assert_eq!(keys.key_a(UID, 1).len(), MIFARE_KEY_LEN);
+    assert_eq!(keys.key_a(UID, 1), keys.key_a(UID, 1));
+    assert_ne!(keys.key_a(UID, 1), keys.key_a(OTHER_UID, 1));
+    assert_ne!(keys.key_a(UID, 1), keys.key_a(UID, 2));
+    assert_ne!(keys.key_b(UID, 1), keys.key_b(OTHER_UID, 1));
+    assert_ne!(keys.key_b(UID, 1), keys.key_b(UID, 2));
+  }
+
+  #[test]
+  fn key_a_and_key_b_differ() {
+    let keys = MifareKeys::diversified(MASTER).unwrap();
+
+    assert_eq!(keys.key_b(UID, 1).len(), MIFARE_KEY_LEN);
+    assert_ne!(keys.key_a(UID, 1), keys.key_b(UID, 1));
+  }
+
+  #[test]
+  fn fixed_keys_ignore_uid() {
+    let keys = MifareKeys::fixed(&[0xff; 6], &[0x00; 6]);
+
+    assert!(!keys.is_diversified());
+    assert_eq!(keys.key_a(UID, 1), keys.key_a(OTHER_UID, 2));
+    assert_eq!(keys.key_b(UID, 1), vec![0x00; 6]);
+  }
+
+  #[test]
+  fn sector_boundaries() {
+    assert_eq!(MifareKeys::sector(3), 0);
+    assert_eq!(MifareKeys::sector(4), 1);
+    assert_eq!(MifareKeys::sector(127), 31);
+    assert_eq!(MifareKeys::sector(128), 32);
+    assert_eq!(MifareKeys::sector(143), 32);
+    assert_eq!(MifareKeys::sector(144), 33);
+    assert_eq!(MifareKeys::sector(255), 39);
+
+    assert!(MifareKeys::is_trailer(3));
+    assert!(!MifareKeys::is_trailer(4));
+    assert!(MifareKeys::is_trailer(127));
+    assert!(!MifareKeys::is_trailer(128));
+    assert!(!MifareKeys::is_trailer(131));
+    assert!(MifareKeys::is_trailer(143));
+    assert!(MifareKeys::is_trailer(255));
+  }
+
+  #[test]
+  fn short_master_key_is_rejected() {
+    assert!(MifareKeys::diversified(&MASTER[..MIFARE_MASTER_KEY_MIN_LEN - 1]).is_err());
+  }
+
+  #[test]
+  fn ntag_protection_requires_master_key() {
+    assert!(MifareKeys::fixed(&[0xff; 6], &[0xff; 6]).ntag_protect_password(UID).is_err());
+
+    let keys = MifareKeys::diversified(MASTER).unwrap();
+    let (pwd, pack) = keys.ntag_protect_password(UID).unwrap();
+    assert_eq!(pwd.len(), NTAG_PWD_LEN);
+    assert_eq!(pack.len(), NTAG_PACK_LEN);
+    assert_ne!(pwd, keys.ntag_protect_password(OTHER_UID).unwrap().0);
+  }
+}
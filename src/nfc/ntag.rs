@@ -0,0 +1,447 @@
+/**
+ * @file   ntag.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  NTAG21x / MIFARE Ultralight EV1 protocol
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use crate::nfc::CardType;
+
+use std::collections::HashMap;
+
+use num_bigint::BigUint;
+
+const CMD_GET_VERSION: u8 = 0x60;
+const CMD_READ: u8 = 0x30;
+const CMD_WRITE: u8 = 0xA2;
+const CMD_PWD_AUTH: u8 = 0x1B;
+const CMD_READ_SIG: u8 = 0x3C;
+
+const ACK: u8 = 0x0A;
+
+const PAGE_SIZE: usize = 4;
+const PAGES_PER_BLOCK: u8 = 4;
+const FIRST_USER_PAGE: u8 = 4;
+
+const VERSION_LEN: usize = 8;
+const SIGNATURE_LEN: usize = 32;
+
+/* CFG1 ACCESS bit: the password protects reads as well as writes. */
+const ACCESS_PROT: u8 = 0x80;
+const AUTH0_DISABLED: u8 = 0xFF;
+const DEFAULT_PWD: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const DEFAULT_PACK: [u8; 2] = [0x00, 0x00];
+
+/* secp128r1 domain parameters used by the NXP originality signature. */
+const SECP128R1_P: &[u8] = b"FFFFFFFDFFFFFFFFFFFFFFFFFFFFFFFF";
+const SECP128R1_A: &[u8] = b"FFFFFFFDFFFFFFFFFFFFFFFFFFFFFFFC";
+const SECP128R1_N: &[u8] = b"FFFFFFFE0000000075A30D1B9038A115";
+const SECP128R1_GX: &[u8] = b"161FF7528B899B2D0C28607CA52C5B86";
+const SECP128R1_GY: &[u8] = b"CF5AC8395BAFEB13C02DA292DDED7A83";
+
+/* NXP originality public keys (uncompressed points). */
+const NXP_NTAG21X_PUBLIC_KEY: &[u8] = b"494E1A386D3D3CFE3DC10E5DE68A499B1C202DB5B132393E89ED19FE5BE8BC61";
+const NXP_ULTRALIGHT_EV1_PUBLIC_KEY: &[u8] = b"90933BDCD6E99B4E255E3DA55389A827564E11718E017292FAF23226A96614B8";
+
+/* A raw ISO 14443-3A link. Frames go out without CRC, which the link
+ * adds, and answers come back without it. */
+pub trait NfcA {
+  fn transceive_frame(&mut self, frame: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+/* Memory layout of an identified NTAG21x / Ultralight EV1 tag. */
+#[derive(Debug, Clone, Copy)]
+pub struct NtagModel {
+  pub card_type: CardType,
+  last_user_page: u8,
+  cfg_page: u8,
+}
+
+impl NtagModel {
+  /* GET_VERSION: header, vendor, type, subtype, major, minor, storage size, protocol. */
+  fn from_version(version: &[u8]) -> Option<NtagModel> {
+    if version.len() < VERSION_LEN || version[1] != 0x04 {
+      return None;
+    }
+
+    let (card_type, last_user_page, cfg_page) = match (version[2], version[6]) {
+      (0x04, 0x0F) => (CardType::Ntag213, 0x27, 0x29),
+      (0x04, 0x11) => (CardType::Ntag215, 0x81, 0x83),
+      (0x04, 0x13) => (CardType::Ntag216, 0xE1, 0xE3),
+      (0x03, 0x0B) => (CardType::Ultralight, 0x0F, 0x10),
+      (0x03, 0x0E) => (CardType::Ultralight, 0x23, 0x25),
+      _ => return None
+    };
+
+    Some(NtagModel { card_type: card_type, last_user_page: last_user_page, cfg_page: cfg_page })
+  }
+
+  fn public_key(&self) -> &'static [u8] {
+    match self.card_type {
+      CardType::Ultralight => NXP_ULTRALIGHT_EV1_PUBLIC_KEY,
+      _ => NXP_NTAG21X_PUBLIC_KEY
+    }
+  }
+
+  /* Blocks are 16 bytes, like MIFARE Classic ones, and block 1 starts at
+   * the first user page so the card payload lands on user memory. */
+  fn block_pages(&self, addr: u8, blocks: u8) -> Result<(u8, u8), String> {
+    let first = addr as u32 * PAGES_PER_BLOCK as u32;
+    let last = first + blocks as u32 * PAGES_PER_BLOCK as u32 - 1;
+
+    if first < FIRST_USER_PAGE as u32 || last > self.last_user_page as u32 {
+      return Err(format!("Blocks {}..{} out of the user memory of this tag", addr, addr as u32 + blocks as u32));
+    }
+
+    Ok((first as u8, blocks))
+  }
+}
+
+/* Whether the drivers should reject tags without a valid NXP
+ * originality signature (`ntag_originality`, on by default). */
+pub fn originality_param(params: &HashMap<String, String>) -> bool {
+  params.get("ntag_originality").map(|value| value != "false").unwrap_or(true)
+}
+
+/* An NTAG21x / Ultralight EV1 tag reached through an NFC-A link. */
+pub struct Ntag<'a, T: NfcA> {
+  link: &'a mut T,
+}
+
+impl<'a, T: NfcA> Ntag<'a, T> {
+  pub fn new(link: &'a mut T) -> Ntag<'a, T> {
+    Ntag { link: link }
+  }
+
+  fn check_ack(answer: &[u8]) -> Result<(), String> {
+    match answer.first() {
+      None => Ok(()),
+      Some(ack) if ack & 0x0F == ACK => Ok(()),
+      Some(nak) => Err(format!("Tag NAK (0x{:X})", nak))
+    }
+  }
+
+  pub fn get_version(&mut self) -> Result<Vec<u8>, String> {
+    let answer = self.link.transceive_frame(&[CMD_GET_VERSION])?;
+    if answer.len() < VERSION_LEN {
+      return Err(String::from("Invalid GET_VERSION answer"));
+    }
+    Ok(answer[..VERSION_LEN].to_vec())
+  }
+
+  /* Reads 4 pages (16 bytes) starting at `page`. */
+  pub fn read(&mut self, page: u8) -> Result<Vec<u8>, String> {
+    let answer = self.link.transceive_frame(&[CMD_READ, page])?;
+    if answer.len() < PAGE_SIZE * 4 {
+      Ntag::<T>::check_ack(&answer)?;
+      return Err(format!("Invalid READ answer at page {}", page));
+    }
+    Ok(answer[..PAGE_SIZE * 4].to_vec())
+  }
+
+  /* Writes one page (4 bytes). */
+  pub fn write(&mut self, page: u8, data: &[u8]) -> Result<(), String> {
+    if data.len() != PAGE_SIZE {
+      return Err(String::from("write error: Invalid page size"));
+    }
+
+    let mut frame = vec![CMD_WRITE, page];
+    frame.extend_from_slice(data);
+
+    let answer = self.link.transceive_frame(&frame)?;
+    Ntag::<T>::check_ack(&answer).map_err(|err| format!("Error writing page {}: {}", page, err))
+  }
+
+  pub fn pwd_auth(&mut self, pwd: &[u8], pack: &[u8]) -> Result<(), String> {
+    let mut frame = vec![CMD_PWD_AUTH];
+    frame.extend_from_slice(pwd);
+
+    let answer = self.link.transceive_frame(&frame)?;
+    if answer.len() < 2 {
+      Ntag::<T>::check_ack(&answer)?;
+      return Err(String::from("Invalid PWD_AUTH answer"));
+    }
+
+    if &answer[..2] != pack {
+      return Err(String::from("Tag password acknowledge mismatch"));
+    }
+
+    Ok(())
+  }
+
+  pub fn read_sig(&mut self) -> Result<Vec<u8>, String> {
+    let answer = self.link.transceive_frame(&[CMD_READ_SIG, 0x00])?;
+    if answer.len() < SIGNATURE_LEN {
+      return Err(String::from("Invalid READ_SIG answer"));
+    }
+    Ok(answer[..SIGNATURE_LEN].to_vec())
+  }
+
+  /* Identifies the tag and, when asked to, checks the NXP originality
+   * signature of its UID. */
+  pub fn identify(&mut self, uid: &[u8], check_originality: bool) -> Result<NtagModel, String> {
+    let version = self.get_version()?;
+
+    let model = match NtagModel::from_version(&version) {
+      Some(model) => model,
+      None => return Err(format!("Unsupported Type 2 tag (version {:X?})", version))
+    };
+
+    if check_originality {
+      let signature = self.read_sig()?;
+      if !originality_valid(model.public_key(), uid, &signature) {
+        return Err(String::from("Tag failed the originality signature check"));
+      }
+    }
+
+    Ok(model)
+  }
+
+  pub fn read_blocks(&mut self, model: &NtagModel, pwd: &[u8], pack: &[u8], addr: u8, blocks: u8) -> Result<Vec<u8>, String> {
+    let (page, blocks) = model.block_pages(addr, blocks)?;
+
+    self.pwd_auth(pwd, pack)?;
+
+    let mut buffer: Vec<u8> = Vec::new();
+    for i in 0..blocks {
+      buffer.extend(self.read(page + i * PAGES_PER_BLOCK)?);
+    }
+
+    Ok(buffer)
+  }
+
+  pub fn write_blocks(&mut self, model: &NtagModel, pwd: &[u8], pack: &[u8], addr: u8, data: &[u8]) -> Result<u8, String> {
+    let blocks = ((data.len() + 15) / 16) as u8;
+    let (page, _) = model.block_pages(addr, blocks)?;
+
+    self.pwd_auth(pwd, pack)?;
+
+    let mut packet = data.to_vec();
+    packet.resize(blocks as usize * 16, 0);
+
+    for (i, chunk) in packet.chunks(PAGE_SIZE).enumerate() {
+      self.write(page + i as u8, chunk)?;
+    }
+
+    Ok(blocks)
+  }
+
+  /* Sets the tag password and protects reads and writes from the first
+   * user page on. The tag must not be protected yet. */
+  pub fn protect(&mut self, model: &NtagModel, pwd: &[u8], pack: &[u8]) -> Result<(), String> {
+    let cfg = match self.read(model.cfg_page) {
+      Ok(cfg) => cfg,
+      Err(_) => return Err(String::from("Tag is already password protected, restore it first"))
+    };
+
+    self.write_cfg(model, &cfg, pwd, pack, FIRST_USER_PAGE, cfg[4] | ACCESS_PROT)
+  }
+
+  /* Removes the password protection set by `protect`. */
+  pub fn unprotect(&mut self, model: &NtagModel, pwd: &[u8], pack: &[u8]) -> Result<(), String> {
+    self.pwd_auth(pwd, pack)?;
+
+    let cfg = self.read(model.cfg_page)?;
+
+    self.write_cfg(model, &cfg, &DEFAULT_PWD, &DEFAULT_PACK, AUTH0_DISABLED, cfg[4] & !ACCESS_PROT)
+  }
+
+  /* PWD and PACK go first and AUTH0 last, so the protection only starts
+   * once the password is in place. */
+  fn write_cfg(&mut self, model: &NtagModel, cfg: &[u8], pwd: &[u8], pack: &[u8], auth0: u8, access: u8) -> Result<(), String> {
+    self.write(model.cfg_page + 2, pwd)?;
+    self.write(model.cfg_page + 3, &[pack[0], pack[1], 0x00, 0x00])?;
+    self.write(model.cfg_page + 1, &[access, cfg[5], cfg[6], cfg[7]])?;
+    self.write(model.cfg_page, &[cfg[0], cfg[1], cfg[2], auth0])
+  }
+}
+
+#[derive(Clone)]
+struct Point {
+  x: BigUint,
+  y: BigUint,
+}
+
+struct Curve {
+  p: BigUint,
+  a: BigUint,
+  n: BigUint,
+}
+
+impl Curve {
+  fn secp128r1() -> Curve {
+    Curve { p: hex_int(SECP128R1_P), a: hex_int(SECP128R1_A), n: hex_int(SECP128R1_N) }
+  }
+
+  fn inverse(value: &BigUint, modulus: &BigUint) -> BigUint {
+    value.modpow(&(modulus - 2u32), modulus)
+  }
+
+  fn sub(&self, a: &BigUint, b: &BigUint) -> BigUint {
+    ((a + &self.p) - (b % &self.p)) % &self.p
+  }
+
+  fn add(&self, p1: &Option<Point>, p2: &Option<Point>) -> Option<Point> {
+    let (p1, p2) = match (p1, p2) {
+      (None, _) => return p2.clone(),
+      (_, None) => return p1.clone(),
+      (Some(p1), Some(p2)) => (p1, p2)
+    };
+
+    let lambda = if p1.x == p2.x {
+      if (&p1.y + &p2.y) % &self.p == BigUint::from(0u32) {
+        return None;
+      }
+      let num = (BigUint::from(3u32) * &p1.x * &p1.x + &self.a) % &self.p;
+      num * Curve::inverse(&((BigUint::from(2u32) * &p1.y) % &self.p), &self.p) % &self.p
+    } else {
+      self.sub(&p2.y, &p1.y) * Curve::inverse(&self.sub(&p2.x, &p1.x), &self.p) % &self.p
+    };
+
+    let x = self.sub(&self.sub(&(&lambda * &lambda), &p1.x), &p2.x);
+    let y = self.sub(&(&lambda * self.sub(&p1.x, &x)), &p1.y);
+
+    Some(Point { x: x, y: y })
+  }
+
+  fn mul(&self, k: &BigUint, point: &Point) -> Option<Point> {
+    let mut result: Option<Point> = None;
+    let base = Some(point.clone());
+
+    for i in (0..k.bits()).rev() {
+      result = self.add(&result, &result);
+      if k.bit(i) {
+        result = self.add(&result, &base);
+      }
+    }
+
+    result
+  }
+}
+
+fn hex_int(value: &[u8]) -> BigUint {
+  BigUint::parse_bytes(value, 16).unwrap()
+}
+
+/* ECDSA (secp128r1, no hashing) check of the signature NXP burns over
+ * the tag UID at manufacturing time. */
+fn originality_valid(public_key: &[u8], uid: &[u8], signature: &[u8]) -> bool {
+  if signature.len() != SIGNATURE_LEN {
+    return false;
+  }
+
+  let curve = Curve::secp128r1();
+  let zero = BigUint::from(0u32);
+
+  let r = BigUint::from_bytes_be(&signature[..16]);
+  let s = BigUint::from_bytes_be(&signature[16..]);
+  if r == zero || s == zero || r >= curve.n || s >= curve.n {
+    return false;
+  }
+
+  let g = Point { x: hex_int(SECP128R1_GX), y: hex_int(SECP128R1_GY) };
+  let q = Point { x: hex_int(&public_key[..32]), y: hex_int(&public_key[32..]) };
+
+  let e = BigUint::from_bytes_be(uid) % &curve.n;
+  let w = Curve::inverse(&s, &curve.n);
+  let u1 = (e * &w) % &curve.n;
+  let u2 = (&r * &w) % &curve.n;
+
+  match curve.add(&curve.mul(&u1, &g), &curve.mul(&u2, &q)) {
+    Some(point) => point.x % &curve.n == r,
+    None => false
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SECP128R1_B: &[u8] = b"E87579C11079F43DD824993C2CEE5ED3";
+
+  /* Generated with OpenSSL: a secp128r1 key and `pkeyutl -sign` over the
+   * raw UID, the same no hash scheme NXP uses. */
+  const TEST_PUBLIC_KEY: &[u8] = b"156AF36BC2F83E4B1BDA119EE8A8DC1FF3773674601AA27717F7E1DE52CC0BFD";
+  const TEST_UID: [u8; 7] = [0x04, 0x51, 0x6A, 0x2A, 0xA3, 0x2B, 0x80];
+  const TEST_SIGNATURE: &[u8] = b"9C69EA104BAA985BF74DFBF57B51636B906B1172D799ED67B1B2572CBF4462A3";
+
+  fn signature() -> Vec<u8> {
+    hex::decode(TEST_SIGNATURE).unwrap()
+  }
+
+  fn on_curve(key: &[u8]) -> bool {
+    let curve = Curve::secp128r1();
+    let (x, y) = (hex_int(&key[..32]), hex_int(&key[32..]));
+    (&y * &y) % &curve.p == (&x * &x * &x + &curve.a * &x + hex_int(SECP128R1_B)) % &curve.p
+  }
+
+  #[test]
+  fn nxp_public_keys_are_on_the_curve() {
+    let mut generator = SECP128R1_GX.to_vec();
+    generator.extend(SECP128R1_GY);
+
+    assert!(on_curve(&generator));
+    assert!(on_curve(NXP_NTAG21X_PUBLIC_KEY));
+    assert!(on_curve(NXP_ULTRALIGHT_EV1_PUBLIC_KEY));
+  }
+
+  #[test]
+  fn generator_has_curve_order() {
+    let curve = Curve::secp128r1();
+    let g = Point { x: hex_int(SECP128R1_GX), y: hex_int(SECP128R1_GY) };
+
+    assert!(curve.mul(&curve.n, &g).is_none());
+    assert!(curve.mul(&(&curve.n - 1u32), &g).is_some());
+  }
+
+  #[test]
+  fn originality_accepts_valid_signature() {
+    assert!(originality_valid(TEST_PUBLIC_KEY, &TEST_UID, &signature()));
+  }
+
+  #[test]
+  fn originality_rejects_other_uid() {
+    let mut uid = TEST_UID;
+    uid[6] ^= 0x01;
+
+    assert!(!originality_valid(TEST_PUBLIC_KEY, &uid, &signature()));
+  }
+
+  #[test]
+  fn originality_rejects_tampered_signature() {
+    for i in [0, 15, 16, 31].iter() {
+      let mut signature = signature();
+      signature[*i] ^= 0x01;
+      assert!(!originality_valid(TEST_PUBLIC_KEY, &TEST_UID, &signature));
+    }
+
+    assert!(!originality_valid(TEST_PUBLIC_KEY, &TEST_UID, &[0u8; SIGNATURE_LEN]));
+    assert!(!originality_valid(TEST_PUBLIC_KEY, &TEST_UID, &signature()[..SIGNATURE_LEN - 1]));
+  }
+
+  #[test]
+  fn originality_rejects_other_key() {
+    assert!(!originality_valid(NXP_NTAG21X_PUBLIC_KEY, &TEST_UID, &signature()));
+  }
+}
@@ -0,0 +1,1245 @@
+/**
+ * @file   nfc/pn532.rs
+ * @author Otavio Ribeiro
+ * @date   24 Dec 2017
+ * @brief  NFC PN532 driver
+ *
+ * Copyright (c) 2022 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+use crate::nfc::{NfcReader, WriteSecMode, CardType, CardInfo, TagEvent, MifareKeys};
+use crate::nfc::desfire::{Desfire, DesfireConfig, IsoDep};
+use crate::nfc::ntag::{self, Ntag, NtagModel, NfcA};
+use crate::nfc::presence::{self, CardPresence};
+use crate::nfc::irq::{self, IrqLine};
+use crate::acontrol_system_log;
+use crate::log::LogType;
+
+use std::mem::transmute;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use std::thread;
+use std::time::{Duration,Instant};
+
+use sysfs_gpio::PinPoller;
+
+
+static MIFARE_DEFAULT_KEY_A:       &'static [u8] = &[0xff,0xff,0xff,0xff,0xff,0xff];
+static MIFARE_DEFAULT_KEY_B:       &'static [u8] = &[0x00,0x00,0x00,0x00,0x00,0x00];
+static MIFARE_DEFAULT_ACCESS_BITS: &'static [u8] = &[0xff,0x07,0x80,0x00];
+
+const DESFIRE_DEFAULT_AID: &str = "F0AC01";
+const DESFIRE_DEFAULT_KEY_NO: u8 = 1;
+const DESFIRE_DEFAULT_FILE: u8 = 1;
+
+//InAutoPoll period, in 150 ms units
+const PN532_DEFAULT_AUTO_POLL_PERIOD: u8 = 1;
+const PN532_IRQ_WAIT_MS: isize = 1000;
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+pub enum PICC {
+  REQIDL	= 0x26,
+  REQALL	= 0x52,
+  ANTICOLL1	= 0x93,
+  ANTICOLL2	= 0x95,
+  ANTICOLL3	= 0x97,
+  AUTH1A	= 0x60,
+  AUTH1B	= 0x61,
+  READ		= 0x30,
+  WRITE		= 0xA0,
+  DECREMENT	= 0xC0,
+  INCREMENT	= 0xC1,
+  RESTORE	= 0xC2,
+  TRANSFER	= 0xB0,
+  HALT		= 0x50
+}
+
+impl PICC {
+  fn value(&self) -> u8 {
+    return (*self) as u8;
+  }
+}
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+enum FrameDirection {
+    FromHost              = 0xd4,
+    FromRemote            = 0xd5,
+}
+
+#[allow(dead_code)]
+impl FrameDirection {
+  fn name(&self) -> &'static str {
+    match *self {
+      FrameDirection::FromHost =>              "FromHost",
+      FrameDirection::FromRemote =>            "FromRemote",
+    }
+  }
+
+  fn value(&self) -> u8 {
+    let value = *self as u8;
+    value
+  }
+}
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+enum FrameType {
+    Normal              = 0x01,
+    Extended            = 0x02,
+    Ack                 = 0x03,
+    NAck                = 0x04,
+    Error               = 0x05,
+    Unknown             = 0xff,
+}
+
+#[allow(dead_code)]
+impl FrameType {
+  fn name(&self) -> &'static str {
+    match *self {
+      FrameType::Normal =>              "Normal",
+      FrameType::Extended =>            "Extended",
+      FrameType::Ack =>                 "Ack",
+      FrameType::NAck =>                "NAck",
+      FrameType::Error =>               "Error",
+      FrameType::Unknown =>             "Unknown",
+    }
+  }
+
+  fn value(&self) -> u8 {
+    let value = *self as u8;
+    value
+  }
+
+  fn is_ack(&self) -> bool {
+      match *self {
+          FrameType::Ack => true,
+          _ => false,
+      }
+  }
+
+  fn is_error(&self) -> bool {
+      match *self {
+          FrameType::Error => true,
+          _ => false,
+      }
+  }
+}
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+enum Command {
+    Diagnose	             = 0x00,
+    GetFirmwareVersion	     = 0x02,
+    GetGeneralStatus	     = 0x04,
+    ReadRegister	         = 0x06,
+    WriteRegister	         = 0x08,
+    ReadGPIO	             = 0x0C,
+    WriteGPIO	             = 0x0E,
+    SetSerialBaudRate	     = 0x10,
+    SetParameters	         = 0x12,
+    SAMConfiguration	     = 0x14,
+    PowerDown	             = 0x16,
+    RFConfiguration	         = 0x32,
+    RFRegulationTest	     = 0x58,
+    InJumpForDEP	         = 0x56,
+    InJumpForPSL	         = 0x46,
+    InListPassiveTarget	     = 0x4A,
+    InATR	                 = 0x50,
+    InPSL	                 = 0x4E,
+    InDataExchange	         = 0x40,
+    InCommunicateThru	     = 0x42,
+    InDeselect	             = 0x44,
+    InRelease	             = 0x52,
+    InSelect	             = 0x54,
+    InAutoPoll	             = 0x60,
+    TgInitAsTarget	         = 0x8C,
+    TgSetGeneralBytes	     = 0x92,
+    TgGetData	             = 0x86,
+    TgSetData	             = 0x8E,
+    TgSetMetaData	         = 0x94,
+    TgGetInitiatorCommand	 = 0x88,
+    TgResponseToInitiator	 = 0x90,
+    TgGetTargetStatus	     = 0x8A,
+}
+
+#[allow(dead_code)]
+impl Command {
+  fn name(&self) -> &'static str {
+    match *self {
+      Command::Diagnose =>              "Diagnose",
+      Command::GetFirmwareVersion =>    "GetFirmwareVersion",
+      Command::GetGeneralStatus =>      "GetGeneralStatus",
+      Command::ReadRegister =>          "ReadRegister",
+      Command::WriteRegister =>         "WriteRegister",
+      Command::ReadGPIO =>              "ReadGPIO",
+      Command::WriteGPIO =>             "WriteGPIO",
+      Command::SetSerialBaudRate =>     "SetSerialBaudRate",
+      Command::SetParameters =>         "SetParameters",
+      Command::SAMConfiguration =>      "SAMConfiguration",
+      Command::PowerDown =>             "PowerDown",
+      Command::RFConfiguration =>       "RFConfiguration",
+      Command::RFRegulationTest =>      "RFRegulationTest",
+      Command::InJumpForDEP =>          "InJumpForDEP",
+      Command::InJumpForPSL =>          "InJumpForPSL",
+      Command::InListPassiveTarget =>   "InListPassiveTarget",
+      Command::InATR =>                 "InATR",
+      Command::InPSL =>                 "InPSL",
+      Command::InDataExchange =>        "InDataExchange",
+      Command::InCommunicateThru =>     "InCommunicateThru",
+      Command::InDeselect =>            "InDeselect",
+      Command::InRelease =>             "InRelease",
+      Command::InSelect =>              "InSelect",
+      Command::InAutoPoll =>            "InAutoPoll",
+      Command::TgInitAsTarget =>        "TgInitAsTarget",
+      Command::TgSetGeneralBytes =>     "TgSetGeneralBytes",
+      Command::TgGetData =>             "TgGetData",
+      Command::TgSetData =>             "TgSetData",
+      Command::TgSetMetaData =>         "TgSetMetaData",
+      Command::TgGetInitiatorCommand => "TgGetInitiatorCommand",
+      Command::TgResponseToInitiator => "TgResponseToInitiator",
+      Command::TgGetTargetStatus =>     "TgGetTargetStatus",
+    }
+  }
+
+  fn value(&self) -> u8 {
+    let value = *self as u8;
+    value
+  }
+
+  fn response(&self) -> u8 {
+      let value = *self as u8;
+      value+1
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+pub enum MifareAuthKey {
+  DefaultKeyA,
+  DefaultKeyB,
+  CustomKeyA,
+  CustomKeyB
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+#[repr(u8)]
+enum Error {
+  Success               = 0x00,
+  TimedOut              = 0x01,
+  Crc                   = 0x02,
+  Parity                = 0x03,
+  Select                = 0x04,
+  Framing               = 0x05,
+  BitCollision          = 0x06,
+  Buffer                = 0x07,
+  Overflow              = 0x09,
+  NotSwitched           = 0x0A,
+  RFProtocol            = 0x0B,
+  Temperature           = 0x0D,
+  BufferOverflow        = 0x0E,
+  InvalidParameter      = 0x10,
+  InvalidCommand        = 0x12,
+  InvalidFormat         = 0x13,
+  InvalidAuth           = 0x14,
+  UidCheck              = 0x23,
+  InvalidDeviceState    = 0x25,
+  OperationNotAllowed   = 0x26,
+  CommandOutOfContext   = 0x27,
+  Released              = 0x29,
+  InvalidUid            = 0x2A,
+  CardDisappeared       = 0x2B,
+  MismatchInitiator     = 0x2C,
+  OverCurrent           = 0x2D,
+  NadMissing            = 0x2E,
+  GenericError          = 0x99,
+}
+
+#[allow(dead_code)]
+impl Error {
+  fn name(&self) -> &str {
+    match *self {
+      Error::Success                => "Success",
+      Error::TimedOut               => "TimedOut",
+      Error::Crc                    => "Crc",
+      Error::Parity                 => "Parity",
+      Error::Select                 => "Select",
+      Error::Framing                => "Framing",
+      Error::BitCollision           => "BitCollision",
+      Error::Buffer                 => "Buffer",
+      Error::Overflow               => "Overflow",
+      Error::NotSwitched            => "NotSwitched",
+      Error::RFProtocol             => "RFProtocol",
+      Error::Temperature            => "Temperature",
+      Error::BufferOverflow         => "BufferOverflow",
+      Error::InvalidParameter       => "InvalidParameter",
+      Error::InvalidCommand         => "InvalidCommand",
+      Error::InvalidFormat          => "InvalidFormat",
+      Error::InvalidAuth            => "InvalidAuth",
+      Error::UidCheck               => "UidCheck",
+      Error::InvalidDeviceState     => "InvalidDeviceState",
+      Error::OperationNotAllowed    => "OperationNotAllowed",
+      Error::CommandOutOfContext    => "CommandOutOfContext",
+      Error::Released               => "Released",
+      Error::InvalidUid             => "InvalidUid",
+      Error::CardDisappeared        => "CardDisappeared",
+      Error::MismatchInitiator      => "MismatchInitiator",
+      Error::OverCurrent            => "OverCurrent",
+      Error::NadMissing             => "NadMissing",
+      Error::GenericError           => "GenericError",
+    }
+  }
+}
+
+impl From<u8> for Error {
+    fn from(t:u8) -> Error {
+        assert!(Error::Success as u8 <= t && t <= Error::GenericError as u8);
+        unsafe { transmute(t) }
+    }
+}
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+enum ResponseSize {
+    Ack,
+    Frame,
+}
+
+#[allow(dead_code)]
+impl ResponseSize {
+    fn name(&self) -> &'static str {
+        match *self {
+            ResponseSize::Ack =>      "Ack",
+            ResponseSize::Frame =>    "Frame",
+        }
+    }
+
+    fn size(&self, len: usize) -> usize {
+        match *self {
+            ResponseSize::Ack =>      0x07 + len,
+            ResponseSize::Frame =>    0x08 + len,
+        }
+    }
+}
+
+#[allow(dead_code)]
+struct Frame {
+    buffer: Vec<u8>
+}
+
+#[allow(dead_code)]
+impl Frame {
+    fn from_vec(data: &Vec<u8>) -> Result<Frame,std::io::Error>{
+        let len = data.len() as u8 + 1;
+
+        if len > 0xfe {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid buffer length"));
+        }
+
+        let lcs = !len + 1;
+
+        let mut dcs = FrameDirection::FromHost as u8;
+        for b in data {
+            dcs = dcs.wrapping_add(*b);
+        }
+
+        dcs = !dcs + 1;
+
+        let mut b = vec![
+            0x00, // preamble
+            0x00, 0xff,  // start
+        ];
+        b.push(len);
+        b.push(lcs);
+        b.push(FrameDirection::FromHost as u8); // direction
+        b.extend(data);
+        b.push(dcs);
+        b.push(0x00); // postamble
+
+        Ok(Frame {buffer: b})
+    }
+
+    fn from_buffer(data: &[u8]) -> Result<Frame,std::io::Error>{
+        Frame::from_vec(&data.to_vec())
+    }
+
+    fn frame_type(&self) -> FrameType {
+        if self.buffer.len() < 5 {
+            return FrameType::Unknown;
+        }
+
+        //Transports hand frames over after a ready byte (0x01)
+        if self.buffer[0] != 0x01 {
+            return FrameType::Unknown;
+        }
+
+        //4 and 5 because of the ready byte on start
+        if self.buffer[4] == 0x00 && self.buffer[5] == 0xFF {
+            return FrameType::Ack;
+        } else if self.buffer[4] == 0xFF && self.buffer[5] == 0x00 {
+            return FrameType::NAck;
+        } else if self.buffer[4] == 0xFF && self.buffer[5] == 0xFF {
+            return FrameType::Extended;
+        } else if self.buffer[4] == 0x01 && self.buffer[5] == 0xFF {
+            return FrameType::Error;
+        }
+
+        return FrameType::Normal;
+    }
+
+    fn data(&self) -> Result<Vec<u8>, std::io::Error> {
+        match self.frame_type() {
+            FrameType::Normal => Ok(self.buffer[6..self.buffer.len()-2].to_vec()),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::Other, "Frame has no data"))
+        }
+    }
+
+    /* Frame data bounded by the LEN field, for answers of unknown size. */
+    fn payload(&self) -> Result<Vec<u8>, std::io::Error> {
+        match self.frame_type() {
+            FrameType::Normal => {
+                let len = self.buffer[4] as usize;
+                if 6 + len > self.buffer.len() {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Truncated frame"));
+                }
+                Ok(self.buffer[6..6+len].to_vec())
+            },
+            _ => Err(std::io::Error::new(std::io::ErrorKind::Other, "Frame has no data"))
+        }
+    }
+
+    fn response_byte(&self) -> Result<u8,std::io::Error> {
+        match self.frame_type() {
+            FrameType::Normal => Ok(self.buffer[7]),
+            _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Frame has no data"))
+        }
+    }
+}
+
+/* The bus a PN532 is wired to. Framing is the same on all of them. */
+pub trait Pn532Transport {
+  fn open(&mut self) -> Result<(), String>;
+  fn close(&mut self) -> Result<(), String>;
+  fn wake_up(&mut self) -> Result<(), std::io::Error>;
+  /* Sends a complete frame, preamble to postamble. */
+  fn write(&mut self, frame: &[u8]) -> Result<(), std::io::Error>;
+  /* Reads `len` bytes of the pending frame, preceded by a ready byte
+   * (0x01). Fails when the PN532 has nothing ready yet. */
+  fn read(&mut self, len: usize) -> Result<Vec<u8>, std::io::Error>;
+  fn name(&self) -> &'static str;
+}
+
+#[allow(dead_code)]
+struct Pn532ThreadSafe {
+  transport: Box<dyn Pn532Transport + Send>,
+  keys: MifareKeys,
+  access_bits: Vec<u8>,
+  card_type: CardType,
+  desfire: Option<DesfireConfig>,
+  ntag: Option<NtagModel>,
+  ntag_originality: bool
+}
+
+impl Pn532ThreadSafe {
+
+  fn wake_up(&mut self) -> Result<(),std::io::Error> {
+      self.transport.wake_up()
+  }
+
+  fn read_frame(&mut self, len: Option<usize>) -> Result<Frame, std::io::Error> {
+    let buffer = self.transport.read(len.unwrap_or(256))?;
+    Ok(Frame { buffer: buffer })
+  }
+
+  fn read_frame_timeout(&mut self, len: Option<usize>, timeout: Duration) -> Result<Frame,std::io::Error> {
+      let now = Instant::now();
+      loop {
+          if now.elapsed() > timeout {
+              return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "TimedOut"));
+          }
+
+          if let Ok(ret) = self.read_frame(len) {
+              return Ok(ret);
+          }
+
+          thread::sleep(Duration::from_millis(100));
+      }
+  }
+
+  fn write_frame(&mut self, frame: Frame) -> Result<(), std::io::Error> {
+    self.transport.write(&frame.buffer)
+  }
+
+  fn command(&mut self, command: Command, data: Option<&[u8]>) -> Result<(), std::io::Error> {
+    let mut buffer = vec![command as u8];
+    if let Some(data) = data {
+        buffer.extend_from_slice(data);
+    }
+
+    match Frame::from_vec(&buffer) {
+        Ok(frame) => {
+            self.write_frame(frame)?;
+            match self.read_frame_timeout(Some(ResponseSize::Ack.size(0)),Duration::from_millis(1000)) {
+                Ok(frame) => {
+                    if frame.frame_type().is_ack() {
+                        return Ok(());
+                    } else {
+                        return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Not an ack frame: {:?}", &frame.buffer)));
+                    }
+                },
+                Err(err) => Err(std::io::Error::new(err.kind(), format!("Ack frame error: {}", err)))
+            }
+        },
+        Err(err) => Err(err)
+    }
+  }
+
+  fn setup(&mut self) -> Result<Vec<u8>, std::io::Error>{
+      self.wake_up()?;
+      match self.command(Command::SAMConfiguration, Some(&[0x01])) {
+          Ok(_) => {
+              if let Ok(frame) = self.read_frame_timeout(Some(ResponseSize::Frame.size(2)), Duration::from_millis(1000)) {
+                  if frame.response_byte()? == Command::SAMConfiguration.response() {
+                      return Ok(frame.data()?);
+                  } else {
+                      return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid Response Code"));
+                  }
+              }
+
+              return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "TimedOut"));
+          },
+          Err(err) => Err(err)
+      }
+  }
+
+  fn version(&mut self) -> Result<Vec<u8>, std::io::Error> {
+    match self.command(Command::GetFirmwareVersion, Option::None) {
+        Ok(_) => {
+            if let Ok(frame) = self.read_frame_timeout(Some(ResponseSize::Frame.size(6)), Duration::from_millis(1000)) {
+                if frame.response_byte()? == Command::GetFirmwareVersion.response() {
+                    return Ok(frame.data()?[3..5].to_vec());
+                } else {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid Response Code"));
+                }
+            }
+
+            return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "TimedOut"));
+        },
+        Err(err) => Err(err)
+    }
+  }
+
+  /* Target data of a 106 kbps type A target, as InListPassiveTarget and
+   * InAutoPoll return it: Tg, SENS_RES, SEL_RES, NFCID1 and the ATS. */
+  fn parse_target(data: &[u8], card_type: CardType) -> Result<CardInfo, std::io::Error> {
+      if data.len() < 5 || data.len() < 5 + data[4] as usize {
+          return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Truncated target data"));
+      }
+
+      //let tg = data[0];
+      let sens_res = data[1..3].to_vec();
+      let sel_res = data[3];
+      let id_len = data[4] as usize;
+      let id = &data[5..5+id_len];
+
+      /* The PN532 only sends an ATS for cards it activated as ISO 14443-4. */
+      let mut ats: Vec<u8> = Vec::new();
+      if sel_res & 0x20 != 0 && data.len() > 5+id_len {
+          let ats_len = data[5+id_len] as usize;
+          if ats_len > 1 && data.len() >= 5+id_len+ats_len {
+              ats = data[6+id_len..5+id_len+ats_len].to_vec();
+          }
+      }
+
+      /* ISO 14443-4 compliant and not emulating a Classic card. */
+      let found = if sel_res & 0x20 != 0 && sel_res & 0x18 == 0 {
+          CardType::Desfire
+      } else if sel_res == 0x00 {
+          /* Type 2 tag, the exact model comes from GET_VERSION. */
+          CardType::Ultralight
+      } else {
+          card_type
+      };
+
+      Ok(CardInfo::new(id.to_vec(), sens_res, sel_res, ats, found))
+  }
+
+  /* Starts an endless InAutoPoll for 106 kbps type A targets and returns
+   * without waiting: the PN532 lowers its IRQ line once a card shows up.
+   * The field is only on every `period` x 150 ms. */
+  fn start_auto_poll(&mut self, period: u8) -> Result<(), std::io::Error> {
+      self.command(Command::InAutoPoll, Some(&[0xFF, period, 0x00]))
+  }
+
+  /* The InAutoPoll answer. Fails while the PN532 has nothing ready. */
+  fn auto_poll_result(&mut self) -> Result<CardInfo, std::io::Error> {
+      let frame = self.read_frame(None)?;
+      if frame.response_byte()? != Command::InAutoPoll.response() {
+          return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid Response Code"));
+      }
+
+      /* D5 61 NbTg Type1 Len1 TargetData1 */
+      let data = frame.payload()?;
+      if data.len() < 5 || data[2] == 0 {
+          return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "No Target Detected"));
+      }
+
+      Pn532ThreadSafe::parse_target(&data[5..], CardType::Mifare)
+  }
+
+  fn read_passive_target(&mut self, card_type: CardType) -> Result<CardInfo, std::io::Error> {
+
+      let freq:u8 = match card_type {
+          CardType::Mifare | CardType::Desfire => 0x00,
+          CardType::Ntag213 | CardType::Ntag215 | CardType::Ntag216 | CardType::Ultralight => 0x00,
+          CardType::FelicaA => 0x01,
+          CardType::FelicaB => 0x02,
+          CardType::Jewel => 0x04,
+      };
+
+      match self.command(Command::InListPassiveTarget, Some(&[0x02, freq])) {
+          Ok(_) => {
+              if let Ok(frame) = self.read_frame_timeout(None, Duration::from_millis(1000)) {
+                  if frame.response_byte()? == Command::InListPassiveTarget.response() {
+
+                      let data = frame.data()?;
+                      let devices = data[2];
+
+                      if devices > 0 {
+                          return Pn532ThreadSafe::parse_target(&data[3..], card_type);
+                      }
+                      return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "No Target Detected"));
+                  } else {
+                      return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid Response Code"));
+                  }
+              }
+
+              return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "TimedOut"));
+          },
+          Err(err) => Err(std::io::Error::new(err.kind(), format!("Command Error: {}",err)))
+      }
+  }
+
+  fn auth(&mut self, auth_mode: u8, addr: u8, uuid: &Vec<u8>, key: MifareAuthKey) -> Result<(), std::io::Error> {
+    let mut tx_buf = vec![0x01, auth_mode, addr];
+    match key {
+      MifareAuthKey::DefaultKeyA => tx_buf.extend(MIFARE_DEFAULT_KEY_A),
+      MifareAuthKey::DefaultKeyB => tx_buf.extend(MIFARE_DEFAULT_KEY_B),
+      MifareAuthKey::CustomKeyA => tx_buf.extend(self.keys.key_a(uuid, MifareKeys::sector(addr))),
+      MifareAuthKey::CustomKeyB => tx_buf.extend(self.keys.key_b(uuid, MifareKeys::sector(addr)))
+    }
+    tx_buf.extend(uuid);
+
+    match self.command(Command::InDataExchange , Some(&tx_buf)) {
+        Ok(_) => {
+            if let Ok(frame) = self.read_frame_timeout(Some(ResponseSize::Frame.size(3)), Duration::from_millis(1000)) {
+                if frame.response_byte()? == Command::InDataExchange.response() {
+                    let data = frame.data()?;
+                    acontrol_system_log!(LogType::Debug, "Auth received response: {:X?}", data);
+
+                    let status:Error = Error::from(data[2]);
+
+                    if status == Error::Success {
+                        return Ok(());
+                    } else {
+                        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Status Error (0x{:X}) = {}", status as u8, &status.name())));
+                    }
+                } else {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid Response Code"));
+                }
+            }
+            return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "TimedOut"));
+        },
+        Err(err) => Err(std::io::Error::new(err.kind(), format!("Command Error: {}",err)))
+    }
+  }
+
+  fn read(&mut self, addr: u8) -> Result<Vec<u8>, std::io::Error> {
+      let tx_buf = vec![0x01, PICC::READ as u8, addr];
+
+      match self.command(Command::InDataExchange, Some(&tx_buf)) {
+        Ok(_) => {
+            if let Ok(frame) = self.read_frame_timeout(Some(ResponseSize::Frame.size(19)), Duration::from_millis(1000)) {
+                if frame.response_byte()? == Command::InDataExchange.response() {
+                    let data = frame.data()?;
+                    acontrol_system_log!(LogType::Debug, "Read received response: {:X?}", data);
+
+                    let status:Error = Error::from(data[2]);
+
+                    if status == Error::Success {
+                        return Ok(data[3..19].to_vec());
+                    } else {
+                        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Status Error (0x{:X}) = {}", status as u8, &status.name())));
+                    }
+                } else {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid Response Code"));
+                }
+            }
+            return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "TimedOut"));
+        },
+        Err(err) => Err(std::io::Error::new(err.kind(), format!("Command Error: {}",err)))
+      }
+  }
+
+  fn write(&mut self, addr: u8, data: &Vec<u8>) -> Result<(), std::io::Error> {
+    let mut tx_buf = vec![0x01, PICC::WRITE as u8, addr];
+
+    if data.len() < 16 {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid buffer length"));
+    }
+
+    tx_buf.extend_from_slice(data);
+
+    match self.command(Command::InDataExchange, Some(&tx_buf)) {
+      Ok(_) => {
+        if let Ok(frame) = self.read_frame_timeout(Some(ResponseSize::Frame.size(3)), Duration::from_millis(1000)) {
+          if frame.response_byte()? == Command::InDataExchange.response() {
+              let data = frame.data()?;
+
+              acontrol_system_log!(LogType::Debug, "Write received response: {:X?}", data);
+
+              let status:Error = Error::from(data[2]);
+
+              if status == Error::Success {
+                  return Ok(());
+              } else {
+                  return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Status Error (0x{:X}) = {}", status as u8, &status.name())));
+              }
+          } else {
+              return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid Response Code"));
+          }
+        }
+        return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "TimedOut"));
+      },
+      Err(err) => Err(std::io::Error::new(err.kind(), format!("Command Error: {}",err)))
+    }
+  }
+
+  fn write_sec(&mut self, uuid: &Vec<u8>, mode: WriteSecMode) -> Result<(), std::io::Error> {
+    let mut addr:u8 = 3;
+    let mut packet:Vec<u8> = Vec::new();
+
+    let key:MifareAuthKey = match mode {
+      WriteSecMode::Format => MifareAuthKey::DefaultKeyA,
+      WriteSecMode::Restore => MifareAuthKey::CustomKeyA
+    };
+
+    loop {
+      let sector = MifareKeys::sector(addr);
+
+      packet.clear();
+      match mode {
+        WriteSecMode::Format => {
+          packet.extend(self.keys.key_a(uuid, sector));
+          packet.extend(MIFARE_DEFAULT_ACCESS_BITS);
+          packet.extend(self.keys.key_b(uuid, sector));
+        },
+        WriteSecMode::Restore => {
+          packet.extend(MIFARE_DEFAULT_KEY_A);
+          packet.extend(MIFARE_DEFAULT_ACCESS_BITS);
+          packet.extend(MIFARE_DEFAULT_KEY_B);
+        }
+      }
+
+      match self.auth(PICC::AUTH1A.value(), addr, uuid, key) {
+        Ok(_val) => {
+          if let Err(err) = self.write(addr, &packet) {
+            return Err(err);
+          }
+
+          if addr < 62 { addr+=4; } else { break; }
+        },
+        Err(err) => return Err(err)
+      }
+    }
+    Ok(())
+  }
+
+
+  fn initialize(&mut self) -> Result<(), std::io::Error> {
+    Ok(())
+  }
+
+  fn ntag_identify(&mut self, uuid: &Vec<u8>) -> Result<(), String> {
+    let check_originality = self.ntag_originality;
+    let model = Ntag::new(self).identify(uuid, check_originality)?;
+
+    acontrol_system_log!(LogType::Debug, "Type 2 tag identified as {:?}", model.card_type);
+
+    self.card_type = model.card_type;
+    self.ntag = Some(model);
+    Ok(())
+  }
+
+  fn desfire_config(&self) -> Result<DesfireConfig, String> {
+    match self.desfire {
+      Some(ref config) => Ok(config.clone()),
+      None => Err(String::from("DESFire support not configured (desfire_key)"))
+    }
+  }
+
+  fn desfire_open(&mut self) -> Result<(), String> {
+    let config = self.desfire_config()?;
+    let mut card = Desfire::new(self);
+    card.select_application(&config.aid)?;
+    card.authenticate_aes(config.key_no, &config.key)
+  }
+
+  fn desfire_read(&mut self, offset: u32, len: u32) -> Result<Vec<u8>, String> {
+    let config = self.desfire_config()?;
+    let mut card = Desfire::new(self);
+    card.select_application(&config.aid)?;
+    card.authenticate_aes(config.key_no, &config.key)?;
+    card.read_data(config.file_no, offset, len)
+  }
+
+  fn desfire_write(&mut self, offset: u32, data: &[u8]) -> Result<(), String> {
+    let config = self.desfire_config()?;
+    let mut card = Desfire::new(self);
+    card.select_application(&config.aid)?;
+    card.authenticate_aes(config.key_no, &config.key)?;
+    card.write_data(config.file_no, offset, data)
+  }
+}
+
+impl NfcA for Pn532ThreadSafe {
+  fn transceive_frame(&mut self, frame: &[u8]) -> Result<Vec<u8>, String> {
+    if let Err(err) = self.command(Command::InCommunicateThru, Some(frame)) {
+      return Err(format!("Command Error: {}", err));
+    }
+
+    let answer = match self.read_frame_timeout(None, Duration::from_millis(1000)) {
+      Ok(answer) => answer,
+      Err(err) => return Err(format!("{}", err))
+    };
+
+    match answer.response_byte() {
+      Ok(response) if response == Command::InCommunicateThru.response() => {},
+      _ => return Err(String::from("Invalid Response Code"))
+    }
+
+    let data = match answer.payload() {
+      Ok(data) if data.len() >= 3 => data,
+      _ => return Err(String::from("Invalid InCommunicateThru answer"))
+    };
+
+    acontrol_system_log!(LogType::Debug, "Tag received response: {:X?}", data);
+
+    let status = data[2] & 0x3F;
+    if status != 0 {
+      return Err(format!("Status Error (0x{:X})", status));
+    }
+
+    Ok(data[3..].to_vec())
+  }
+}
+
+impl IsoDep for Pn532ThreadSafe {
+  fn transceive_apdu(&mut self, apdu: &[u8]) -> Result<Vec<u8>, String> {
+    let mut tx_buf = vec![0x01];
+    tx_buf.extend_from_slice(apdu);
+
+    if let Err(err) = self.command(Command::InDataExchange, Some(&tx_buf)) {
+      return Err(format!("Command Error: {}", err));
+    }
+
+    let frame = match self.read_frame_timeout(None, Duration::from_millis(1000)) {
+      Ok(frame) => frame,
+      Err(err) => return Err(format!("{}", err))
+    };
+
+    match frame.response_byte() {
+      Ok(response) if response == Command::InDataExchange.response() => {},
+      _ => return Err(String::from("Invalid Response Code"))
+    }
+
+    let data = match frame.payload() {
+      Ok(data) if data.len() >= 3 => data,
+      _ => return Err(String::from("Invalid InDataExchange answer"))
+    };
+
+    acontrol_system_log!(LogType::Debug, "APDU received response: {:X?}", data);
+
+    let status = data[2] & 0x3F;
+    if status != 0 {
+      return Err(format!("Status Error (0x{:X})", status));
+    }
+
+    Ok(data[3..].to_vec())
+  }
+}
+
+unsafe impl Send for Pn532ThreadSafe {}
+unsafe impl Sync for Pn532ThreadSafe {}
+
+pub struct Pn532 {
+  desfire: Result<Option<DesfireConfig>, String>,
+  card_hold_off: Duration,
+  irq_pin: Option<u64>,
+  irq: Option<IrqLine>,
+  auto_poll_period: u8,
+  pn532: Arc<Mutex<Pn532ThreadSafe>>
+}
+
+impl Pn532 {
+  pub fn new(transport: Box<dyn Pn532Transport + Send>, params: &HashMap<String, String>) -> Self {
+    let auto_poll_period = params.get("auto_poll_period").and_then(|period| period.parse::<u8>().ok()).unwrap_or(PN532_DEFAULT_AUTO_POLL_PERIOD);
+
+    return Pn532 {desfire: Pn532::desfire_params(params), card_hold_off: presence::hold_off_param(params),
+      irq_pin: irq::irq_pin_param(params), irq: None, auto_poll_period: auto_poll_period, pn532: Arc::new(Mutex::new(Pn532ThreadSafe
+      {
+        transport: transport,
+        keys: MifareKeys::fixed(MIFARE_DEFAULT_KEY_A, MIFARE_DEFAULT_KEY_B),
+        access_bits: vec![0xff,0x07,0x80,0x69],
+        card_type: CardType::Mifare,
+        desfire: None,
+        ntag: None,
+        ntag_originality: ntag::originality_param(params)
+      }
+    ))};
+  }
+
+  /* Leaves the PN532 polling on its own and sleeps on the IRQ line until it
+   * has a card. The lock is released while waiting. */
+  fn wait_for_card(pn532: &Arc<Mutex<Pn532ThreadSafe>>, poller: &mut PinPoller, period: u8) -> Result<CardInfo, std::io::Error> {
+    pn532.lock().unwrap().start_auto_poll(period)?;
+
+    loop {
+      poller.poll(PN532_IRQ_WAIT_MS)?;
+
+      /* A missed edge only delays the answer until the next timeout. */
+      match pn532.lock().unwrap().auto_poll_result() {
+        Ok(info) => return Ok(info),
+        Err(ref err) if err.kind() == std::io::ErrorKind::InvalidData => {
+          return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, format!("Auto poll ended: {}", err)));
+        },
+        Err(_) => {}
+      }
+    }
+  }
+
+  /* DESFire cards are only read when an application key is configured. */
+  fn desfire_params(params: &HashMap<String, String>) -> Result<Option<DesfireConfig>, String> {
+    let key = match params.get("desfire_key") {
+      Some(key) => hex::decode(key.trim()).map_err(|err| format!("Invalid desfire_key: {}", err))?,
+      None => return Ok(None)
+    };
+
+    let aid = hex::decode(params.get("desfire_aid").map(|aid| aid.as_str()).unwrap_or(DESFIRE_DEFAULT_AID).trim())
+      .map_err(|err| format!("Invalid desfire_aid: {}", err))?;
+
+    let key_no = match params.get("desfire_key_no") {
+      Some(key_no) => key_no.parse::<u8>().map_err(|err| format!("Invalid desfire_key_no: {}", err))?,
+      None => DESFIRE_DEFAULT_KEY_NO
+    };
+
+    let file_no = match params.get("desfire_file") {
+      Some(file_no) => file_no.parse::<u8>().map_err(|err| format!("Invalid desfire_file: {}", err))?,
+      None => DESFIRE_DEFAULT_FILE
+    };
+
+    DesfireConfig::new(&aid, key_no, &key, file_no).map(|config| Some(config))
+  }
+}
+
+impl NfcReader for Pn532 {
+  fn init(&mut self) -> Result<(), String> {
+    let pn532 = self.pn532.clone();
+
+    pn532.lock().unwrap().desfire = self.desfire.clone()?;
+
+    pn532.lock().unwrap().transport.open()?;
+
+    let mut pn532_init = false;
+
+    match pn532.lock().unwrap().setup() {
+        Ok(_) => {
+          acontrol_system_log!(LogType::Info, "NFC hardware initialized");
+        },
+        Err(err) => acontrol_system_log!(LogType::Error, "NFC hardware setup error: {}", err)
+    };
+
+    for _i in 0..10 {
+      thread::sleep(Duration::from_millis(50));
+      match pn532.lock().unwrap().version() {
+          Ok(version) => {
+            acontrol_system_log!(LogType::Info, "NFC hardware version: {}.{}", version[0], version[1]);
+            pn532_init = true;
+            break;
+          },
+          Err(err) => acontrol_system_log!(LogType::Error, "NFC hardware version error: {}", err)
+      };
+    }
+
+    if !pn532_init{
+      return Err(format!("{}", "NFC error. Could not retrieve hardware version"));
+    }
+
+    if let Err(_err) = pn532.lock().unwrap().initialize() {
+      return Err(format!("{}", "NFC error. Error initializing device"));
+    } else {
+      acontrol_system_log!(LogType::Info, "NFC device initialized successfully");
+    }
+
+    if let Some(irq_pin) = self.irq_pin {
+      self.irq = Some(IrqLine::open(irq_pin)?);
+      acontrol_system_log!(LogType::Info, "NFC card detection on irq gpio {}", irq_pin);
+    }
+
+    Ok(())
+  }
+
+  fn find_tag(&mut self, reader: &str, func: fn(&str, TagEvent) -> bool) -> Result<(),String> {
+    let reader = String::from(reader);
+    let mut presence = CardPresence::new(self.card_hold_off);
+    let pn532 = self.pn532.clone();
+    let period = self.auto_poll_period;
+    let mut poller = match self.irq {
+      Some(ref irq) => Some(irq.poller()?),
+      None => None
+    };
+
+    let _handler = thread::spawn(move || {
+        loop {
+            let mut card:Option<CardInfo> = None;
+
+            /* A card already on the reader is still polled to notice its removal. */
+            let detected = match poller {
+                Some(ref mut poller) if !presence.is_present() => Some(Pn532::wait_for_card(&pn532, poller, period)),
+                _ => None
+            };
+
+            {
+                let mut pn532_inner = pn532.lock().unwrap();
+
+                let selected = match detected {
+                    Some(result) => result,
+                    None => pn532_inner.read_passive_target(CardType::Mifare)
+                };
+
+                match selected {
+                    Ok(mut info) => {
+                        acontrol_system_log!(LogType::Debug, "Card selected: {}", info);
+
+                        pn532_inner.card_type = info.technology;
+                        pn532_inner.ntag = None;
+
+                        let identified = match info.technology {
+                            CardType::Ultralight => pn532_inner.ntag_identify(&info.uid),
+                            _ => Ok(())
+                        };
+
+                        match identified {
+                            Ok(_) => {
+                                info.technology = pn532_inner.card_type;
+                                card = Some(info);
+                            },
+                            Err(err) => acontrol_system_log!(LogType::Error, "Rejecting tag {:X?}: {}", info.uid, err)
+                        }
+                    },
+                    Err(err) => {
+                        match err.kind() {
+                            std::io::ErrorKind::TimedOut => {/*No card found*/},
+                            _ => acontrol_system_log!(LogType::Error, "Card Detection Error: {}", err)
+                        }
+                    },
+                };
+            }
+
+            for event in presence.update(card) {
+                func(&reader, event);
+            }
+
+            thread::sleep(Duration::from_millis(500));
+        }
+    });
+    Ok(())
+  }
+
+  fn set_auth_keys(&mut self, keys: MifareKeys) -> Result<(), String> {
+    let pn532 = self.pn532.clone();
+    let mut pn532_inner = pn532.lock().unwrap();
+
+    pn532_inner.keys = keys;
+
+    Ok(())
+  }
+
+  fn set_auth_bits(&mut self, _access_bits: Vec<u8>) -> Result<(), String> {
+    Err(String::from("Not Implement"))
+  }
+
+  fn format(&mut self, uuid: &Vec<u8>) -> Result<(), String> {
+      let pn532 = self.pn532.clone();
+      let mut pn532_inner = pn532.lock().unwrap();
+
+      if let CardType::Desfire = pn532_inner.card_type {
+          return pn532_inner.desfire_open();
+      }
+
+      if let Some(model) = pn532_inner.ntag {
+          let (pwd, pack) = pn532_inner.keys.ntag_protect_password(uuid)?;
+          return Ntag::new(&mut *pn532_inner).protect(&model, &pwd, &pack);
+      }
+
+      match pn532_inner.write_sec(uuid, WriteSecMode::Format) {
+          Ok(_) => Ok(()),
+          Err(err) => Err(format!("{}",err))
+      }
+  }
+
+  fn restore(&mut self, uuid: &Vec<u8>) -> Result<(), String> {
+      let pn532 = self.pn532.clone();
+      let mut pn532_inner = pn532.lock().unwrap();
+
+      if let CardType::Desfire = pn532_inner.card_type {
+          return Err(String::from("Not supported on DESFire cards"));
+      }
+
+      if let Some(model) = pn532_inner.ntag {
+          let (pwd, pack) = pn532_inner.keys.ntag_password(uuid);
+          return Ntag::new(&mut *pn532_inner).unprotect(&model, &pwd, &pack);
+      }
+
+      match pn532_inner.write_sec(uuid, WriteSecMode::Restore) {
+          Ok(_) => Ok(()),
+          Err(err) => Err(format!("{}",err))
+      }
+  }
+
+  fn read_data(&mut self, uuid: &Vec<u8>, addr: u8, blocks: u8) -> Result<Vec<u8>, String> {
+      let pn532 = self.pn532.clone();
+      let mut pn532_inner = pn532.lock().unwrap();
+
+      /* The DESFire data file mirrors the Classic block layout, 16 bytes per block. */
+      if let CardType::Desfire = pn532_inner.card_type {
+          return pn532_inner.desfire_read(addr as u32 * 16, (blocks as u32 + 1) * 16);
+      }
+
+      if let Some(model) = pn532_inner.ntag {
+          let (pwd, pack) = pn532_inner.keys.ntag_password(uuid);
+          return Ntag::new(&mut *pn532_inner).read_blocks(&model, &pwd, &pack, addr, blocks + 1);
+      }
+
+      let mut cur_addr:u8 = addr;
+      let mut buffer: Vec<u8> = Vec::new();
+
+      loop {
+
+        if MifareKeys::is_trailer(cur_addr) { cur_addr += 1; }
+
+        match pn532_inner.auth(PICC::AUTH1A as u8, cur_addr, uuid, MifareAuthKey::CustomKeyA) {
+          Ok(_) => {
+            match pn532_inner.read(cur_addr) {
+              Ok(val) => {
+                buffer.extend(val);
+              },
+              Err(err) => return Err(format!("{}",err)),
+            }
+
+            if cur_addr < addr+blocks { cur_addr+=1; } else { break; }
+          },
+          Err(err) => return Err(format!("{}",err))
+        }
+      }
+
+      Ok(buffer)
+  }
+
+  fn write_data(&mut self, uuid: &Vec<u8>, addr: u8, data: &Vec<u8>) -> Result<u8, String> {
+      let pn532 = self.pn532.clone();
+      let mut pn532_inner = pn532.lock().unwrap();
+
+      if let CardType::Desfire = pn532_inner.card_type {
+          let mut packet = data.clone();
+          packet.resize((data.len() + 15) / 16 * 16, 0);
+          pn532_inner.desfire_write(addr as u32 * 16, &packet)?;
+          return Ok((packet.len() / 16) as u8);
+      }
+
+      if let Some(model) = pn532_inner.ntag {
+          let (pwd, pack) = pn532_inner.keys.ntag_password(uuid);
+          return Ntag::new(&mut *pn532_inner).write_blocks(&model, &pwd, &pack, addr, data);
+      }
+
+      let mut cur_addr:u8 = addr;
+      let mut buffer:VecDeque<u8> = VecDeque::new();
+      let mut packet:Vec<u8> = Vec::new();
+
+      buffer.extend(data);
+
+      loop {
+
+        if MifareKeys::is_trailer(cur_addr) { cur_addr += 1; }
+
+        match pn532_inner.auth(PICC::AUTH1A.value(), cur_addr, uuid, MifareAuthKey::CustomKeyA) {
+          Ok(_val) => {
+
+            packet.clear();
+
+            if buffer.len() == 0 { break; }
+
+            loop {
+                match buffer.pop_front(){
+                  Some(val) => packet.push(val),
+                  None => packet.push(0),
+                }
+                if packet.len() >= 16 { break  };
+            }
+
+            if let Err(err) = pn532_inner.write(cur_addr, &packet) {
+              return Err(format!("{}",err));
+            }
+
+            if cur_addr < 62 { cur_addr+=1; } else { break; }
+          },
+          Err(err) => return Err(format!("{}",err))
+        }
+      }
+
+      Ok(cur_addr - addr)
+  }
+
+  fn unload(&mut self) -> Result<(), String>{
+    acontrol_system_log!(LogType::Info, "NFC driver unloading");
+    let pn532 = self.pn532.clone();
+    if let Err(err) = pn532.lock().unwrap().transport.close() {
+      return Err(format!("{}(=>{})", "NFC driver error",err));
+    }
+    if let Some(ref irq) = self.irq {
+      if let Err(err) = irq.close() {
+        return Err(format!("{}(=>{})", "NFC driver error",err));
+      }
+    }
+    Ok(())
+  }
+
+  fn signature(&self) -> String {
+    return format!("PN532 NFC Reader Module ({})", self.pn532.lock().unwrap().transport.name());
+  }
+}
+
+unsafe impl Send for Pn532 {}
+unsafe impl Sync for Pn532 {}
@@ -0,0 +1,124 @@
+/**
+ * @file   nfc/pn532_i2c.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  NFC PN532 I2C transport
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use crate::nfc::pn532::Pn532Transport;
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+use std::os::unix::io::AsRawFd;
+use std::thread;
+use std::time::Duration;
+
+const PN532_DEFAULT_I2C_DEVICE: &str = "/dev/i2c-1";
+const PN532_DEFAULT_I2C_ADDRESS: u16 = 0x24;
+
+/* Status byte bit set when the PN532 has a frame ready. */
+const I2C_READY: u8 = 0x01;
+
+mod i2c_ioctl {
+  const I2C_SLAVE: u16 = 0x0703;
+
+  nix::ioctl_write_int_bad!(set_slave_address, I2C_SLAVE);
+}
+
+pub struct Pn532I2c {
+  device: String,
+  address: u16,
+  file: Option<File>
+}
+
+impl Pn532I2c {
+  pub fn new(params: &HashMap<String, String>) -> Self {
+    let device = params.get("device").cloned().unwrap_or(String::from(PN532_DEFAULT_I2C_DEVICE));
+    let address = params.get("address").and_then(|address| {
+      match address.trim_start_matches("0x") {
+        hex if hex.len() != address.len() => u16::from_str_radix(hex, 16).ok(),
+        _ => address.parse::<u16>().ok()
+      }
+    }).unwrap_or(PN532_DEFAULT_I2C_ADDRESS);
+
+    Pn532I2c { device: device, address: address, file: None }
+  }
+
+  fn file(&mut self) -> Result<&mut File, std::io::Error> {
+    match self.file {
+      Some(ref mut file) => Ok(file),
+      None => Err(std::io::Error::new(std::io::ErrorKind::Other, "I2C device not found"))
+    }
+  }
+}
+
+impl Pn532Transport for Pn532I2c {
+  fn open(&mut self) -> Result<(), String> {
+    let file = match OpenOptions::new().read(true).write(true).open(&self.device) {
+      Ok(file) => file,
+      Err(err) => return Err(format!("{} {} - {}", "Error initializing i2c port", self.device, err))
+    };
+
+    if let Err(err) = unsafe { i2c_ioctl::set_slave_address(file.as_raw_fd(), self.address as libc::c_int) } {
+      return Err(format!("Error selecting i2c address 0x{:X}: {}", self.address, err));
+    }
+
+    self.file = Some(file);
+    Ok(())
+  }
+
+  fn close(&mut self) -> Result<(), String> {
+    self.file = None;
+    Ok(())
+  }
+
+  /* The PN532 wakes up when it sees its own address on the bus. */
+  fn wake_up(&mut self) -> Result<(), std::io::Error> {
+    let _ = self.file()?.read(&mut [0]);
+    thread::sleep(Duration::from_millis(500));
+    Ok(())
+  }
+
+  /* Every read transaction starts with the status byte, so the frame
+   * comes already preceded by it. */
+  fn read(&mut self, len: usize) -> Result<Vec<u8>, std::io::Error> {
+    let mut buffer = vec![0; len];
+    self.file()?.read_exact(&mut buffer)?;
+
+    if buffer[0] & I2C_READY == 0 {
+      return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("No data: 0x{:X}", buffer[0])));
+    }
+
+    Ok(buffer)
+  }
+
+  fn write(&mut self, frame: &[u8]) -> Result<(), std::io::Error> {
+    self.file()?.write_all(frame)
+  }
+
+  fn name(&self) -> &'static str {
+    "I2C"
+  }
+}
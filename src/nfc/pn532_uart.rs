@@ -0,0 +1,141 @@
+/**
+ * @file   nfc/pn532_uart.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  NFC PN532 HSU (UART) transport
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use crate::nfc::pn532::Pn532Transport;
+
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use serialport::{DataBits,FlowControl,Parity,StopBits,SerialPort,SerialPortSettings};
+
+const PN532_DEFAULT_UART_DEVICE: &str = "/dev/ttyS0";
+const PN532_DEFAULT_BAUD_RATE: u32 = 115200;
+
+/* A long preamble takes the PN532 out of power down on HSU. */
+const HSU_WAKE_UP: [u8; 16] = [0x55, 0x55, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                               0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+pub struct Pn532Uart {
+  device: String,
+  baud_rate: u32,
+  port: Option<Box<dyn SerialPort>>
+}
+
+impl Pn532Uart {
+  pub fn new(params: &HashMap<String, String>) -> Self {
+    let device = params.get("device").cloned().unwrap_or(String::from(PN532_DEFAULT_UART_DEVICE));
+    let baud_rate = params.get("baud_rate").and_then(|baud| baud.parse::<u32>().ok()).unwrap_or(PN532_DEFAULT_BAUD_RATE);
+
+    Pn532Uart { device: device, baud_rate: baud_rate, port: None }
+  }
+
+  fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, std::io::Error> {
+    let mut buffer = vec![0; len];
+    match self.port {
+      Some(ref mut port) => port.read_exact(&mut buffer)?,
+      None => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Serial port not found"))
+    }
+    Ok(buffer)
+  }
+}
+
+impl Pn532Transport for Pn532Uart {
+  fn open(&mut self) -> Result<(), String> {
+    let s = SerialPortSettings {
+        baud_rate: self.baud_rate,
+        data_bits: DataBits::Eight,
+        flow_control: FlowControl::None,
+        parity: Parity::None,
+        stop_bits: StopBits::One,
+        timeout: Duration::from_millis(100),
+    };
+
+    match serialport::open_with_settings(&self.device, &s) {
+      Ok(port) => {
+        self.port = Some(port);
+        Ok(())
+      },
+      Err(err) => Err(format!("{} {} - {}", "Error initializing serial port", self.device, err))
+    }
+  }
+
+  fn close(&mut self) -> Result<(), String> {
+    self.port = None;
+    Ok(())
+  }
+
+  fn wake_up(&mut self) -> Result<(), std::io::Error> {
+    match self.port {
+      Some(ref mut port) => port.write_all(&HSU_WAKE_UP)?,
+      None => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Serial port not found"))
+    }
+    thread::sleep(Duration::from_millis(10));
+    Ok(())
+  }
+
+  /* There is no status byte on HSU: frames are read field by field and
+   * handed over behind a ready byte like the other transports do. */
+  fn read(&mut self, _len: usize) -> Result<Vec<u8>, std::io::Error> {
+    let mut previous = 0xFF;
+    loop {
+      let byte = self.read_bytes(1)?[0];
+      if previous == 0x00 && byte == 0xFF {
+        break;
+      }
+      previous = byte;
+    }
+
+    let header = self.read_bytes(2)?;
+    let mut buffer = vec![0x01, 0x00, 0x00, 0xFF, header[0], header[1]];
+
+    /* ACK and NACK frames end right after the postamble. */
+    if (header[0] == 0x00 && header[1] == 0xFF) || (header[0] == 0xFF && header[1] == 0x00) {
+      buffer.extend(self.read_bytes(1)?);
+      return Ok(buffer);
+    }
+
+    if header[0].wrapping_add(header[1]) != 0 {
+      return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid frame length checksum"));
+    }
+
+    buffer.extend(self.read_bytes(header[0] as usize + 2)?);
+    Ok(buffer)
+  }
+
+  fn write(&mut self, frame: &[u8]) -> Result<(), std::io::Error> {
+    match self.port {
+      Some(ref mut port) => port.write_all(frame),
+      None => Err(std::io::Error::new(std::io::ErrorKind::Other, "Serial port not found"))
+    }
+  }
+
+  fn name(&self) -> &'static str {
+    "UART"
+  }
+}
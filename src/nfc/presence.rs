@@ -0,0 +1,206 @@
+/**
+ * @file   presence.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  Card presence tracking for the polling loops
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use crate::nfc::{CardInfo, TagEvent};
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+//empty polls in a row before a present card is reported as removed
+const CARD_REMOVED_POLLS: u32 = 3;
+const CARD_DEFAULT_HOLD_OFF_MS: u64 = 3000;
+
+/* Hold-off for the same card (`card_hold_off_ms`), in milliseconds. */
+pub fn hold_off_param(params: &HashMap<String, String>) -> Duration {
+  Duration::from_millis(params.get("card_hold_off_ms").and_then(|value| value.parse::<u64>().ok()).unwrap_or(CARD_DEFAULT_HOLD_OFF_MS))
+}
+
+/* The card sitting on one reader.
+ *
+ * The polling loops feed every poll result in and only forward the
+ * edges: a card showing up and a card leaving. The same card presented
+ * again within the hold-off of its last appearance or removal is not
+ * reported a second time. */
+pub struct CardPresence {
+  hold_off: Duration,
+  present: Option<CardInfo>,
+  announced: bool,
+  missed: u32,
+  last: Option<(Vec<u8>, Instant)>,
+}
+
+impl CardPresence {
+  pub fn new(hold_off: Duration) -> Self {
+    CardPresence { hold_off: hold_off, present: None, announced: false, missed: 0, last: None }
+  }
+
+  pub fn is_present(&self) -> bool {
+    self.present.is_some()
+  }
+
+  /* Feeds the result of one poll, `None` when no card answered. */
+  pub fn update(&mut self, card: Option<CardInfo>) -> Vec<TagEvent> {
+    let mut events: Vec<TagEvent> = Vec::new();
+
+    match card {
+      Some(card) => {
+        match self.present {
+          Some(ref present) if present.uid == card.uid => {
+            self.missed = 0;
+            return events;
+          },
+          Some(_) => self.remove(&mut events),
+          None => {}
+        }
+
+        let now = Instant::now();
+        let repeated = match self.last {
+          Some((ref uid, at)) => *uid == card.uid && now.duration_since(at) < self.hold_off,
+          None => false
+        };
+
+        self.missed = 0;
+        self.announced = !repeated;
+        self.present = Some(card.clone());
+
+        if !repeated {
+          self.last = Some((card.uid.clone(), now));
+          events.push(TagEvent::Presented(card));
+        }
+      },
+      None => {
+        if self.present.is_some() {
+          self.missed += 1;
+          if self.missed >= CARD_REMOVED_POLLS {
+            self.remove(&mut events);
+          }
+        }
+      }
+    }
+
+    events
+  }
+
+  fn remove(&mut self, events: &mut Vec<TagEvent>) {
+    self.missed = 0;
+    if let Some(card) = self.present.take() {
+      self.last = Some((card.uid.clone(), Instant::now()));
+      if self.announced {
+        events.push(TagEvent::Removed(card));
+      }
+    }
+    self.announced = false;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::nfc::CardType;
+
+  fn card(uid: u8) -> Option<CardInfo> {
+    Some(CardInfo::new(vec![uid; 4], vec![0x04, 0x00], 0x08, Vec::new(), CardType::Mifare))
+  }
+
+  fn names(events: Vec<TagEvent>) -> Vec<(&'static str, Vec<u8>)> {
+    events.iter().map(|event| match event {
+      TagEvent::Presented(card) => ("presented", card.uid.clone()),
+      TagEvent::Removed(card) => ("removed", card.uid.clone())
+    }).collect()
+  }
+
+  #[test]
+  fn removed_after_missed_polls() {
+    let mut presence = CardPresence::new(Duration::from_secs(60));
+
+    assert_eq!(names(presence.update(card(1))), vec![("presented", vec![1; 4])]);
+    assert!(presence.update(card(1)).is_empty());
+
+    for _ in 1..CARD_REMOVED_POLLS {
+      assert!(presence.update(None).is_empty());
+      assert!(presence.is_present());
+    }
+
+    assert_eq!(names(presence.update(None)), vec![("removed", vec![1; 4])]);
+    assert!(!presence.is_present());
+  }
+
+  #[test]
+  fn a_single_answer_resets_the_missed_polls() {
+    let mut presence = CardPresence::new(Duration::from_secs(60));
+    presence.update(card(1));
+
+    for _ in 0..3 {
+      for _ in 1..CARD_REMOVED_POLLS {
+        assert!(presence.update(None).is_empty());
+      }
+      assert!(presence.update(card(1)).is_empty());
+    }
+    assert!(presence.is_present());
+  }
+
+  #[test]
+  fn swapped_card_is_removed_then_presented() {
+    let mut presence = CardPresence::new(Duration::from_secs(60));
+    presence.update(card(1));
+
+    assert_eq!(names(presence.update(card(2))), vec![("removed", vec![1; 4]), ("presented", vec![2; 4])]);
+    assert!(presence.is_present());
+  }
+
+  #[test]
+  fn same_card_within_hold_off_is_not_reported() {
+    let mut presence = CardPresence::new(Duration::from_secs(60));
+    presence.update(card(1));
+    for _ in 0..CARD_REMOVED_POLLS {
+      presence.update(None);
+    }
+
+    /* Back on the reader: tracked again, but neither edge is reported. */
+    assert!(presence.update(card(1)).is_empty());
+    assert!(presence.is_present());
+    for _ in 0..CARD_REMOVED_POLLS {
+      assert!(presence.update(None).is_empty());
+    }
+
+    /* Another card is not held off. */
+    assert_eq!(names(presence.update(card(2))), vec![("presented", vec![2; 4])]);
+  }
+
+  #[test]
+  fn same_card_after_hold_off_is_reported() {
+    let mut presence = CardPresence::new(Duration::from_millis(0));
+    presence.update(card(1));
+    for _ in 0..CARD_REMOVED_POLLS {
+      presence.update(None);
+    }
+
+    assert_eq!(names(presence.update(card(1))), vec![("presented", vec![1; 4])]);
+  }
+}
@@ -32,42 +32,320 @@ use std::collections::HashMap;
 pub enum PersistError {
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdminRole {
+  Auditor,
+  Admin
+}
+
+impl AdminRole {
+  pub fn name(&self) -> &'static str {
+    match *self {
+      AdminRole::Auditor => "auditor",
+      AdminRole::Admin => "admin",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<AdminRole> {
+    match name {
+      "auditor" => Some(AdminRole::Auditor),
+      "admin" => Some(AdminRole::Admin),
+      _ => None
+    }
+  }
+}
+
+pub struct Admin {
+  pub id: i32,
+  pub name: String,
+  pub role: AdminRole,
+  pub password_hash: String,
+  pub token_hash: Option<String>
+}
+
+pub struct User {
+  pub id: i32,
+  pub name: String,
+  pub enabled: bool,
+  pub group_id: Option<i32>,
+  pub schedule_id: Option<i32>
+}
+
+pub struct Group {
+  pub id: i32,
+  pub name: String,
+  pub schedule_id: Option<i32>
+}
+
+#[derive(Clone)]
+pub struct ScheduleWindow {
+  pub weekday: u32,
+  pub start: u32,
+  pub end: u32
+}
+
+pub struct Schedule {
+  pub id: i32,
+  pub name: String,
+  pub windows: Vec<ScheduleWindow>
+}
+
+impl Schedule {
+  /* weekday counts from monday (0) and start/end are minutes since midnight,
+   * end exclusive */
+  pub fn allows(&self, weekday: u32, minute: u32) -> bool {
+    self.windows.iter().any(|window| window.weekday == weekday && minute >= window.start && minute < window.end)
+  }
+}
+
+pub struct Holiday {
+  pub id: i32,
+  pub date: String,
+  pub name: String
+}
+
+#[derive(Clone)]
+pub struct CredentialValidity {
+  pub valid_from: Option<i64>,
+  pub valid_until: Option<i64>,
+  pub max_uses: Option<u32>,
+  pub uses: u32
+}
+
+impl CredentialValidity {
+  pub fn unlimited() -> Self {
+    CredentialValidity { valid_from: None, valid_until: None, max_uses: None, uses: 0 }
+  }
+
+  pub fn check(&self, now: i64) -> Result<(), String> {
+    if let Some(valid_from) = self.valid_from {
+      if now < valid_from {
+        return Err(String::from("Credential not yet valid"));
+      }
+    }
+
+    if let Some(valid_until) = self.valid_until {
+      if now > valid_until {
+        return Err(String::from("Credential expired"));
+      }
+    }
+
+    if let Some(max_uses) = self.max_uses {
+      if self.uses >= max_uses {
+        return Err(String::from("Credential use limit reached"));
+      }
+    }
+
+    Ok(())
+  }
+}
+
 pub struct Card  {
   pub id: i32,
   pub uuid: Vec<u8>,
-  pub name: Vec<u8>
+  pub name: Vec<u8>,
+  pub user_id: Option<i32>,
+  pub validity: CredentialValidity
 }
 
 pub struct Fingerprint {
   pub id: i32,
   pub pos: i32,
-  pub name: Vec<u8>
+  pub name: Vec<u8>,
+  pub user_id: Option<i32>,
+  pub validity: CredentialValidity
+}
+
+/* Sealed sensor template, see template_vault.rs. */
+pub struct FingerprintTemplate {
+  pub pos: i32,
+  pub template: Vec<u8>,
+  pub created_at: i64
 }
 
 pub struct Bluetooth {
   pub id: i32,
   pub addr: Vec<u8>,
-  pub name: Vec<u8>
+  pub name: Vec<u8>,
+  pub user_id: Option<i32>,
+  pub validity: CredentialValidity
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(dead_code)]
+pub enum CredentialType {
+  Nfc,
+  Fingerprint,
+  Bluetooth,
+  ExitButton,
+  Door
+}
+
+impl CredentialType {
+  pub fn name(&self) -> &'static str {
+    match *self {
+      CredentialType::Nfc => "nfc",
+      CredentialType::Fingerprint => "fingerprint",
+      CredentialType::Bluetooth => "bluetooth",
+      CredentialType::ExitButton => "exit_button",
+      CredentialType::Door => "door",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<CredentialType> {
+    match name {
+      "nfc" => Some(CredentialType::Nfc),
+      "fingerprint" => Some(CredentialType::Fingerprint),
+      "bluetooth" => Some(CredentialType::Bluetooth),
+      "exit_button" => Some(CredentialType::ExitButton),
+      "door" => Some(CredentialType::Door),
+      _ => None
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(dead_code)]
+pub enum AccessDecision {
+  Granted,
+  Denied,
+  Alarm
+}
+
+impl AccessDecision {
+  pub fn name(&self) -> &'static str {
+    match *self {
+      AccessDecision::Granted => "granted",
+      AccessDecision::Denied => "denied",
+      AccessDecision::Alarm => "alarm",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<AccessDecision> {
+    match name {
+      "granted" => Some(AccessDecision::Granted),
+      "denied" => Some(AccessDecision::Denied),
+      "alarm" => Some(AccessDecision::Alarm),
+      _ => None
+    }
+  }
+}
+
+pub struct AccessEvent {
+  pub id: i64,
+  pub timestamp: i64,
+  pub credential_type: CredentialType,
+  pub credential_id: String,
+  /* Name of the NFC reader the credential was presented to. Empty for
+   * credentials that do not come from a reader. */
+  pub reader: String,
+  pub user: String,
+  pub decision: AccessDecision,
+  pub reason: String,
+  /* What the card told the reader when selected. Empty, and no SAK, for
+   * credentials that are not cards. */
+  pub card_technology: String,
+  pub card_atqa: String,
+  pub card_sak: Option<u8>
+}
+
+impl AccessEvent {
+  pub fn new(credential_type: CredentialType, credential_id: &str, reader: &str, user: &str, decision: AccessDecision, reason: &str) -> Self {
+    AccessEvent {
+      id: 0,
+      timestamp: chrono::Utc::now().timestamp(),
+      credential_type: credential_type,
+      credential_id: String::from(credential_id),
+      reader: String::from(reader),
+      user: String::from(user),
+      decision: decision,
+      reason: String::from(reason),
+      card_technology: String::new(),
+      card_atqa: String::new(),
+      card_sak: None
+    }
+  }
+}
+
+pub struct AccessEventFilter {
+  pub from: Option<i64>,
+  pub to: Option<i64>,
+  pub user: Option<String>,
+  pub reader: Option<String>,
+  pub decision: Option<AccessDecision>,
+  pub offset: u32,
+  pub limit: u32
 }
 
 pub trait Persist {
   fn init(&mut self, params: &HashMap<String,String>) -> Result<(), String>;
   fn unload(&mut self) -> Result<(), String>;
 
-  fn nfc_add(&mut self, uuid: &Vec<u8>, name: &Vec<u8>) -> Result<(), String>;
+  fn admin_add(&mut self, name: &str, password_hash: &str, role: AdminRole) -> Result<Admin, String>;
+  fn admin_find_by_name(&mut self, name: &str) -> Result<Admin, String>;
+  fn admin_find_by_token(&mut self, token_hash: &str) -> Result<Admin, String>;
+  fn admin_list(&mut self) -> Result<Vec<Admin>, String>;
+  fn admin_set_token(&mut self, id: i32, token_hash: Option<&str>) -> Result<(), String>;
+  fn admin_delete(&mut self, id: i32) -> Result<(), String>;
+
+  fn user_add(&mut self, name: &str) -> Result<User, String>;
+  fn user_find(&mut self, id: i32) -> Result<User, String>;
+  fn user_find_by_name(&mut self, name: &str) -> Result<User, String>;
+  fn user_list(&mut self) -> Result<Vec<User>, String>;
+  fn user_update(&mut self, user: &User) -> Result<(), String>;
+  fn user_set_enabled(&mut self, id: i32, enabled: bool) -> Result<(), String>;
+  fn user_delete(&mut self, id: i32) -> Result<(), String>;
+  fn user_cards(&mut self, id: i32) -> Result<Vec<Card>, String>;
+  fn user_fingerprints(&mut self, id: i32) -> Result<Vec<Fingerprint>, String>;
+  fn user_bluetooth(&mut self, id: i32) -> Result<Vec<Bluetooth>, String>;
+
+  fn user_schedule(&mut self, id: i32) -> Result<Option<Schedule>, String>;
+
+  fn group_add(&mut self, name: &str) -> Result<Group, String>;
+  fn group_find(&mut self, id: i32) -> Result<Group, String>;
+  fn group_list(&mut self) -> Result<Vec<Group>, String>;
+  fn group_update(&mut self, group: &Group) -> Result<(), String>;
+  fn group_delete(&mut self, id: i32) -> Result<(), String>;
+
+  fn schedule_add(&mut self, name: &str, windows: &Vec<ScheduleWindow>) -> Result<Schedule, String>;
+  fn schedule_find(&mut self, id: i32) -> Result<Schedule, String>;
+  fn schedule_list(&mut self) -> Result<Vec<Schedule>, String>;
+  fn schedule_update(&mut self, schedule: &Schedule) -> Result<(), String>;
+  fn schedule_delete(&mut self, id: i32) -> Result<(), String>;
+
+  fn holiday_add(&mut self, date: &str, name: &str) -> Result<Holiday, String>;
+  fn holiday_list(&mut self) -> Result<Vec<Holiday>, String>;
+  fn holiday_delete(&mut self, id: i32) -> Result<(), String>;
+  fn holiday_is(&mut self, date: &str) -> Result<bool, String>;
+
+  fn nfc_add(&mut self, uuid: &Vec<u8>, name: &Vec<u8>, user_id: i32) -> Result<(), String>;
   fn nfc_find(&mut self, uuid: &Vec<u8>) -> Result<Card, String>;
   fn nfc_list(&mut self) -> Result<Vec<Card>, String>;
   fn nfc_delete(&mut self, uuid: &Vec<u8>) -> Result<(), String>;
 
-  fn fingerprint_add(&mut self, pos: i32, name: &Vec<u8>) -> Result<(), String>;
+  fn fingerprint_add(&mut self, pos: i32, name: &Vec<u8>, user_id: i32) -> Result<(), String>;
   fn fingerprint_find(&mut self, pos: i32) -> Result<Fingerprint, String>;
   fn fingerprint_list(&mut self) -> Result<Vec<Fingerprint>, String>;
   fn fingerprint_delete(&mut self, pos: i32) -> Result<(), String>;
+  fn fingerprint_delete_all(&mut self) -> Result<(), String>;
+
+  fn fingerprint_template_save(&mut self, template: &FingerprintTemplate) -> Result<(), String>;
+  fn fingerprint_template_list(&mut self) -> Result<Vec<FingerprintTemplate>, String>;
+  fn fingerprint_template_delete(&mut self, pos: i32) -> Result<(), String>;
 
-  fn bluetooth_add(&mut self, addr: &Vec<u8>, name: &Vec<u8>) -> Result<(), String>;
+  fn bluetooth_add(&mut self, addr: &Vec<u8>, name: &Vec<u8>, user_id: i32) -> Result<(), String>;
   fn bluetooth_find(&mut self, addr: &Vec<u8>) -> Result<Bluetooth, String>;
   fn bluetooth_list(&mut self) -> Result<Vec<Bluetooth>, String>;
-  fn bluetooth_delete(&mut self, _addr: &Vec<u8>) -> Result<(), String>;
+  fn bluetooth_delete(&mut self, addr: &Vec<u8>) -> Result<(), String>;
+
+  fn credential_validity(&mut self, credential_type: CredentialType, id: i32) -> Result<CredentialValidity, String>;
+  fn credential_set_validity(&mut self, credential_type: CredentialType, id: i32, validity: &CredentialValidity) -> Result<(), String>;
+  fn credential_use(&mut self, credential_type: CredentialType, id: i32) -> Result<(), String>;
+  fn credential_cleanup(&mut self, now: i64) -> Result<Vec<Fingerprint>, String>;
+
+  fn event_add(&mut self, event: &AccessEvent) -> Result<(), String>;
+  fn event_list(&mut self, filter: &AccessEventFilter) -> Result<Vec<AccessEvent>, String>;
+  fn event_count(&mut self, filter: &AccessEventFilter) -> Result<u32, String>;
 }
 
 pub fn persist_by_name(name: &str) -> Option<Box<dyn Persist+Sync+Send>> {
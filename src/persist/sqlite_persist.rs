@@ -25,13 +25,13 @@
  * THE SOFTWARE.
  *
  */
-use super::{Persist, Card, Fingerprint, Bluetooth};
+use super::{Persist, Admin, AdminRole, User, Group, Schedule, ScheduleWindow, Holiday, CredentialValidity, Card, Fingerprint, FingerprintTemplate, Bluetooth, AccessEvent, AccessEventFilter, AccessDecision, CredentialType};
 use crate::acontrol_system_log;
 use crate::log::LogType;
 
 use std::path::Path;
 use std::collections::HashMap;
-use rusqlite::{Connection,NO_PARAMS};
+use rusqlite::{Connection,Row,NO_PARAMS};
 use rusqlite::types::ToSql;
 
 pub struct SQLitePersist {
@@ -44,6 +44,173 @@ impl SQLitePersist {
   }
 }
 
+impl SQLitePersist {
+  fn admin_from_row(row: &Row) -> rusqlite::Result<Admin> {
+    let role: String = row.get(2).unwrap_or(String::new());
+    Ok(Admin {
+      id: row.get(0).unwrap_or(0),
+      name: row.get(1).unwrap_or(String::new()),
+      role: AdminRole::from_name(&role).unwrap_or(AdminRole::Auditor),
+      password_hash: row.get(3).unwrap_or(String::new()),
+      token_hash: row.get(4).unwrap_or(None),
+    })
+  }
+
+  fn user_from_row(row: &Row) -> rusqlite::Result<User> {
+    Ok(User {
+      id: row.get(0).unwrap_or(0),
+      name: row.get(1).unwrap_or(String::new()),
+      enabled: row.get(2).unwrap_or(false),
+      group_id: row.get(3).unwrap_or(None),
+      schedule_id: row.get(4).unwrap_or(None),
+    })
+  }
+
+  fn validity_from_row(row: &Row, first: usize) -> CredentialValidity {
+    CredentialValidity {
+      valid_from: row.get(first).unwrap_or(None),
+      valid_until: row.get(first + 1).unwrap_or(None),
+      max_uses: row.get(first + 2).unwrap_or(None),
+      uses: row.get(first + 3).unwrap_or(0),
+    }
+  }
+
+  fn credential_table(credential_type: CredentialType) -> Result<&'static str, String> {
+    match credential_type {
+      CredentialType::Nfc => Ok("cards"),
+      CredentialType::Fingerprint => Ok("fingerprint"),
+      CredentialType::Bluetooth => Ok("bluetooth"),
+      _ => Err(format!("{} is not a stored credential", credential_type.name()))
+    }
+  }
+
+  fn schedule_windows(conn: &Connection, schedule_id: i32) -> Result<Vec<ScheduleWindow>, String> {
+    let mut stmt = match conn.prepare("SELECT weekday,start_minute,end_minute FROM schedule_windows WHERE schedule_id=?1 ORDER BY weekday,start_minute") {
+      Ok(stmt) => stmt,
+      Err(err) => return Err(format!("Error querying schedule windows: {}", err)),
+    };
+
+    let windows = match stmt.query_map(&[schedule_id], |row| Ok(ScheduleWindow {
+          weekday: row.get(0).unwrap_or(0),
+          start: row.get(1).unwrap_or(0),
+          end: row.get(2).unwrap_or(0),
+      })) {
+      Ok(iter) => iter.filter_map(|window| window.ok()).collect(),
+      Err(err) => return Err(format!("Error querying schedule windows: {}", err)),
+    };
+
+    Ok(windows)
+  }
+
+  fn schedule_windows_replace(conn: &Connection, schedule_id: i32, windows: &Vec<ScheduleWindow>) -> Result<(), String> {
+    if let Err(err) = conn.execute("DELETE FROM schedule_windows WHERE schedule_id=?1", &[schedule_id]) {
+      return Err(format!("Error updating schedule windows: {}", err));
+    }
+
+    for window in windows {
+      if let Err(err) = conn.execute("INSERT INTO schedule_windows (schedule_id, weekday, start_minute, end_minute) VALUES (?1,?2,?3,?4)",
+          &[&schedule_id as &dyn ToSql, &window.weekday, &window.start, &window.end]) {
+        return Err(format!("Error updating schedule windows: {}", err));
+      }
+    }
+
+    Ok(())
+  }
+
+  fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<(), String> {
+    let mut stmt = match conn.prepare(&format!("PRAGMA table_info({})", table)) {
+      Ok(stmt) => stmt,
+      Err(err) => return Err(format!("Error reading table {} info: {}", table, err)),
+    };
+
+    let columns: Vec<String> = match stmt.query_map(NO_PARAMS, |row| row.get::<_, String>(1)) {
+      Ok(iter) => iter.filter_map(|column| column.ok()).collect(),
+      Err(err) => return Err(format!("Error reading table {} info: {}", table, err)),
+    };
+
+    if !columns.iter().any(|name| name == column) {
+      if let Err(err) = conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), NO_PARAMS) {
+        return Err(format!("Error adding column {} to table {}: {}", column, table, err));
+      }
+    }
+
+    Ok(())
+  }
+
+  /* Credentials created before the users table existed only carry a free-form
+   * name. Give each distinct name its own user so nothing loses access. */
+  fn migrate_credential_users(conn: &Connection, table: &str) -> Result<(), String> {
+    let names: Vec<Vec<u8>> = {
+      let mut stmt = match conn.prepare(&format!("SELECT DISTINCT name FROM {} WHERE user_id IS NULL", table)) {
+        Ok(stmt) => stmt,
+        Err(err) => return Err(format!("Error migrating table {}: {}", table, err)),
+      };
+
+      let names = match stmt.query_map(NO_PARAMS, |row| row.get::<_, Vec<u8>>(0)) {
+        Ok(iter) => iter.filter_map(|name| name.ok()).collect(),
+        Err(err) => return Err(format!("Error migrating table {}: {}", table, err)),
+      };
+      names
+    };
+
+    for name in names {
+      let user_name = String::from_utf8_lossy(&name).to_string();
+      let user_id: i32 = match conn.query_row("SELECT id FROM users WHERE name=?1", &[&user_name], |row| row.get(0)) {
+        Ok(id) => id,
+        Err(_err) => {
+          if let Err(err) = conn.execute("INSERT INTO users (name, enabled) VALUES (?1,1)", &[&user_name]) {
+            return Err(format!("Error migrating table {}: {}", table, err));
+          }
+          conn.last_insert_rowid() as i32
+        }
+      };
+
+      if let Err(err) = conn.execute(&format!("UPDATE {} SET user_id=?1 WHERE user_id IS NULL AND name=?2", table),
+          &[&user_id as &dyn ToSql, &name]) {
+        return Err(format!("Error migrating table {}: {}", table, err));
+      }
+    }
+
+    Ok(())
+  }
+
+  fn event_filter_clause(filter: &AccessEventFilter) -> (String, Vec<Box<dyn ToSql>>) {
+    let mut clause: Vec<&str> = Vec::new();
+    let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+
+    if let Some(from) = filter.from {
+      clause.push("timestamp >= ?");
+      params.push(Box::new(from));
+    }
+
+    if let Some(to) = filter.to {
+      clause.push("timestamp <= ?");
+      params.push(Box::new(to));
+    }
+
+    if let Some(ref user) = filter.user {
+      clause.push("user_name = ? COLLATE NOCASE");
+      params.push(Box::new(user.clone()));
+    }
+
+    if let Some(ref reader) = filter.reader {
+      clause.push("reader = ?");
+      params.push(Box::new(reader.clone()));
+    }
+
+    if let Some(decision) = filter.decision {
+      clause.push("decision = ?");
+      params.push(Box::new(String::from(decision.name())));
+    }
+
+    if clause.len() == 0 {
+      return (String::new(), params);
+    }
+
+    (format!(" WHERE {}", clause.join(" AND ")), params)
+  }
+}
+
 impl Persist for SQLitePersist {
   fn init(&mut self, params: &HashMap<String,String>) -> Result<(), String> {
     let path = Path::new(&params["DATA_PATH"]).join("acontrol.db");
@@ -53,52 +220,752 @@ impl Persist for SQLitePersist {
     };
 
     if let Some(ref conn) = self.conn {
-      if let Err(err) = conn.execute(
-          "create table if not exists cards (
-               id integer primary key,
-               uuid varchar(10) not null,
-               name varchar(255) not null
-           )",
-          NO_PARAMS,
-      ) {
-        return Err(format!("Error creating table cards: {}",err));
+      if let Err(err) = conn.execute(
+          "create table if not exists cards (
+               id integer primary key,
+               uuid varchar(10) not null,
+               name varchar(255) not null
+           )",
+          NO_PARAMS,
+      ) {
+        return Err(format!("Error creating table cards: {}",err));
+      }
+
+      if let Err(err) = conn.execute(
+          "create table if not exists fingerprint (
+               id integer primary key,
+               pos integer not null,
+               name varchar(255) not null
+           )",
+          NO_PARAMS,
+      ) {
+        return Err(format!("Error creating table fingerprint: {}",err));
+      }
+
+      if let Err(err) = conn.execute(
+          "create table if not exists fingerprint_templates (
+               pos integer primary key,
+               template blob not null,
+               created_at integer not null
+           )",
+          NO_PARAMS,
+      ) {
+        return Err(format!("Error creating table fingerprint_templates: {}",err));
+      }
+
+      if let Err(err) = conn.execute(
+        "create table if not exists bluetooth (
+             id integer primary key,
+             addr varchar(255) not null,
+             name varchar(255) not null
+         )",
+        NO_PARAMS,
+    ) {
+      return Err(format!("Error creating table bluetooth: {}",err));
+    }
+
+      if let Err(err) = conn.execute(
+          "create table if not exists admins (
+               id integer primary key,
+               name varchar(255) not null unique,
+               role varchar(16) not null,
+               password_hash varchar(255) not null,
+               token_hash varchar(64) unique
+           )",
+          NO_PARAMS,
+      ) {
+        return Err(format!("Error creating table admins: {}",err));
+      }
+
+      if let Err(err) = conn.execute(
+          "create table if not exists users (
+               id integer primary key,
+               name varchar(255) not null,
+               enabled integer not null default 1
+           )",
+          NO_PARAMS,
+      ) {
+        return Err(format!("Error creating table users: {}",err));
+      }
+
+      if let Err(err) = conn.execute(
+          "create table if not exists schedules (
+               id integer primary key,
+               name varchar(255) not null
+           )",
+          NO_PARAMS,
+      ) {
+        return Err(format!("Error creating table schedules: {}",err));
+      }
+
+      if let Err(err) = conn.execute(
+          "create table if not exists schedule_windows (
+               id integer primary key,
+               schedule_id integer not null references schedules(id) on delete cascade,
+               weekday integer not null,
+               start_minute integer not null,
+               end_minute integer not null
+           )",
+          NO_PARAMS,
+      ) {
+        return Err(format!("Error creating table schedule_windows: {}",err));
+      }
+
+      if let Err(err) = conn.execute(
+          "create table if not exists user_groups (
+               id integer primary key,
+               name varchar(255) not null,
+               schedule_id integer references schedules(id) on delete set null
+           )",
+          NO_PARAMS,
+      ) {
+        return Err(format!("Error creating table user_groups: {}",err));
+      }
+
+      if let Err(err) = conn.execute(
+          "create table if not exists holidays (
+               id integer primary key,
+               date varchar(10) not null,
+               name varchar(255) not null
+           )",
+          NO_PARAMS,
+      ) {
+        return Err(format!("Error creating table holidays: {}",err));
+      }
+
+      SQLitePersist::add_column_if_missing(conn, "users", "group_id", "integer references user_groups(id) on delete set null")?;
+      SQLitePersist::add_column_if_missing(conn, "users", "schedule_id", "integer references schedules(id) on delete set null")?;
+
+      if let Err(err) = conn.execute_batch("PRAGMA foreign_keys = ON") {
+        return Err(format!("Error enabling foreign keys: {}",err));
+      }
+
+      for table in ["cards", "fingerprint", "bluetooth"].iter() {
+        SQLitePersist::add_column_if_missing(conn, table, "user_id", "integer references users(id)")?;
+        SQLitePersist::migrate_credential_users(conn, table)?;
+        SQLitePersist::add_column_if_missing(conn, table, "valid_from", "integer")?;
+        SQLitePersist::add_column_if_missing(conn, table, "valid_until", "integer")?;
+        SQLitePersist::add_column_if_missing(conn, table, "max_uses", "integer")?;
+        SQLitePersist::add_column_if_missing(conn, table, "uses", "integer not null default 0")?;
+      }
+
+      if let Err(err) = conn.execute(
+          "create table if not exists access_events (
+               id integer primary key,
+               timestamp integer not null,
+               credential_type varchar(16) not null,
+               credential_id varchar(255) not null,
+               user_name varchar(255) not null,
+               decision varchar(16) not null,
+               reason varchar(255) not null
+           )",
+          NO_PARAMS,
+      ) {
+        return Err(format!("Error creating table access_events: {}",err));
+      }
+
+      SQLitePersist::add_column_if_missing(conn, "access_events", "reader", "varchar(64) not null default ''")?;
+      SQLitePersist::add_column_if_missing(conn, "access_events", "card_technology", "varchar(16) not null default ''")?;
+      SQLitePersist::add_column_if_missing(conn, "access_events", "card_atqa", "varchar(8) not null default ''")?;
+      SQLitePersist::add_column_if_missing(conn, "access_events", "card_sak", "integer")?;
+
+      if let Err(err) = conn.execute(
+          "create index if not exists access_events_timestamp on access_events (timestamp)",
+          NO_PARAMS,
+      ) {
+        return Err(format!("Error creating index access_events_timestamp: {}",err));
+      }
+    }
+
+    Ok(())
+  }
+
+  fn unload(&mut self) -> Result<(), String> {
+    acontrol_system_log!(LogType::Info, "Persistence driver unloading");
+    Ok(())
+  }
+
+  fn admin_add(&mut self, name: &str, password_hash: &str, role: AdminRole) -> Result<Admin, String> {
+    if let Some(ref conn) = self.conn {
+      if let Err(err) = conn.execute("INSERT INTO admins (name, role, password_hash) VALUES (?1,?2,?3)",
+          &[&name, &role.name(), &password_hash],
+      ) {
+        return Err(format!("Error inserting admin to the database: {}", err));
+      }
+
+      return Ok(Admin { id: conn.last_insert_rowid() as i32, name: String::from(name), role: role, password_hash: String::from(password_hash), token_hash: None });
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn admin_find_by_name(&mut self, name: &str) -> Result<Admin, String> {
+    if let Some(ref conn) = self.conn {
+      return match conn.query_row("SELECT id,name,role,password_hash,token_hash FROM admins where name=?1", &[&name], SQLitePersist::admin_from_row) {
+        Ok(admin) => Ok(admin),
+        Err(_err) => Err(format!("{}","Admin Not Found"))
+      };
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn admin_find_by_token(&mut self, token_hash: &str) -> Result<Admin, String> {
+    if let Some(ref conn) = self.conn {
+      return match conn.query_row("SELECT id,name,role,password_hash,token_hash FROM admins where token_hash=?1", &[&token_hash], SQLitePersist::admin_from_row) {
+        Ok(admin) => Ok(admin),
+        Err(_err) => Err(format!("{}","Admin Not Found"))
+      };
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn admin_list(&mut self) -> Result<Vec<Admin>, String> {
+
+    let mut ret: Vec<Admin> = Vec::new();
+
+    if let Some(ref conn) = self.conn {
+      let mut stmt = conn
+        .prepare("SELECT id,name,role,password_hash,token_hash FROM admins")
+        .unwrap();
+
+      let admin_iter = stmt
+        .query_map(NO_PARAMS, SQLitePersist::admin_from_row).unwrap();
+
+      for admin in admin_iter {
+        ret.push(admin.unwrap());
+      }
+      return Ok(ret);
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn admin_set_token(&mut self, id: i32, token_hash: Option<&str>) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      match conn.execute("UPDATE admins SET token_hash=?1 WHERE id=?2",
+          &[&token_hash as &dyn ToSql, &id],
+      ) {
+        Ok(0) => return Err(format!("{}","Admin Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error updating admin token: {}", err)),
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(())
+  }
+
+  fn admin_delete(&mut self, id: i32) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      match conn.execute("DELETE FROM admins WHERE id=?1", &[id]) {
+        Ok(0) => return Err(format!("{}","Admin Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error deleting admin: {}", err)),
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(())
+  }
+
+  fn user_add(&mut self, name: &str) -> Result<User, String> {
+    if let Some(ref conn) = self.conn {
+      if let Err(err) = conn.execute("INSERT INTO users (name, enabled) VALUES (?1,1)",
+          &[&name],
+      ) {
+        return Err(format!("Error inserting user to the database: {}", err));
+      }
+
+      return Ok(User { id: conn.last_insert_rowid() as i32, name: String::from(name), enabled: true, group_id: None, schedule_id: None });
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn user_find(&mut self, id: i32) -> Result<User, String> {
+    if let Some(ref conn) = self.conn {
+      return match conn.query_row("SELECT id,name,enabled,group_id,schedule_id FROM users where id=?1", &[id], SQLitePersist::user_from_row) {
+        Ok(user) => Ok(user),
+        Err(_err) => Err(format!("{}","User Not Found"))
+      };
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn user_find_by_name(&mut self, name: &str) -> Result<User, String> {
+    if let Some(ref conn) = self.conn {
+      return match conn.query_row("SELECT id,name,enabled,group_id,schedule_id FROM users where name=?1", &[&name], SQLitePersist::user_from_row) {
+        Ok(user) => Ok(user),
+        Err(_err) => Err(format!("{}","User Not Found"))
+      };
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn user_list(&mut self) -> Result<Vec<User>, String> {
+
+    let mut ret: Vec<User> = Vec::new();
+
+    if let Some(ref conn) = self.conn {
+      let mut stmt = conn
+        .prepare("SELECT id,name,enabled,group_id,schedule_id FROM users")
+        .unwrap();
+
+      let user_iter = stmt
+        .query_map(NO_PARAMS, SQLitePersist::user_from_row).unwrap();
+
+      for user in user_iter {
+        ret.push(user.unwrap());
+      }
+      return Ok(ret);
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn user_update(&mut self, user: &User) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      match conn.execute("UPDATE users SET name=?1, enabled=?2, group_id=?3, schedule_id=?4 WHERE id=?5",
+          &[&user.name as &dyn ToSql, &user.enabled, &user.group_id, &user.schedule_id, &user.id],
+      ) {
+        Ok(0) => return Err(format!("{}","User Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error updating user: {}", err)),
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(())
+  }
+
+  fn user_set_enabled(&mut self, id: i32, enabled: bool) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      match conn.execute("UPDATE users SET enabled=?1 WHERE id=?2",
+          &[&enabled as &dyn ToSql, &id],
+      ) {
+        Ok(0) => return Err(format!("{}","User Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error updating user: {}", err)),
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(())
+  }
+
+  fn user_delete(&mut self, id: i32) -> Result<(), String> {
+    if let Some(ref mut conn) = self.conn {
+      let tx = match conn.transaction() {
+        Ok(tx) => tx,
+        Err(err) => return Err(format!("Error deleting user: {}", err)),
+      };
+
+      for table in ["cards", "fingerprint", "bluetooth"].iter() {
+        if let Err(err) = tx.execute(&format!("DELETE FROM {} WHERE user_id=?1", table), &[id]) {
+          return Err(format!("Error deleting user credentials from {}: {}", table, err));
+        }
+      }
+
+      match tx.execute("DELETE FROM users WHERE id=?1", &[id]) {
+        Ok(0) => return Err(format!("{}","User Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error deleting user: {}", err)),
+      }
+
+      if let Err(err) = tx.commit() {
+        return Err(format!("Error deleting user: {}", err));
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(())
+  }
+
+  fn user_cards(&mut self, id: i32) -> Result<Vec<Card>, String> {
+
+    let mut ret: Vec<Card> = Vec::new();
+
+    if let Some(ref conn) = self.conn {
+      let mut stmt = conn
+        .prepare("SELECT id,uuid,name,user_id,valid_from,valid_until,max_uses,uses FROM cards where user_id=?1")
+        .unwrap();
+
+      let card_iter = stmt
+        .query_map(&[id], |row| Ok(Card {
+            id: row.get(0).unwrap_or(0),
+            uuid: row.get(1).unwrap_or(Vec::new()),
+            name: row.get(2).unwrap_or(Vec::new()),
+            user_id: row.get(3).unwrap_or(None),
+            validity: SQLitePersist::validity_from_row(row, 4),
+        })).unwrap();
+
+      for card in card_iter {
+        ret.push(card.unwrap());
+      }
+      return Ok(ret);
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn user_fingerprints(&mut self, id: i32) -> Result<Vec<Fingerprint>, String> {
+
+    let mut ret: Vec<Fingerprint> = Vec::new();
+
+    if let Some(ref conn) = self.conn {
+      let mut stmt = conn
+        .prepare("SELECT id,pos,name,user_id,valid_from,valid_until,max_uses,uses FROM fingerprint where user_id=?1")
+        .unwrap();
+
+      let fingerprint_iter = stmt
+        .query_map(&[id], |row| Ok(Fingerprint {
+            id: row.get(0).unwrap_or(0),
+            pos: row.get(1).unwrap_or(0),
+            name: row.get(2).unwrap_or(Vec::new()),
+            user_id: row.get(3).unwrap_or(None),
+            validity: SQLitePersist::validity_from_row(row, 4),
+        })).unwrap();
+
+      for fingerprint in fingerprint_iter {
+        ret.push(fingerprint.unwrap());
+      }
+      return Ok(ret);
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn user_bluetooth(&mut self, id: i32) -> Result<Vec<Bluetooth>, String> {
+
+    let mut ret: Vec<Bluetooth> = Vec::new();
+
+    if let Some(ref conn) = self.conn {
+      let mut stmt = conn
+        .prepare("SELECT id,addr,name,user_id,valid_from,valid_until,max_uses,uses FROM bluetooth where user_id=?1")
+        .unwrap();
+
+      let bluetooth_iter = stmt
+        .query_map(&[id], |row| Ok(Bluetooth {
+            id: row.get(0).unwrap_or(0),
+            addr: row.get(1).unwrap_or(Vec::new()),
+            name: row.get(2).unwrap_or(Vec::new()),
+            user_id: row.get(3).unwrap_or(None),
+            validity: SQLitePersist::validity_from_row(row, 4),
+        })).unwrap();
+
+      for bluetooth in bluetooth_iter {
+        ret.push(bluetooth.unwrap());
+      }
+      return Ok(ret);
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn user_schedule(&mut self, id: i32) -> Result<Option<Schedule>, String> {
+    let schedule_id: Option<i32> = if let Some(ref conn) = self.conn {
+      match conn.query_row("SELECT coalesce(users.schedule_id, user_groups.schedule_id) FROM users LEFT JOIN user_groups ON user_groups.id = users.group_id WHERE users.id=?1",
+          &[id], |row| row.get(0)) {
+        Ok(schedule_id) => schedule_id,
+        Err(_err) => return Err(format!("{}","User Not Found"))
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    };
+
+    match schedule_id {
+      Some(schedule_id) => self.schedule_find(schedule_id).map(|schedule| Some(schedule)),
+      None => Ok(None)
+    }
+  }
+
+  fn group_add(&mut self, name: &str) -> Result<Group, String> {
+    if let Some(ref conn) = self.conn {
+      if let Err(err) = conn.execute("INSERT INTO user_groups (name) VALUES (?1)",
+          &[&name],
+      ) {
+        return Err(format!("Error inserting group to the database: {}", err));
+      }
+
+      return Ok(Group { id: conn.last_insert_rowid() as i32, name: String::from(name), schedule_id: None });
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn group_find(&mut self, id: i32) -> Result<Group, String> {
+    if let Some(ref conn) = self.conn {
+      return match conn.query_row("SELECT id,name,schedule_id FROM user_groups where id=?1", &[id], |row| Ok(Group {
+            id: row.get(0).unwrap_or(0),
+            name: row.get(1).unwrap_or(String::new()),
+            schedule_id: row.get(2).unwrap_or(None),
+        })) {
+        Ok(group) => Ok(group),
+        Err(_err) => Err(format!("{}","Group Not Found"))
+      };
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn group_list(&mut self) -> Result<Vec<Group>, String> {
+
+    let mut ret: Vec<Group> = Vec::new();
+
+    if let Some(ref conn) = self.conn {
+      let mut stmt = conn
+        .prepare("SELECT id,name,schedule_id FROM user_groups")
+        .unwrap();
+
+      let group_iter = stmt
+        .query_map(NO_PARAMS, |row| Ok(Group {
+            id: row.get(0).unwrap_or(0),
+            name: row.get(1).unwrap_or(String::new()),
+            schedule_id: row.get(2).unwrap_or(None),
+        })).unwrap();
+
+      for group in group_iter {
+        ret.push(group.unwrap());
+      }
+      return Ok(ret);
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn group_update(&mut self, group: &Group) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      match conn.execute("UPDATE user_groups SET name=?1, schedule_id=?2 WHERE id=?3",
+          &[&group.name as &dyn ToSql, &group.schedule_id, &group.id],
+      ) {
+        Ok(0) => return Err(format!("{}","Group Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error updating group: {}", err)),
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(())
+  }
+
+  fn group_delete(&mut self, id: i32) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      /* The foreign key would set the members' group to null, which lifts
+       * their schedule restriction. */
+      let members: Result<i64, rusqlite::Error> = conn.query_row(
+        "SELECT count(*) FROM users WHERE group_id=?1", &[id], |row| row.get(0));
+
+      match members {
+        Ok(0) => {},
+        Ok(users) => return Err(format!("Group still has {} users", users)),
+        Err(err) => return Err(format!("Error deleting group: {}", err)),
+      }
+
+      match conn.execute("DELETE FROM user_groups WHERE id=?1", &[id]) {
+        Ok(0) => return Err(format!("{}","Group Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error deleting group: {}", err)),
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(())
+  }
+
+  fn schedule_add(&mut self, name: &str, windows: &Vec<ScheduleWindow>) -> Result<Schedule, String> {
+    if let Some(ref mut conn) = self.conn {
+      let tx = match conn.transaction() {
+        Ok(tx) => tx,
+        Err(err) => return Err(format!("Error inserting schedule to the database: {}", err)),
+      };
+
+      if let Err(err) = tx.execute("INSERT INTO schedules (name) VALUES (?1)", &[&name]) {
+        return Err(format!("Error inserting schedule to the database: {}", err));
+      }
+
+      let id = tx.last_insert_rowid() as i32;
+      SQLitePersist::schedule_windows_replace(&tx, id, windows)?;
+
+      if let Err(err) = tx.commit() {
+        return Err(format!("Error inserting schedule to the database: {}", err));
+      }
+
+      return Ok(Schedule { id: id, name: String::from(name), windows: windows.clone() });
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn schedule_find(&mut self, id: i32) -> Result<Schedule, String> {
+    if let Some(ref conn) = self.conn {
+      let name: String = match conn.query_row("SELECT name FROM schedules where id=?1", &[id], |row| row.get(0)) {
+        Ok(name) => name,
+        Err(_err) => return Err(format!("{}","Schedule Not Found"))
+      };
+
+      return Ok(Schedule { id: id, name: name, windows: SQLitePersist::schedule_windows(conn, id)? });
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn schedule_list(&mut self) -> Result<Vec<Schedule>, String> {
+
+    let mut ret: Vec<Schedule> = Vec::new();
+
+    if let Some(ref conn) = self.conn {
+      let mut stmt = conn
+        .prepare("SELECT id,name FROM schedules")
+        .unwrap();
+
+      let schedule_iter = stmt
+        .query_map(NO_PARAMS, |row| Ok(Schedule {
+            id: row.get(0).unwrap_or(0),
+            name: row.get(1).unwrap_or(String::new()),
+            windows: Vec::new(),
+        })).unwrap();
+
+      for schedule in schedule_iter {
+        let mut schedule = schedule.unwrap();
+        schedule.windows = SQLitePersist::schedule_windows(conn, schedule.id)?;
+        ret.push(schedule);
       }
+      return Ok(ret);
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
 
-      if let Err(err) = conn.execute(
-          "create table if not exists fingerprint (
-               id integer primary key,
-               pos integer not null,
-               name varchar(255) not null
-           )",
-          NO_PARAMS,
-      ) {
-        return Err(format!("Error creating table fingerprint: {}",err));
+  fn schedule_update(&mut self, schedule: &Schedule) -> Result<(), String> {
+    if let Some(ref mut conn) = self.conn {
+      let tx = match conn.transaction() {
+        Ok(tx) => tx,
+        Err(err) => return Err(format!("Error updating schedule: {}", err)),
+      };
+
+      match tx.execute("UPDATE schedules SET name=?1 WHERE id=?2", &[&schedule.name as &dyn ToSql, &schedule.id]) {
+        Ok(0) => return Err(format!("{}","Schedule Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error updating schedule: {}", err)),
       }
 
-      if let Err(err) = conn.execute(
-        "create table if not exists bluetooth (
-             id integer primary key,
-             addr varchar(255) not null,
-             name varchar(255) not null
-         )",
-        NO_PARAMS,
-    ) {
-      return Err(format!("Error creating table bluetooth: {}",err));
+      SQLitePersist::schedule_windows_replace(&tx, schedule.id, &schedule.windows)?;
+
+      if let Err(err) = tx.commit() {
+        return Err(format!("Error updating schedule: {}", err));
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
     }
+
+    Ok(())
+  }
+
+  fn schedule_delete(&mut self, id: i32) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      /* The foreign keys would set these to null, and no schedule means
+       * access at any time. */
+      let references: Result<(i64, i64), rusqlite::Error> = conn.query_row(
+        "SELECT (SELECT count(*) FROM users WHERE schedule_id=?1), (SELECT count(*) FROM user_groups WHERE schedule_id=?1)",
+        &[id], |row| Ok((row.get(0)?, row.get(1)?)));
+
+      match references {
+        Ok((0, 0)) => {},
+        Ok((users, groups)) => return Err(format!("Schedule is still assigned to {} users and {} groups", users, groups)),
+        Err(err) => return Err(format!("Error deleting schedule: {}", err)),
+      }
+
+      match conn.execute("DELETE FROM schedules WHERE id=?1", &[id]) {
+        Ok(0) => return Err(format!("{}","Schedule Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error deleting schedule: {}", err)),
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
     }
 
     Ok(())
   }
 
-  fn unload(&mut self) -> Result<(), String> {
-    acontrol_system_log!(LogType::Info, "Persistence driver unloading");
+  fn holiday_add(&mut self, date: &str, name: &str) -> Result<Holiday, String> {
+    if let Some(ref conn) = self.conn {
+      if let Err(err) = conn.execute("INSERT INTO holidays (date, name) VALUES (?1,?2)",
+          &[&date, &name],
+      ) {
+        return Err(format!("Error inserting holiday to the database: {}", err));
+      }
+
+      return Ok(Holiday { id: conn.last_insert_rowid() as i32, date: String::from(date), name: String::from(name) });
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn holiday_list(&mut self) -> Result<Vec<Holiday>, String> {
+
+    let mut ret: Vec<Holiday> = Vec::new();
+
+    if let Some(ref conn) = self.conn {
+      let mut stmt = conn
+        .prepare("SELECT id,date,name FROM holidays ORDER BY date")
+        .unwrap();
+
+      let holiday_iter = stmt
+        .query_map(NO_PARAMS, |row| Ok(Holiday {
+            id: row.get(0).unwrap_or(0),
+            date: row.get(1).unwrap_or(String::new()),
+            name: row.get(2).unwrap_or(String::new()),
+        })).unwrap();
+
+      for holiday in holiday_iter {
+        ret.push(holiday.unwrap());
+      }
+      return Ok(ret);
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn holiday_delete(&mut self, id: i32) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      match conn.execute("DELETE FROM holidays WHERE id=?1", &[id]) {
+        Ok(0) => return Err(format!("{}","Holiday Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error deleting holiday: {}", err)),
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
     Ok(())
   }
 
-  fn nfc_add(&mut self, uuid: &Vec<u8>, name: &Vec<u8>)-> Result<(), String> {
+  fn holiday_is(&mut self, date: &str) -> Result<bool, String> {
+    if let Some(ref conn) = self.conn {
+      return match conn.query_row("SELECT count(*) FROM holidays WHERE date=?1", &[&date], |row| row.get::<_, u32>(0)) {
+        Ok(count) => Ok(count > 0),
+        Err(err) => Err(format!("Error querying holidays: {}", err)),
+      };
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn nfc_add(&mut self, uuid: &Vec<u8>, name: &Vec<u8>, user_id: i32)-> Result<(), String> {
     if let Some(ref conn) = self.conn {
-      if let Err(err) = conn.execute("INSERT INTO cards (uuid, name) VALUES (?1,?2)",
-          &[uuid as &dyn ToSql, name as &dyn ToSql],
+      if let Err(err) = conn.execute("INSERT INTO cards (uuid, name, user_id) VALUES (?1,?2,?3)",
+          &[uuid as &dyn ToSql, name as &dyn ToSql, &user_id],
       ) {
         return Err(format!("Error inserting card to the database: {}", err));
       }
@@ -113,7 +980,7 @@ impl Persist for SQLitePersist {
 
     if let Some(ref conn) = self.conn {
       let mut stmt = conn
-        .prepare("SELECT id,uuid,name FROM cards")
+        .prepare("SELECT id,uuid,name,user_id,valid_from,valid_until,max_uses,uses FROM cards")
         .unwrap();
 
       let card_iter = stmt
@@ -121,6 +988,8 @@ impl Persist for SQLitePersist {
             id: row.get(0).unwrap_or(0),
             uuid: row.get(1).unwrap_or(Vec::new()),
             name: row.get(2).unwrap_or(Vec::new()),
+            user_id: row.get(3).unwrap_or(None),
+            validity: SQLitePersist::validity_from_row(row, 4),
         })).unwrap();
 
       for card in card_iter {
@@ -136,7 +1005,7 @@ impl Persist for SQLitePersist {
 
     if let Some(ref conn) = self.conn {
       let mut stmt = conn
-        .prepare("SELECT id,uuid,name FROM cards where uuid=?1")
+        .prepare("SELECT id,uuid,name,user_id,valid_from,valid_until,max_uses,uses FROM cards where uuid=?1")
         .unwrap();
 
       let card_iter = stmt
@@ -144,6 +1013,8 @@ impl Persist for SQLitePersist {
             id: row.get(0).unwrap_or(0),
             uuid: row.get(1).unwrap_or(Vec::new()),
             name: row.get(2).unwrap_or(Vec::new()),
+            user_id: row.get(3).unwrap_or(None),
+            validity: SQLitePersist::validity_from_row(row, 4),
         })).unwrap();
 
       for card in card_iter {
@@ -156,14 +1027,24 @@ impl Persist for SQLitePersist {
     Err(format!("{}","Card Not Found"))
   }
 
-  fn nfc_delete(&mut self, _uuid: &Vec<u8>) -> Result<(), String> {
+  fn nfc_delete(&mut self, uuid: &Vec<u8>) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      match conn.execute("DELETE FROM cards WHERE uuid=?1", &[uuid as &dyn ToSql]) {
+        Ok(0) => return Err(format!("{}","Card Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error deleting card from the database: {}", err)),
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
     Ok(())
   }
 
-  fn fingerprint_add(&mut self, pos: i32, name: &Vec<u8>) -> Result<(), String> {
+  fn fingerprint_add(&mut self, pos: i32, name: &Vec<u8>, user_id: i32) -> Result<(), String> {
     if let Some(ref conn) = self.conn {
-      if let Err(err) = conn.execute("INSERT INTO fingerprint (pos, name) VALUES (?1,?2)",
-          &[&pos, name as &dyn ToSql],
+      if let Err(err) = conn.execute("INSERT INTO fingerprint (pos, name, user_id) VALUES (?1,?2,?3)",
+          &[&pos, name as &dyn ToSql, &user_id],
       ) {
         return Err(format!("Error inserting fingerprint to the database: {}", err));
       }
@@ -176,7 +1057,7 @@ impl Persist for SQLitePersist {
 
     if let Some(ref conn) = self.conn {
       let mut stmt = conn
-        .prepare("SELECT id,pos,name FROM fingerprint where pos=?1")
+        .prepare("SELECT id,pos,name,user_id,valid_from,valid_until,max_uses,uses FROM fingerprint where pos=?1")
         .unwrap();
 
       let fingerprint_iter = stmt
@@ -184,6 +1065,8 @@ impl Persist for SQLitePersist {
             id: row.get(0).unwrap_or(0),
             pos: row.get(1).unwrap_or(0),
             name: row.get(2).unwrap_or(Vec::new()),
+            user_id: row.get(3).unwrap_or(None),
+            validity: SQLitePersist::validity_from_row(row, 4),
         })).unwrap();
 
       for fingerprint in fingerprint_iter {
@@ -202,14 +1085,16 @@ impl Persist for SQLitePersist {
 
     if let Some(ref conn) = self.conn {
       let mut stmt = conn
-        .prepare("SELECT id,pos,name FROM fingerprint")
+        .prepare("SELECT id,pos,name,user_id,valid_from,valid_until,max_uses,uses FROM fingerprint")
         .unwrap();
 
       let fingerprint_iter = stmt
         .query_map(NO_PARAMS, |row| Ok(Fingerprint {
             id: row.get(0).unwrap_or(0),
             pos: row.get(1).unwrap_or(0),
-            name: row.get(2).unwrap_or(Vec::new())
+            name: row.get(2).unwrap_or(Vec::new()),
+            user_id: row.get(3).unwrap_or(None),
+            validity: SQLitePersist::validity_from_row(row, 4),
         })).unwrap();
 
       for fingerprint in fingerprint_iter {
@@ -221,17 +1106,100 @@ impl Persist for SQLitePersist {
     }
   }
 
-  fn fingerprint_delete(&mut self, _pos: i32) -> Result<(), String> {
+  fn fingerprint_delete(&mut self, pos: i32) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      match conn.execute("DELETE FROM fingerprint WHERE pos=?1", &[&pos as &dyn ToSql]) {
+        Ok(0) => return Err(format!("{}","Fingerprint Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error deleting fingerprint from the database: {}", err)),
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(())
+  }
+
+
+  fn fingerprint_delete_all(&mut self) -> Result<(), String> {
+    if let Some(ref mut conn) = self.conn {
+      let tx = match conn.transaction() {
+        Ok(tx) => tx,
+        Err(err) => return Err(format!("Error deleting all fingerprints: {}", err)),
+      };
+
+      for table in ["fingerprint", "fingerprint_templates"].iter() {
+        if let Err(err) = tx.execute(&format!("DELETE FROM {}", table), NO_PARAMS) {
+          return Err(format!("Error deleting all fingerprints from {}: {}", table, err));
+        }
+      }
+
+      if let Err(err) = tx.commit() {
+        return Err(format!("Error deleting all fingerprints: {}", err));
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(())
+  }
+
+
+  fn fingerprint_template_save(&mut self, template: &FingerprintTemplate) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      if let Err(err) = conn.execute("INSERT OR REPLACE INTO fingerprint_templates (pos, template, created_at) VALUES (?1,?2,?3)",
+          &[&template.pos as &dyn ToSql, &template.template, &template.created_at],
+      ) {
+        return Err(format!("Error inserting fingerprint template to the database: {}", err));
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
     Ok(())
   }
 
+  fn fingerprint_template_list(&mut self) -> Result<Vec<FingerprintTemplate>, String> {
+
+    let mut ret: Vec<FingerprintTemplate> = Vec::new();
+
+    if let Some(ref conn) = self.conn {
+      let mut stmt = conn
+        .prepare("SELECT pos,template,created_at FROM fingerprint_templates ORDER BY pos")
+        .unwrap();
+
+      let template_iter = stmt
+        .query_map(NO_PARAMS, |row| Ok(FingerprintTemplate {
+            pos: row.get(0).unwrap_or(0),
+            template: row.get(1).unwrap_or(Vec::new()),
+            created_at: row.get(2).unwrap_or(0),
+        })).unwrap();
+
+      for template in template_iter {
+        ret.push(template.unwrap());
+      }
+      return Ok(ret);
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
 
+  fn fingerprint_template_delete(&mut self, pos: i32) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      if let Err(err) = conn.execute("DELETE FROM fingerprint_templates WHERE pos=?1", &[&pos as &dyn ToSql]) {
+        return Err(format!("Error deleting fingerprint template from the database: {}", err));
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
 
+    Ok(())
+  }
 
-  fn bluetooth_add(&mut self, addr: &Vec<u8>, name: &Vec<u8>) -> Result<(), String> {
+  fn bluetooth_add(&mut self, addr: &Vec<u8>, name: &Vec<u8>, user_id: i32) -> Result<(), String> {
     if let Some(ref conn) = self.conn {
-      if let Err(err) = conn.execute("INSERT INTO bluetooth (addr, name) VALUES (?1,?2)",
-          &[addr as &dyn ToSql, name as &dyn ToSql],
+      if let Err(err) = conn.execute("INSERT INTO bluetooth (addr, name, user_id) VALUES (?1,?2,?3)",
+          &[addr as &dyn ToSql, name as &dyn ToSql, &user_id],
       ) {
         return Err(format!("Error inserting bluetooth device to the database: {}", err));
       }
@@ -244,7 +1212,7 @@ impl Persist for SQLitePersist {
 
     if let Some(ref conn) = self.conn {
       let mut stmt = conn
-        .prepare("SELECT id,addr,name FROM bluetooth where addr=?1")
+        .prepare("SELECT id,addr,name,user_id,valid_from,valid_until,max_uses,uses FROM bluetooth where addr=?1")
         .unwrap();
 
       let bluetooth_iter = stmt
@@ -252,6 +1220,8 @@ impl Persist for SQLitePersist {
             id: row.get(0).unwrap_or(0),
             addr: row.get(1).unwrap_or(Vec::new()),
             name: row.get(2).unwrap_or(Vec::new()),
+            user_id: row.get(3).unwrap_or(None),
+            validity: SQLitePersist::validity_from_row(row, 4),
         })).unwrap();
 
       for bluetooth in bluetooth_iter {
@@ -270,14 +1240,16 @@ impl Persist for SQLitePersist {
 
     if let Some(ref conn) = self.conn {
       let mut stmt = conn
-        .prepare("SELECT id,addr,name FROM bluetooth")
+        .prepare("SELECT id,addr,name,user_id,valid_from,valid_until,max_uses,uses FROM bluetooth")
         .unwrap();
 
       let bluetooth_iter = stmt
         .query_map(NO_PARAMS, |row| Ok(Bluetooth {
             id: row.get(0).unwrap_or(0),
             addr: row.get(1).unwrap_or(Vec::new()),
-            name: row.get(2).unwrap_or(Vec::new())
+            name: row.get(2).unwrap_or(Vec::new()),
+            user_id: row.get(3).unwrap_or(None),
+            validity: SQLitePersist::validity_from_row(row, 4),
         })).unwrap();
 
       for bluetooth in bluetooth_iter {
@@ -289,9 +1261,193 @@ impl Persist for SQLitePersist {
     }
   }
 
-  fn bluetooth_delete(&mut self, _addr: &Vec<u8>) -> Result<(), String> {
+  fn bluetooth_delete(&mut self, addr: &Vec<u8>) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      match conn.execute("DELETE FROM bluetooth WHERE addr=?1", &[addr as &dyn ToSql]) {
+        Ok(0) => return Err(format!("{}","Bluetooth Device Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error deleting bluetooth device from the database: {}", err)),
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(())
+  }
+
+  fn credential_validity(&mut self, credential_type: CredentialType, id: i32) -> Result<CredentialValidity, String> {
+    let table = SQLitePersist::credential_table(credential_type)?;
+
+    if let Some(ref conn) = self.conn {
+      match conn.query_row(&format!("SELECT valid_from,valid_until,max_uses,uses FROM {} WHERE id=?1", table), &[id],
+          |row| Ok(SQLitePersist::validity_from_row(row, 0))) {
+        Ok(validity) => Ok(validity),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(format!("{}","Credential Not Found")),
+        Err(err) => Err(format!("Error querying credential validity: {}", err)),
+      }
+    } else {
+      Err(format!("{}","Database not connected"))
+    }
+  }
+
+  fn credential_set_validity(&mut self, credential_type: CredentialType, id: i32, validity: &CredentialValidity) -> Result<(), String> {
+    let table = SQLitePersist::credential_table(credential_type)?;
+
+    if let Some(ref conn) = self.conn {
+      match conn.execute(&format!("UPDATE {} SET valid_from=?1, valid_until=?2, max_uses=?3 WHERE id=?4", table),
+          &[&validity.valid_from as &dyn ToSql, &validity.valid_until, &validity.max_uses, &id],
+      ) {
+        Ok(0) => return Err(format!("{}","Credential Not Found")),
+        Ok(_) => {},
+        Err(err) => return Err(format!("Error updating credential validity: {}", err)),
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(())
+  }
+
+  fn credential_use(&mut self, credential_type: CredentialType, id: i32) -> Result<(), String> {
+    let table = SQLitePersist::credential_table(credential_type)?;
+
+    if let Some(ref conn) = self.conn {
+      if let Err(err) = conn.execute(&format!("UPDATE {} SET uses=uses+1 WHERE id=?1", table), &[id]) {
+        return Err(format!("Error updating credential uses: {}", err));
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(())
+  }
+
+  fn credential_cleanup(&mut self, now: i64) -> Result<Vec<Fingerprint>, String> {
+    let expired = "(valid_until IS NOT NULL AND valid_until < ?1) OR (max_uses IS NOT NULL AND uses >= max_uses)";
+    let mut fingerprints: Vec<Fingerprint> = Vec::new();
+
+    if let Some(ref mut conn) = self.conn {
+      let tx = match conn.transaction() {
+        Ok(tx) => tx,
+        Err(err) => return Err(format!("Error cleaning up credentials: {}", err)),
+      };
+
+      {
+        let mut stmt = match tx.prepare(&format!("SELECT id,pos,name,user_id,valid_from,valid_until,max_uses,uses FROM fingerprint WHERE {}", expired)) {
+          Ok(stmt) => stmt,
+          Err(err) => return Err(format!("Error cleaning up credentials: {}", err)),
+        };
+
+        match stmt.query_map(&[now], |row| Ok(Fingerprint {
+              id: row.get(0).unwrap_or(0),
+              pos: row.get(1).unwrap_or(0),
+              name: row.get(2).unwrap_or(Vec::new()),
+              user_id: row.get(3).unwrap_or(None),
+              validity: SQLitePersist::validity_from_row(row, 4),
+          })) {
+          Ok(iter) => fingerprints.extend(iter.filter_map(|fingerprint| fingerprint.ok())),
+          Err(err) => return Err(format!("Error cleaning up credentials: {}", err)),
+        };
+      }
+
+      /*
+       * Bluetooth addresses can not be wiped from the phone that advertises
+       * them, so their rows are kept and valid_until keeps denying them.
+       */
+      for table in ["cards", "fingerprint"].iter() {
+        match tx.execute(&format!("DELETE FROM {} WHERE {}", table, expired), &[now]) {
+          Ok(0) => {},
+          Ok(count) => acontrol_system_log!(LogType::Info, "Removed {} expired credentials from {}", count, table),
+          Err(err) => return Err(format!("Error cleaning up credentials from {}: {}", table, err)),
+        }
+      }
+
+      if let Err(err) = tx.commit() {
+        return Err(format!("Error cleaning up credentials: {}", err));
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
+    Ok(fingerprints)
+  }
+
+  fn event_add(&mut self, event: &AccessEvent) -> Result<(), String> {
+    if let Some(ref conn) = self.conn {
+      if let Err(err) = conn.execute("INSERT INTO access_events (timestamp, credential_type, credential_id, user_name, decision, reason, reader, card_technology, card_atqa, card_sak) VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10)",
+          &[&event.timestamp as &dyn ToSql, &event.credential_type.name(), &event.credential_id, &event.user, &event.decision.name(), &event.reason, &event.reader,
+            &event.card_technology, &event.card_atqa, &event.card_sak],
+      ) {
+        return Err(format!("Error inserting access event to the database: {}", err));
+      }
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+
     Ok(())
   }
+
+  fn event_list(&mut self, filter: &AccessEventFilter) -> Result<Vec<AccessEvent>, String> {
+
+    let mut ret: Vec<AccessEvent> = Vec::new();
+
+    if let Some(ref conn) = self.conn {
+      let (clause, mut params) = SQLitePersist::event_filter_clause(filter);
+      params.push(Box::new(filter.limit));
+      params.push(Box::new(filter.offset));
+
+      let sql = format!("SELECT id,timestamp,credential_type,credential_id,user_name,decision,reason,reader,card_technology,card_atqa,card_sak FROM access_events{} ORDER BY timestamp DESC, id DESC LIMIT ? OFFSET ?", clause);
+      let mut stmt = match conn.prepare(&sql) {
+        Ok(stmt) => stmt,
+        Err(err) => return Err(format!("Error querying access events: {}", err)),
+      };
+
+      let event_iter = match stmt
+        .query_map(params.iter().map(|param| param.as_ref()), |row| {
+          let credential_type: String = row.get(2).unwrap_or(String::new());
+          let decision: String = row.get(5).unwrap_or(String::new());
+          Ok(AccessEvent {
+            id: row.get(0).unwrap_or(0),
+            timestamp: row.get(1).unwrap_or(0),
+            credential_type: CredentialType::from_name(&credential_type).unwrap_or(CredentialType::Nfc),
+            credential_id: row.get(3).unwrap_or(String::new()),
+            reader: row.get(7).unwrap_or(String::new()),
+            user: row.get(4).unwrap_or(String::new()),
+            decision: AccessDecision::from_name(&decision).unwrap_or(AccessDecision::Denied),
+            reason: row.get(6).unwrap_or(String::new()),
+            card_technology: row.get(8).unwrap_or(String::new()),
+            card_atqa: row.get(9).unwrap_or(String::new()),
+            card_sak: row.get(10).unwrap_or(None),
+          })
+        }) {
+        Ok(iter) => iter,
+        Err(err) => return Err(format!("Error querying access events: {}", err)),
+      };
+
+      for event in event_iter {
+        if let Ok(event) = event {
+          ret.push(event);
+        }
+      }
+      return Ok(ret);
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
+
+  fn event_count(&mut self, filter: &AccessEventFilter) -> Result<u32, String> {
+    if let Some(ref conn) = self.conn {
+      let (clause, params) = SQLitePersist::event_filter_clause(filter);
+      let sql = format!("SELECT count(*) FROM access_events{}", clause);
+
+      return match conn.query_row(&sql, params.iter().map(|param| param.as_ref()), |row| row.get::<_, u32>(0)) {
+        Ok(count) => Ok(count),
+        Err(err) => Err(format!("Error counting access events: {}", err)),
+      };
+    } else {
+      return Err(format!("{}","Database not connected"));
+    }
+  }
 }
 
 unsafe impl Send for SQLitePersist {}
@@ -26,6 +26,9 @@
  *
  */
 mod webserver;
+mod auth;
+
+pub use self::auth::bootstrap_admin;
 
 #[derive(Serialize, Deserialize)]
 struct WebServerDefaultResponse {
@@ -33,11 +36,75 @@ struct WebServerDefaultResponse {
   msg: String
 }
 
+#[derive(Serialize, Deserialize)]
+struct WebCredentialValidity {
+  valid_from: Option<i64>,
+  valid_until: Option<i64>,
+  max_uses: Option<u32>,
+  uses: u32,
+}
+
 #[derive(Serialize, Deserialize)]
 struct WebCard {
   id: i32,
   uuid: Vec<u8>,
   name: String,
+  user_id: Option<i32>,
+  #[serde(flatten)]
+  validity: WebCredentialValidity,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebFingerprint {
+  id: i32,
+  pos: i32,
+  name: String,
+  #[serde(flatten)]
+  validity: WebCredentialValidity,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebBluetooth {
+  id: i32,
+  addr: String,
+  name: String,
+  #[serde(flatten)]
+  validity: WebCredentialValidity,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebUser {
+  id: i32,
+  name: String,
+  enabled: bool,
+  group_id: Option<i32>,
+  schedule_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebUserDetail {
+  id: i32,
+  name: String,
+  enabled: bool,
+  group_id: Option<i32>,
+  schedule_id: Option<i32>,
+  cards: Vec<WebCard>,
+  fingerprints: Vec<WebFingerprint>,
+  bluetooth: Vec<WebBluetooth>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerUserListResponse {
+  ret: bool,
+  msg: String,
+  users: Vec<WebUser>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerUserResponse {
+  ret: bool,
+  msg: String,
+  user: WebUserDetail,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -47,9 +114,189 @@ struct WebServerNfcListResponse {
   cards: Vec<WebCard>,
 }
 
+#[derive(Serialize, Deserialize)]
+struct WebServerNfcReaderListResponse {
+  ret: bool,
+  msg: String,
+  readers: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebFingerprintDevice {
+  module: String,
+  firmware_version: String,
+  iso_area_max_size: u32,
+  serial_number: String,
+  security_level: u8,
+  enrolled: u32,
+  slots: u16,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerFingerprintDeviceResponse {
+  ret: bool,
+  msg: String,
+  device: WebFingerprintDevice,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerFingerprintEnrollResponse {
+  ret: bool,
+  msg: String,
+  pos: u16,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebFingerprintEnrollProgress {
+  status: String,
+  step: u8,
+  steps: u8,
+  pos: Option<u16>,
+  name: Option<String>,
+  message: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerFingerprintEnrollProgressResponse {
+  ret: bool,
+  msg: String,
+  enroll: WebFingerprintEnrollProgress,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebFingerprintBackup {
+  pos: i32,
+  created_at: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerFingerprintBackupListResponse {
+  ret: bool,
+  msg: String,
+  backups: Vec<WebFingerprintBackup>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebFingerprintSlotResult {
+  pos: i32,
+  ret: bool,
+  msg: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerFingerprintSlotsResponse {
+  ret: bool,
+  msg: String,
+  slots: Vec<WebFingerprintSlotResult>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebAccessEvent {
+  id: i64,
+  timestamp: i64,
+  credential_type: String,
+  credential_id: String,
+  reader: String,
+  user: String,
+  decision: String,
+  reason: String,
+  card_technology: String,
+  card_atqa: String,
+  card_sak: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerEventListResponse {
+  ret: bool,
+  msg: String,
+  total: u32,
+  offset: u32,
+  limit: u32,
+  events: Vec<WebAccessEvent>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebGroup {
+  id: i32,
+  name: String,
+  schedule_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerGroupListResponse {
+  ret: bool,
+  msg: String,
+  groups: Vec<WebGroup>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebScheduleWindow {
+  weekday: u32,
+  start: String,
+  end: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebSchedule {
+  id: i32,
+  name: String,
+  windows: Vec<WebScheduleWindow>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerScheduleListResponse {
+  ret: bool,
+  msg: String,
+  schedules: Vec<WebSchedule>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerScheduleResponse {
+  ret: bool,
+  msg: String,
+  schedule: WebSchedule,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebHoliday {
+  id: i32,
+  date: String,
+  name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerHolidayListResponse {
+  ret: bool,
+  msg: String,
+  holidays: Vec<WebHoliday>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebAdmin {
+  id: i32,
+  name: String,
+  role: String,
+  has_token: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerAdminListResponse {
+  ret: bool,
+  msg: String,
+  admins: Vec<WebAdmin>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebServerTokenResponse {
+  ret: bool,
+  msg: String,
+  token: String,
+}
+
 pub trait Server {
   fn port(&mut self, port: u32) -> Box<&mut dyn Server>;
   fn host(&mut self, host: &str) -> Box<&mut dyn Server>;
+  fn tls(&mut self, cert: &str, key: &str) -> Box<&mut dyn Server>;
   fn init(&self) -> Result<(),String>;
   fn signature(&self) -> String;
 }
@@ -0,0 +1,259 @@
+/**
+ * @file   auth.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  Management API authentication
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+use iron::prelude::*;
+use iron::{BeforeMiddleware, status};
+use iron::method::Method;
+use iron::typemap::Key;
+
+use hmac::Hmac;
+use sha2::{Digest, Sha256};
+use rand::RngCore;
+
+use serde_json;
+
+use crate::acontrol_system_log;
+use crate::log::LogType;
+use crate::persist::{Admin, AdminRole};
+
+use super::super::system;
+use super::WebServerDefaultResponse;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+const PASSWORD_HASH_SCHEME: &str = "pbkdf2-sha256";
+const PASSWORD_HASH_ROUNDS: u32 = 10000;
+const INITIAL_ADMIN_NAME: &str = "admin";
+const INITIAL_ADMIN_PASSWORD_FILE: &str = "initial-admin-password";
+
+pub struct AuthenticatedAdmin {
+  pub id: i32,
+  pub name: String,
+}
+
+impl Key for AuthenticatedAdmin {
+  type Value = AuthenticatedAdmin;
+}
+
+#[derive(Debug)]
+struct AuthError(String);
+
+impl fmt::Display for AuthError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl Error for AuthError {}
+
+pub fn to_hex(data: &[u8]) -> String {
+  data.iter().map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join("")
+}
+
+fn from_hex(value: &str) -> Option<Vec<u8>> {
+  if value.len() % 2 != 0 {
+    return None;
+  }
+
+  (0..value.len()).step_by(2)
+    .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+    .collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+  let mut data = vec![0u8; len];
+  rand::thread_rng().fill_bytes(&mut data);
+  data
+}
+
+fn pbkdf2_sha256(password: &str, salt: &[u8], rounds: u32) -> Vec<u8> {
+  let mut hash = vec![0u8; 32];
+  pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, rounds, &mut hash);
+  hash
+}
+
+pub fn hash_password(password: &str) -> String {
+  let salt = random_bytes(16);
+  let hash = pbkdf2_sha256(password, &salt, PASSWORD_HASH_ROUNDS);
+  format!("{}${}${}${}", PASSWORD_HASH_SCHEME, PASSWORD_HASH_ROUNDS, to_hex(&salt), to_hex(&hash))
+}
+
+pub fn verify_password(password: &str, stored: &str) -> bool {
+  let parts: Vec<&str> = stored.split('$').collect();
+  if parts.len() != 4 || parts[0] != PASSWORD_HASH_SCHEME {
+    return false;
+  }
+
+  match (parts[1].parse::<u32>(), from_hex(parts[2]), from_hex(parts[3])) {
+    (Ok(rounds), Some(salt), Some(hash)) => constant_time_eq(&pbkdf2_sha256(password, &salt, rounds), &hash),
+    _ => false
+  }
+}
+
+pub fn generate_token() -> String {
+  to_hex(&random_bytes(32))
+}
+
+/* Tokens are random enough that a plain digest is all the database needs */
+pub fn hash_token(token: &str) -> String {
+  to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+/* On the very first start there is nobody able to call the API. Create an
+ * administrator with a random password and leave it next to the database,
+ * readable only by root. */
+pub fn bootstrap_admin(params: &HashMap<String,String>) -> Result<(), String> {
+  let mut admins: Result<Vec<Admin>, String> = Err(String::from("Persistence module not found"));
+  let _ret = system::acontrol_system_get_persist_drv(|persist| {
+    admins = persist.admin_list();
+  });
+
+  if admins?.len() > 0 {
+    return Ok(());
+  }
+
+  let password = generate_token();
+  let path = Path::new(&params["DATA_PATH"]).join(INITIAL_ADMIN_PASSWORD_FILE);
+
+  match OpenOptions::new().create(true).write(true).truncate(true).mode(0o600).open(&path) {
+    Ok(mut file) => {
+      if let Err(err) = file.write_all(format!("{}:{}\n", INITIAL_ADMIN_NAME, password).as_bytes()) {
+        return Err(format!("Error writing {}: {}", path.display(), err));
+      }
+    },
+    Err(err) => return Err(format!("Error creating {}: {}", path.display(), err))
+  }
+
+  let mut result: Result<Admin, String> = Err(String::from("Persistence module not found"));
+  let _ret = system::acontrol_system_get_persist_drv(|persist| {
+    result = persist.admin_add(INITIAL_ADMIN_NAME, &hash_password(&password), AdminRole::Admin);
+  });
+
+  result?;
+  acontrol_system_log!(LogType::Warning, "No administrators found. Initial credentials written to {}", path.display());
+  Ok(())
+}
+
+pub struct AuthMiddleware;
+
+impl AuthMiddleware {
+  fn reject(status: status::Status, msg: &str) -> IronError {
+    let mut resp = Response::with((status,
+      serde_json::to_string(&WebServerDefaultResponse {ret: false, msg: String::from(msg)} ).unwrap()));
+
+    resp.headers.set(iron::headers::ContentType(
+      iron::mime::Mime(iron::mime::TopLevel::Application, iron::mime::SubLevel::Json, vec![])
+    ));
+
+    if status == status::Unauthorized {
+      resp.headers.set_raw("WWW-Authenticate", vec![b"Basic realm=\"acontrol\"".to_vec()]);
+    }
+
+    IronError { error: Box::new(AuthError(String::from(msg))), response: resp }
+  }
+
+  fn authenticate(req: &Request) -> Result<Admin, String> {
+    let header = match req.headers.get_raw("Authorization").and_then(|values| values.first()) {
+      Some(value) => String::from_utf8_lossy(value).to_string(),
+      None => return Err(String::from("Authentication required"))
+    };
+
+    let mut result: Result<Admin, String> = Err(String::from("Persistence module not found"));
+
+    if let Some(token) = header.strip_prefix("Bearer ") {
+      let token_hash = hash_token(token.trim());
+      let _ret = system::acontrol_system_get_persist_drv(|persist| {
+        result = persist.admin_find_by_token(&token_hash);
+      });
+      return result.map_err(|_err| String::from("Invalid token"));
+    }
+
+    if let Some(basic) = header.strip_prefix("Basic ") {
+      let credentials = match base64::decode(basic.trim()).ok().and_then(|decoded| String::from_utf8(decoded).ok()) {
+        Some(credentials) => credentials,
+        None => return Err(String::from("Invalid basic credentials"))
+      };
+
+      let mut parts = credentials.splitn(2, ':');
+      let (name, password) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+      let _ret = system::acontrol_system_get_persist_drv(|persist| {
+        result = persist.admin_find_by_name(name);
+      });
+
+      return match result {
+        Ok(admin) if verify_password(password, &admin.password_hash) => Ok(admin),
+        _ => Err(String::from("Invalid user or password"))
+      };
+    }
+
+    Err(String::from("Unsupported authorization scheme"))
+  }
+
+  /* Auditors can read everything, anything that changes state needs an
+   * administrator. Managing one's own token is allowed for every role. */
+  fn required_role(req: &Request) -> AdminRole {
+    if req.url.path().join("/") == "auth/token" {
+      return AdminRole::Auditor;
+    }
+
+    match req.method {
+      Method::Get | Method::Head => AdminRole::Auditor,
+      _ => AdminRole::Admin
+    }
+  }
+}
+
+impl BeforeMiddleware for AuthMiddleware {
+  fn before(&self, req: &mut Request) -> IronResult<()> {
+    let admin = match AuthMiddleware::authenticate(req) {
+      Ok(admin) => admin,
+      Err(err) => {
+        acontrol_system_log!(LogType::Warning, "Rejected API request to /{}: {}", req.url.path().join("/"), err);
+        return Err(AuthMiddleware::reject(status::Unauthorized, &err));
+      }
+    };
+
+    if AuthMiddleware::required_role(req) == AdminRole::Admin && admin.role != AdminRole::Admin {
+      acontrol_system_log!(LogType::Warning, "Rejected API request from {} to /{}: not an administrator", admin.name, req.url.path().join("/"));
+      return Err(AuthMiddleware::reject(status::Forbidden, "Administrator role required"));
+    }
+
+    req.extensions.insert::<AuthenticatedAdmin>(AuthenticatedAdmin { id: admin.id, name: admin.name });
+    Ok(())
+  }
+}
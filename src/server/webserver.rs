@@ -34,18 +34,54 @@ use crate::acontrol_system_log;
 use crate::log::LogType;
 
 use super::super::system;
-use super::{Server,WebServerDefaultResponse,WebCard,WebServerNfcListResponse};
+use crate::persist::{AccessEventFilter, AccessDecision};
+use crate::persist::{Admin, AdminRole, User, Group, Schedule, ScheduleWindow, Holiday, CredentialType, CredentialValidity};
+use super::auth::{self, AuthMiddleware, AuthenticatedAdmin};
+use super::{Server,WebServerDefaultResponse,WebCard,WebServerNfcListResponse,WebServerNfcReaderListResponse,WebAccessEvent,WebServerEventListResponse};
+use super::{WebCredentialValidity,WebFingerprint,WebBluetooth,WebUser,WebUserDetail,WebServerUserListResponse,WebServerUserResponse};
+use super::{WebGroup,WebServerGroupListResponse,WebScheduleWindow,WebSchedule,WebServerScheduleListResponse,WebServerScheduleResponse};
+use super::{WebHoliday,WebServerHolidayListResponse};
+use super::{WebFingerprintEnrollProgress,WebServerFingerprintEnrollProgressResponse,WebFingerprintDevice,WebServerFingerprintDeviceResponse,WebServerFingerprintEnrollResponse,WebFingerprintBackup,WebServerFingerprintBackupListResponse,WebFingerprintSlotResult,WebServerFingerprintSlotsResponse};
+use super::{WebAdmin,WebServerAdminListResponse,WebServerTokenResponse};
+
+use hyper_native_tls::NativeTlsServer;
+use native_tls::{Identity, TlsAcceptor};
 
 use std::collections::HashMap;
 
+const EVENTS_DEFAULT_LIMIT: u32 = 50;
+const EVENTS_MAX_LIMIT: u32 = 500;
+
 pub struct WebServer {
   host: String,
   port: u32,
+  tls: Option<(String, String)>,
 }
 
 impl WebServer {
   pub fn new() -> Self {
-    return WebServer { host: "".to_string(), port: 0};
+    return WebServer { host: "".to_string(), port: 0, tls: None};
+  }
+
+  fn tls_server(cert: &str, key: &str) -> Result<NativeTlsServer, String> {
+    let cert_pem = match std::fs::read(cert) {
+      Ok(data) => data,
+      Err(err) => return Err(format!("Error reading certificate {}: {}", cert, err))
+    };
+    let key_pem = match std::fs::read(key) {
+      Ok(data) => data,
+      Err(err) => return Err(format!("Error reading key {}: {}", key, err))
+    };
+
+    let identity = match Identity::from_pkcs8(&cert_pem, &key_pem) {
+      Ok(identity) => identity,
+      Err(err) => return Err(format!("Invalid certificate or key: {}", err))
+    };
+
+    match TlsAcceptor::new(identity) {
+      Ok(acceptor) => Ok(NativeTlsServer::from(acceptor)),
+      Err(err) => Err(format!("Error creating tls acceptor: {}", err))
+    }
   }
 
   //fn hello_world(req: &mut Request) -> IronResult<Response> {
@@ -53,9 +89,21 @@ impl WebServer {
   //  Ok(Response::with((iron::status::Ok, format!("Hello {}", query))))
   //}
 
+  /* Enrolment happens on one reader only. Without a "reader" field the first
+   * configured one is used. */
+  fn nfc_reader_param(json_body: &serde_json::Value) -> Option<String> {
+    json_body.get("reader").and_then(|reader| reader.as_str()).map(String::from)
+  }
+
+  fn nfc_readers(_req: &mut Request) -> IronResult<Response> {
+    Ok(WebServer::json_response(iron::status::Ok,
+      serde_json::to_string(&WebServerNfcReaderListResponse {ret: true, msg: String::from("Ok"), readers: system::acontrol_system_nfc_reader_names()} ).unwrap()))
+  }
+
   fn nfc_authorize(req: &mut Request) -> IronResult<Response> {
 
     let mut params: HashMap<String,String> = HashMap::new();
+    let mut reader: Option<String> = None;
     let mut resp: Option<Response> = None;
     let json_body = req.get::<bodyparser::Json>();
 
@@ -64,6 +112,7 @@ impl WebServer {
           if json_body.get("name").is_some() {
             params.insert(String::from("name"), String::from(json_body["name"].as_str().unwrap()));
           }
+          reader = WebServer::nfc_reader_param(&json_body);
         },
         Ok(None) => {
           resp = Some(Response::with((iron::status::BadRequest,
@@ -84,10 +133,10 @@ impl WebServer {
     }
 
     if resp.is_none() {
-      system::acontrol_system_set_nfc_state(system::NFCSystemState::AUTHORIZE,Some(params));
-      resp = Some(Response::with((iron::status::Ok,
-         serde_json::to_string(&WebServerDefaultResponse {ret: true, msg: String::from("Ok")} ).unwrap())
-      ));
+      resp = Some(match system::acontrol_system_set_nfc_state(reader.as_ref().map(|reader| reader.as_str()), system::NFCSystemState::AUTHORIZE, Some(params)) {
+        Ok(()) => WebServer::default_response(iron::status::Ok, true, String::from("Ok")),
+        Err(err) => WebServer::default_response(iron::status::NotFound, false, err)
+      });
     }
 
     let mut final_resp = resp.unwrap();
@@ -99,18 +148,16 @@ impl WebServer {
     Ok(final_resp)
   }
 
-  fn nfc_restore(_req: &mut Request) -> IronResult<Response> {
-    system::acontrol_system_set_nfc_state(system::NFCSystemState::RESTORE,None);
+  fn nfc_restore(req: &mut Request) -> IronResult<Response> {
+    let reader = match req.get::<bodyparser::Json>() {
+      Ok(Some(json_body)) => WebServer::nfc_reader_param(&json_body),
+      _ => None
+    };
 
-    let mut resp = Response::with((iron::status::Ok,
-       serde_json::to_string(&WebServerDefaultResponse {ret: true, msg: String::from("Ok")} ).unwrap())
-    );
-
-    resp.headers.set(iron::headers::ContentType(
-      iron::mime::Mime(iron::mime::TopLevel::Application, iron::mime::SubLevel::Json, vec![])
-    ));
-
-    Ok(resp)
+    match system::acontrol_system_set_nfc_state(reader.as_ref().map(|reader| reader.as_str()), system::NFCSystemState::RESTORE, None) {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::NotFound, false, err))
+    }
   }
 
   fn nfc_list(_req: &mut Request) -> IronResult<Response> {
@@ -120,7 +167,7 @@ impl WebServer {
     if let Err(err) = system::acontrol_system_get_persist_drv(|drv| {
       if let Ok(ret) =  drv.nfc_list() {
         for card in ret {
-          cards.push(WebCard {id: card.id, uuid: card.uuid, name: String::from_utf8(card.name).unwrap()});
+          cards.push(WebCard {id: card.id, uuid: card.uuid, name: String::from_utf8(card.name).unwrap(), user_id: card.user_id, validity: WebServer::validity_to_web(&card.validity)});
         }
       } else {
         resp = Some(Response::with((iron::status::InternalServerError,
@@ -148,6 +195,1044 @@ impl WebServer {
     Ok(resp_final)
   }
 
+  fn json_response(status: iron::status::Status, body: String) -> Response {
+    let mut resp = Response::with((status, body));
+
+    resp.headers.set(iron::headers::ContentType(
+      iron::mime::Mime(iron::mime::TopLevel::Application, iron::mime::SubLevel::Json, vec![])
+    ));
+
+    resp
+  }
+
+  fn default_response(status: iron::status::Status, ret: bool, msg: String) -> Response {
+    WebServer::json_response(status, serde_json::to_string(&WebServerDefaultResponse {ret: ret, msg: msg} ).unwrap())
+  }
+
+  fn id_param(req: &Request, label: &str) -> Result<i32, Response> {
+    match req.extensions.get::<Router>().and_then(|router| router.find("id")).map(|id| id.parse::<i32>()) {
+      Some(Ok(id)) => Ok(id),
+      _ => Err(WebServer::default_response(iron::status::BadRequest, false, format!("Invalid {} id", label)))
+    }
+  }
+
+  fn user_id_param(req: &Request) -> Result<i32, Response> {
+    WebServer::id_param(req, "user")
+  }
+
+  /* Distinguishes an absent field (None) from an explicit null (Some(None)),
+   * so updates can clear a reference without touching the others. */
+  fn optional_id_field(json_body: &serde_json::Value, field: &str) -> Result<Option<Option<i32>>, String> {
+    match json_body.get(field) {
+      None => Ok(None),
+      Some(serde_json::Value::Null) => Ok(Some(None)),
+      Some(value) => match value.as_i64() {
+        Some(id) => Ok(Some(Some(id as i32))),
+        None => Err(format!("{} must be a number or null", field))
+      }
+    }
+  }
+
+  fn parse_minute(value: &str) -> Result<u32, String> {
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() == 2 {
+      if let (Ok(hour), Ok(minute)) = (parts[0].parse::<u32>(), parts[1].parse::<u32>()) {
+        if minute < 60 && (hour < 24 || (hour == 24 && minute == 0)) {
+          return Ok(hour * 60 + minute);
+        }
+      }
+    }
+    Err(format!("Invalid time: {}. Use HH:MM", value))
+  }
+
+  fn format_minute(minute: u32) -> String {
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+  }
+
+  fn schedule_to_web(schedule: Schedule) -> WebSchedule {
+    WebSchedule {
+      id: schedule.id,
+      name: schedule.name,
+      windows: schedule.windows.iter().map(|window| WebScheduleWindow {
+        weekday: window.weekday,
+        start: WebServer::format_minute(window.start),
+        end: WebServer::format_minute(window.end)
+      }).collect()
+    }
+  }
+
+  fn schedule_from_json(json_body: &serde_json::Value) -> Result<(String, Vec<ScheduleWindow>), String> {
+    let name = match json_body.get("name").and_then(|name| name.as_str()) {
+      Some(name) if name.len() > 0 => String::from(name),
+      _ => return Err(String::from("Name field is required"))
+    };
+
+    let mut windows: Vec<ScheduleWindow> = Vec::new();
+    if let Some(list) = json_body.get("windows").and_then(|windows| windows.as_array()) {
+      for window in list {
+        let weekday = match window.get("weekday").and_then(|weekday| weekday.as_u64()) {
+          Some(weekday) if weekday < 7 => weekday as u32,
+          _ => return Err(String::from("weekday must be between 0 (monday) and 6 (sunday)"))
+        };
+        let start = WebServer::parse_minute(window.get("start").and_then(|start| start.as_str()).unwrap_or(""))?;
+        let end = WebServer::parse_minute(window.get("end").and_then(|end| end.as_str()).unwrap_or(""))?;
+        if end <= start {
+          return Err(String::from("Window end must be after its start"));
+        }
+        windows.push(ScheduleWindow {weekday: weekday, start: start, end: end});
+      }
+    }
+
+    Ok((name, windows))
+  }
+
+  fn groups_list(_req: &mut Request) -> IronResult<Response> {
+    let mut result: Result<Vec<Group>, String> = Err(String::from("Persistence driver not found"));
+
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.group_list();
+    });
+
+    match result {
+      Ok(groups) => {
+        let groups = groups.into_iter().map(|group| WebGroup {id: group.id, name: group.name, schedule_id: group.schedule_id}).collect();
+        Ok(WebServer::json_response(iron::status::Ok,
+          serde_json::to_string(&WebServerGroupListResponse {ret: true, msg: String::from("Ok"), groups: groups} ).unwrap()))
+      },
+      Err(err) => Ok(WebServer::default_response(iron::status::InternalServerError, false, err))
+    }
+  }
+
+  fn group_add(req: &mut Request) -> IronResult<Response> {
+    let (name, schedule_id) = match req.get::<bodyparser::Json>() {
+      Ok(Some(json_body)) => {
+        match WebServer::optional_id_field(&json_body, "schedule_id") {
+          Ok(schedule_id) => (json_body.get("name").and_then(|name| name.as_str()).map(|name| String::from(name)), schedule_id.unwrap_or(None)),
+          Err(err) => return Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+        }
+      },
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("No body. Or body is not a valid json")))
+    };
+
+    let name = match name {
+      Some(ref name) if name.len() > 0 => name.clone(),
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("Name field is required")))
+    };
+
+    let mut result: Result<Group, String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.group_add(&name).and_then(|mut group| {
+        if schedule_id.is_some() {
+          group.schedule_id = schedule_id;
+          drv.group_update(&group)?;
+        }
+        Ok(group)
+      });
+    });
+
+    match result {
+      Ok(group) => Ok(WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerGroupListResponse {ret: true, msg: String::from("Ok"),
+          groups: vec![WebGroup {id: group.id, name: group.name, schedule_id: group.schedule_id}]} ).unwrap())),
+      Err(err) => Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+    }
+  }
+
+  fn group_update(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::id_param(req, "group") {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    let (name, schedule_id) = match req.get::<bodyparser::Json>() {
+      Ok(Some(json_body)) => {
+        match WebServer::optional_id_field(&json_body, "schedule_id") {
+          Ok(schedule_id) => (json_body.get("name").and_then(|name| name.as_str()).map(|name| String::from(name)), schedule_id),
+          Err(err) => return Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+        }
+      },
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("No body. Or body is not a valid json")))
+    };
+
+    let mut result: Result<(), String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.group_find(id).and_then(|mut group| {
+        if let Some(name) = name {
+          group.name = name;
+        }
+        if let Some(schedule_id) = schedule_id {
+          group.schedule_id = schedule_id;
+        }
+        drv.group_update(&group)
+      });
+    });
+
+    match result {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+    }
+  }
+
+  fn group_delete(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::id_param(req, "group") {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    let mut result: Result<(), String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.group_delete(id);
+    });
+
+    match result {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+    }
+  }
+
+  fn schedules_list(_req: &mut Request) -> IronResult<Response> {
+    let mut result: Result<Vec<Schedule>, String> = Err(String::from("Persistence driver not found"));
+
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.schedule_list();
+    });
+
+    match result {
+      Ok(schedules) => Ok(WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerScheduleListResponse {ret: true, msg: String::from("Ok"),
+          schedules: schedules.into_iter().map(|schedule| WebServer::schedule_to_web(schedule)).collect()} ).unwrap())),
+      Err(err) => Ok(WebServer::default_response(iron::status::InternalServerError, false, err))
+    }
+  }
+
+  fn schedule_get(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::id_param(req, "schedule") {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    let mut result: Result<Schedule, String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.schedule_find(id);
+    });
+
+    match result {
+      Ok(schedule) => Ok(WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerScheduleResponse {ret: true, msg: String::from("Ok"), schedule: WebServer::schedule_to_web(schedule)} ).unwrap())),
+      Err(err) => Ok(WebServer::default_response(iron::status::NotFound, false, err))
+    }
+  }
+
+  fn schedule_add(req: &mut Request) -> IronResult<Response> {
+    let (name, windows) = match req.get::<bodyparser::Json>() {
+      Ok(Some(json_body)) => match WebServer::schedule_from_json(&json_body) {
+        Ok(schedule) => schedule,
+        Err(err) => return Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+      },
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("No body. Or body is not a valid json")))
+    };
+
+    let mut result: Result<Schedule, String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.schedule_add(&name, &windows);
+    });
+
+    match result {
+      Ok(schedule) => Ok(WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerScheduleResponse {ret: true, msg: String::from("Ok"), schedule: WebServer::schedule_to_web(schedule)} ).unwrap())),
+      Err(err) => Ok(WebServer::default_response(iron::status::InternalServerError, false, err))
+    }
+  }
+
+  fn schedule_update(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::id_param(req, "schedule") {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    let (name, windows) = match req.get::<bodyparser::Json>() {
+      Ok(Some(json_body)) => match WebServer::schedule_from_json(&json_body) {
+        Ok(schedule) => schedule,
+        Err(err) => return Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+      },
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("No body. Or body is not a valid json")))
+    };
+
+    let mut result: Result<(), String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.schedule_update(&Schedule {id: id, name: name, windows: windows});
+    });
+
+    match result {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+    }
+  }
+
+  fn schedule_delete(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::id_param(req, "schedule") {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    let mut result: Result<(), String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.schedule_delete(id);
+    });
+
+    match result {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+    }
+  }
+
+  fn holidays_list(_req: &mut Request) -> IronResult<Response> {
+    let mut result: Result<Vec<Holiday>, String> = Err(String::from("Persistence driver not found"));
+
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.holiday_list();
+    });
+
+    match result {
+      Ok(holidays) => {
+        let holidays = holidays.into_iter().map(|holiday| WebHoliday {id: holiday.id, date: holiday.date, name: holiday.name}).collect();
+        Ok(WebServer::json_response(iron::status::Ok,
+          serde_json::to_string(&WebServerHolidayListResponse {ret: true, msg: String::from("Ok"), holidays: holidays} ).unwrap()))
+      },
+      Err(err) => Ok(WebServer::default_response(iron::status::InternalServerError, false, err))
+    }
+  }
+
+  fn holiday_add(req: &mut Request) -> IronResult<Response> {
+    let (date, name) = match req.get::<bodyparser::Json>() {
+      Ok(Some(json_body)) => (
+        json_body.get("date").and_then(|date| date.as_str()).map(|date| String::from(date)),
+        json_body.get("name").and_then(|name| name.as_str()).map(|name| String::from(name)).unwrap_or(String::new())
+      ),
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("No body. Or body is not a valid json")))
+    };
+
+    let date = match date {
+      Some(ref date) if chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok() => date.clone(),
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("Date field is required (YYYY-MM-DD)")))
+    };
+
+    let mut result: Result<Holiday, String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.holiday_add(&date, &name);
+    });
+
+    match result {
+      Ok(holiday) => Ok(WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerHolidayListResponse {ret: true, msg: String::from("Ok"),
+          holidays: vec![WebHoliday {id: holiday.id, date: holiday.date, name: holiday.name}]} ).unwrap())),
+      Err(err) => Ok(WebServer::default_response(iron::status::InternalServerError, false, err))
+    }
+  }
+
+  fn holiday_delete(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::id_param(req, "holiday") {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    let mut result: Result<(), String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.holiday_delete(id);
+    });
+
+    match result {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+    }
+  }
+
+  fn admin_to_web(admin: Admin) -> WebAdmin {
+    WebAdmin {id: admin.id, name: admin.name, role: String::from(admin.role.name()), has_token: admin.token_hash.is_some()}
+  }
+
+  fn admins_list(_req: &mut Request) -> IronResult<Response> {
+    let mut result: Result<Vec<Admin>, String> = Err(String::from("Persistence driver not found"));
+
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.admin_list();
+    });
+
+    match result {
+      Ok(admins) => Ok(WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerAdminListResponse {ret: true, msg: String::from("Ok"),
+          admins: admins.into_iter().map(|admin| WebServer::admin_to_web(admin)).collect()} ).unwrap())),
+      Err(err) => Ok(WebServer::default_response(iron::status::InternalServerError, false, err))
+    }
+  }
+
+  fn admin_add(req: &mut Request) -> IronResult<Response> {
+    let (name, password, role) = match req.get::<bodyparser::Json>() {
+      Ok(Some(json_body)) => (
+        json_body.get("name").and_then(|name| name.as_str()).map(|name| String::from(name)),
+        json_body.get("password").and_then(|password| password.as_str()).map(|password| String::from(password)),
+        json_body.get("role").and_then(|role| role.as_str()).map(|role| AdminRole::from_name(role))
+      ),
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("No body. Or body is not a valid json")))
+    };
+
+    let (name, password, role) = match (name, password, role) {
+      (Some(name), Some(password), Some(Some(role))) if name.len() > 0 && password.len() >= 8 => (name, password, role),
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false,
+        String::from("name, password (8+ characters) and role (auditor or admin) are required")))
+    };
+
+    let mut result: Result<Admin, String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.admin_add(&name, &auth::hash_password(&password), role);
+    });
+
+    match result {
+      Ok(admin) => {
+        if let Some(caller) = req.extensions.get::<AuthenticatedAdmin>() {
+          acontrol_system_log!(LogType::Info, "Administrator {} ({}) created by {}", admin.name, admin.role.name(), caller.name);
+        }
+        Ok(WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerAdminListResponse {ret: true, msg: String::from("Ok"), admins: vec![WebServer::admin_to_web(admin)]} ).unwrap()))
+      },
+      Err(err) => Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+    }
+  }
+
+  fn admin_delete(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::id_param(req, "admin") {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    if req.extensions.get::<AuthenticatedAdmin>().map(|admin| admin.id) == Some(id) {
+      return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("An administrator can not delete itself")));
+    }
+
+    let mut result: Result<(), String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.admin_delete(id);
+    });
+
+    match result {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::NotFound, false, err))
+    }
+  }
+
+  fn auth_token_create(req: &mut Request) -> IronResult<Response> {
+    let (id, name) = match req.extensions.get::<AuthenticatedAdmin>() {
+      Some(admin) => (admin.id, admin.name.clone()),
+      None => return Ok(WebServer::default_response(iron::status::Unauthorized, false, String::from("Authentication required")))
+    };
+
+    acontrol_system_log!(LogType::Info, "Api token issued to {}", name);
+
+    let token = auth::generate_token();
+    let mut result: Result<(), String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.admin_set_token(id, Some(&auth::hash_token(&token)));
+    });
+
+    match result {
+      Ok(()) => Ok(WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerTokenResponse {ret: true, msg: String::from("Ok"), token: token} ).unwrap())),
+      Err(err) => Ok(WebServer::default_response(iron::status::InternalServerError, false, err))
+    }
+  }
+
+  fn auth_token_revoke(req: &mut Request) -> IronResult<Response> {
+    let id = match req.extensions.get::<AuthenticatedAdmin>() {
+      Some(admin) => admin.id,
+      None => return Ok(WebServer::default_response(iron::status::Unauthorized, false, String::from("Authentication required")))
+    };
+
+    let mut result: Result<(), String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.admin_set_token(id, None);
+    });
+
+    match result {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::InternalServerError, false, err))
+    }
+  }
+
+  fn users_list(_req: &mut Request) -> IronResult<Response> {
+    let mut result: Result<Vec<User>, String> = Err(String::from("Persistence driver not found"));
+
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.user_list();
+    });
+
+    match result {
+      Ok(users) => {
+        let users = users.into_iter().map(|user| WebUser {id: user.id, name: user.name, enabled: user.enabled, group_id: user.group_id, schedule_id: user.schedule_id}).collect();
+        Ok(WebServer::json_response(iron::status::Ok,
+          serde_json::to_string(&WebServerUserListResponse {ret: true, msg: String::from("Ok"), users: users} ).unwrap()))
+      },
+      Err(err) => Ok(WebServer::default_response(iron::status::InternalServerError, false, err))
+    }
+  }
+
+  fn user_add(req: &mut Request) -> IronResult<Response> {
+    let name = match req.get::<bodyparser::Json>() {
+      Ok(Some(json_body)) => json_body.get("name").and_then(|name| name.as_str()).map(|name| String::from(name)),
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("No body. Or body is not a valid json")))
+    };
+
+    let name = match name {
+      Some(ref name) if name.len() > 0 => name.clone(),
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("Name field is required")))
+    };
+
+    let mut result: Result<User, String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.user_add(&name);
+    });
+
+    match result {
+      Ok(user) => Ok(WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerUserResponse {ret: true, msg: String::from("Ok"),
+          user: WebUserDetail {id: user.id, name: user.name, enabled: user.enabled, group_id: user.group_id, schedule_id: user.schedule_id, cards: Vec::new(), fingerprints: Vec::new(), bluetooth: Vec::new()}} ).unwrap())),
+      Err(err) => Ok(WebServer::default_response(iron::status::InternalServerError, false, err))
+    }
+  }
+
+  fn user_get(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::user_id_param(req) {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    let mut result: Result<WebUserDetail, String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.user_find(id).and_then(|user| {
+        let cards = drv.user_cards(id)?.into_iter().map(|card| WebCard {
+          id: card.id, uuid: card.uuid, name: String::from_utf8_lossy(&card.name).to_string(), user_id: card.user_id, validity: WebServer::validity_to_web(&card.validity)
+        }).collect();
+        let fingerprints = drv.user_fingerprints(id)?.into_iter().map(|fingerprint| WebFingerprint {
+          id: fingerprint.id, pos: fingerprint.pos, name: String::from_utf8_lossy(&fingerprint.name).to_string(), validity: WebServer::validity_to_web(&fingerprint.validity)
+        }).collect();
+        let bluetooth = drv.user_bluetooth(id)?.into_iter().map(|bluetooth| WebBluetooth {
+          id: bluetooth.id, addr: String::from_utf8_lossy(&bluetooth.addr).to_string(), name: String::from_utf8_lossy(&bluetooth.name).to_string(), validity: WebServer::validity_to_web(&bluetooth.validity)
+        }).collect();
+
+        Ok(WebUserDetail {id: user.id, name: user.name, enabled: user.enabled, group_id: user.group_id, schedule_id: user.schedule_id, cards: cards, fingerprints: fingerprints, bluetooth: bluetooth})
+      });
+    });
+
+    match result {
+      Ok(user) => Ok(WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerUserResponse {ret: true, msg: String::from("Ok"), user: user} ).unwrap())),
+      Err(err) => Ok(WebServer::default_response(iron::status::NotFound, false, err))
+    }
+  }
+
+  fn user_update(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::user_id_param(req) {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    let (name, enabled, group_id, schedule_id) = match req.get::<bodyparser::Json>() {
+      Ok(Some(json_body)) => {
+        match (WebServer::optional_id_field(&json_body, "group_id"), WebServer::optional_id_field(&json_body, "schedule_id")) {
+          (Ok(group_id), Ok(schedule_id)) => (
+            json_body.get("name").and_then(|name| name.as_str()).map(|name| String::from(name)),
+            json_body.get("enabled").and_then(|enabled| enabled.as_bool()),
+            group_id,
+            schedule_id
+          ),
+          (Err(err), _) | (_, Err(err)) => return Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+        }
+      },
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("No body. Or body is not a valid json")))
+    };
+
+    let mut result: Result<(), String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.user_find(id).and_then(|mut user| {
+        if let Some(name) = name {
+          user.name = name;
+        }
+        if let Some(enabled) = enabled {
+          user.enabled = enabled;
+        }
+        if let Some(group_id) = group_id {
+          user.group_id = group_id;
+        }
+        if let Some(schedule_id) = schedule_id {
+          user.schedule_id = schedule_id;
+        }
+        drv.user_update(&user)
+      });
+    });
+
+    match result {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+    }
+  }
+
+  fn user_set_enabled(req: &mut Request, enabled: bool) -> IronResult<Response> {
+    let id = match WebServer::user_id_param(req) {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    let mut result: Result<(), String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.user_set_enabled(id, enabled);
+    });
+
+    match result {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+    }
+  }
+
+  fn user_enable(req: &mut Request) -> IronResult<Response> {
+    WebServer::user_set_enabled(req, true)
+  }
+
+  fn user_disable(req: &mut Request) -> IronResult<Response> {
+    WebServer::user_set_enabled(req, false)
+  }
+
+  fn user_delete(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::user_id_param(req) {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    acontrol_system_log!(LogType::Info, "Deleting user {} and all of its credentials", id);
+
+    match system::acontrol_system_user_delete(id) {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+    }
+  }
+
+  fn validity_to_web(validity: &CredentialValidity) -> WebCredentialValidity {
+    WebCredentialValidity {
+      valid_from: validity.valid_from,
+      valid_until: validity.valid_until,
+      max_uses: validity.max_uses,
+      uses: validity.uses
+    }
+  }
+
+  fn optional_timestamp_field(json_body: &serde_json::Value, field: &str) -> Result<Option<Option<i64>>, String> {
+    match json_body.get(field) {
+      None => Ok(None),
+      Some(serde_json::Value::Null) => Ok(Some(None)),
+      Some(serde_json::Value::String(value)) => WebServer::parse_timestamp(value).map(|timestamp| Some(Some(timestamp))),
+      Some(value) => match value.as_i64() {
+        Some(timestamp) => Ok(Some(Some(timestamp))),
+        None => Err(format!("{} must be unix seconds, RFC3339 or null", field))
+      }
+    }
+  }
+
+  /* Fields missing from the body keep their current value, null clears them */
+  fn validity_from_json(json_body: &serde_json::Value, mut validity: CredentialValidity) -> Result<CredentialValidity, String> {
+    if let Some(valid_from) = WebServer::optional_timestamp_field(json_body, "valid_from")? {
+      validity.valid_from = valid_from;
+    }
+
+    if let Some(valid_until) = WebServer::optional_timestamp_field(json_body, "valid_until")? {
+      validity.valid_until = valid_until;
+    }
+
+    match json_body.get("max_uses") {
+      None => {},
+      Some(serde_json::Value::Null) => validity.max_uses = None,
+      Some(value) => match value.as_u64() {
+        Some(max_uses) if max_uses <= u32::MAX as u64 => validity.max_uses = Some(max_uses as u32),
+        Some(_) => return Err(format!("max_uses must be at most {}", u32::MAX)),
+        None => return Err(String::from("max_uses must be a positive number or null"))
+      }
+    }
+
+    Ok(validity)
+  }
+
+  fn credential_validity_update(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::id_param(req, "credential") {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    let credential_type = match req.extensions.get::<Router>().and_then(|router| router.find("type")).and_then(|name| CredentialType::from_name(name)) {
+      Some(credential_type @ CredentialType::Nfc) |
+      Some(credential_type @ CredentialType::Fingerprint) |
+      Some(credential_type @ CredentialType::Bluetooth) => credential_type,
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("Credential type must be nfc, fingerprint or bluetooth")))
+    };
+
+    let json_body = match req.get::<bodyparser::Json>() {
+      Ok(Some(json_body)) => json_body,
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("No body. Or body is not a valid json")))
+    };
+
+    let mut result: Result<(), String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.credential_validity(credential_type, id)
+        .and_then(|current| WebServer::validity_from_json(&json_body, current))
+        .and_then(|validity| drv.credential_set_validity(credential_type, id, &validity));
+    });
+
+    match result {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+    }
+  }
+
+  fn user_nfc_authorize(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::user_id_param(req) {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    let (validity, reader) = match req.get::<bodyparser::Json>() {
+      Ok(Some(json_body)) => match WebServer::validity_from_json(&json_body, CredentialValidity::unlimited()) {
+        Ok(validity) => (validity, WebServer::nfc_reader_param(&json_body)),
+        Err(err) => return Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+      },
+      _ => (CredentialValidity::unlimited(), None)
+    };
+
+    let mut result: Result<User, String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.user_find(id);
+    });
+
+    match result {
+      Ok(user) => {
+        let mut params: HashMap<String,String> = HashMap::new();
+        params.insert(String::from("user_id"), user.id.to_string());
+        params.insert(String::from("name"), user.name);
+        if let Some(valid_from) = validity.valid_from {
+          params.insert(String::from("valid_from"), valid_from.to_string());
+        }
+        if let Some(valid_until) = validity.valid_until {
+          params.insert(String::from("valid_until"), valid_until.to_string());
+        }
+        if let Some(max_uses) = validity.max_uses {
+          params.insert(String::from("max_uses"), max_uses.to_string());
+        }
+        match system::acontrol_system_set_nfc_state(reader.as_ref().map(|reader| reader.as_str()), system::NFCSystemState::AUTHORIZE, Some(params)) {
+          Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+          Err(err) => Ok(WebServer::default_response(iron::status::NotFound, false, err))
+        }
+      },
+      Err(err) => Ok(WebServer::default_response(iron::status::NotFound, false, err))
+    }
+  }
+
+  /* "pos" may be a number or a string and can be left out to let the
+   * sensor pick a free slot. "overwrite" must be set to reuse an enrolled
+   * one. */
+  fn fingerprint_enroll_params(json_body: &serde_json::Value, params: &mut HashMap<String,String>) -> Result<(), String> {
+    if let Some(pos) = json_body.get("pos") {
+      match pos.as_u64().or(pos.as_str().and_then(|pos| pos.parse::<u64>().ok())) {
+        Some(pos) if pos <= u16::MAX as u64 => { params.insert(String::from("pos"), pos.to_string()); },
+        _ => return Err(String::from("Invalid fingerprint position"))
+      }
+    }
+
+    if json_body.get("overwrite").and_then(|overwrite| overwrite.as_bool()).unwrap_or(false) {
+      params.insert(String::from("overwrite"), String::from("true"));
+    }
+
+    Ok(())
+  }
+
+  fn fingerprint_enroll_response(params: HashMap<String,String>) -> Response {
+    match system::acontrol_system_fingerprint_start_enroll(params) {
+      Ok(pos) => WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerFingerprintEnrollResponse {ret: true, msg: String::from("Ok"), pos: pos} ).unwrap()),
+      Err(err) => WebServer::default_response(iron::status::BadRequest, false, err)
+    }
+  }
+
+  fn user_fingerprint_enroll(req: &mut Request) -> IronResult<Response> {
+    let id = match WebServer::user_id_param(req) {
+      Ok(id) => id,
+      Err(resp) => return Ok(resp)
+    };
+
+    let mut params: HashMap<String,String> = HashMap::new();
+
+    if let Ok(Some(json_body)) = req.get::<bodyparser::Json>() {
+      if let Err(err) = WebServer::fingerprint_enroll_params(&json_body, &mut params) {
+        return Ok(WebServer::default_response(iron::status::BadRequest, false, err));
+      }
+    }
+
+    let mut result: Result<User, String> = Err(String::from("Persistence driver not found"));
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.user_find(id);
+    });
+
+    match result {
+      Ok(user) => {
+        params.insert(String::from("user_id"), user.id.to_string());
+        params.insert(String::from("name"), user.name);
+        Ok(WebServer::fingerprint_enroll_response(params))
+      },
+      Err(err) => Ok(WebServer::default_response(iron::status::NotFound, false, err))
+    }
+  }
+
+  fn parse_timestamp(value: &str) -> Result<i64, String> {
+    if let Ok(timestamp) = value.parse::<i64>() {
+      return Ok(timestamp);
+    }
+
+    match chrono::DateTime::parse_from_rfc3339(value) {
+      Ok(date) => Ok(date.timestamp()),
+      Err(_err) => Err(format!("Invalid date: {}. Use unix seconds or RFC3339", value))
+    }
+  }
+
+  fn events_filter(req: &Request) -> Result<AccessEventFilter, String> {
+    let mut filter = AccessEventFilter {
+      from: None,
+      to: None,
+      user: None,
+      reader: None,
+      decision: None,
+      offset: 0,
+      limit: EVENTS_DEFAULT_LIMIT
+    };
+
+    for (key, value) in req.url.as_ref().query_pairs() {
+      match key.as_ref() {
+        "from" => filter.from = Some(WebServer::parse_timestamp(&value)?),
+        "to" => filter.to = Some(WebServer::parse_timestamp(&value)?),
+        "user" => filter.user = Some(value.to_string()),
+        "reader" => filter.reader = Some(value.to_string()),
+        "decision" => {
+          filter.decision = match AccessDecision::from_name(&value) {
+            Some(decision) => Some(decision),
+            None => return Err(format!("Invalid decision: {}. Use granted, denied or alarm", value))
+          }
+        },
+        "offset" => {
+          filter.offset = match value.parse::<u32>() {
+            Ok(offset) => offset,
+            Err(_err) => return Err(format!("Invalid offset: {}", value))
+          }
+        },
+        "limit" => {
+          filter.limit = match value.parse::<u32>() {
+            Ok(limit) if limit > 0 && limit <= EVENTS_MAX_LIMIT => limit,
+            _ => return Err(format!("Invalid limit: {}. Must be between 1 and {}", value, EVENTS_MAX_LIMIT))
+          }
+        },
+        _ => {}
+      }
+    }
+
+    Ok(filter)
+  }
+
+  fn events_list(req: &mut Request) -> IronResult<Response> {
+    let mut events: Vec<WebAccessEvent> = Vec::new();
+    let mut total: u32 = 0;
+    let mut resp: Option<Response> = None;
+
+    match WebServer::events_filter(req) {
+      Ok(filter) => {
+        if let Err(err) = system::acontrol_system_get_persist_drv(|drv| {
+          match (drv.event_count(&filter), drv.event_list(&filter)) {
+            (Ok(count), Ok(ret)) => {
+              total = count;
+              for event in ret {
+                events.push(WebAccessEvent {
+                  id: event.id,
+                  timestamp: event.timestamp,
+                  credential_type: String::from(event.credential_type.name()),
+                  credential_id: event.credential_id,
+                  reader: event.reader,
+                  user: event.user,
+                  decision: String::from(event.decision.name()),
+                  reason: event.reason,
+                  card_technology: event.card_technology,
+                  card_atqa: event.card_atqa,
+                  card_sak: event.card_sak
+                });
+              }
+            },
+            (Err(err), _) | (_, Err(err)) => {
+              resp = Some(Response::with((iron::status::InternalServerError,
+                serde_json::to_string(&WebServerDefaultResponse {ret: false, msg: format!("Error searching events: {}", err)} ).unwrap())
+              ));
+            }
+          }
+        }) {
+          resp = Some(Response::with((iron::status::InternalServerError,
+            serde_json::to_string(&WebServerDefaultResponse {ret: false, msg: format!("Persistence driver not found: {}", err)} ).unwrap())
+          ));
+        }
+
+        if resp.is_none() {
+          resp = Some(Response::with((iron::status::Ok,
+             serde_json::to_string(&WebServerEventListResponse {ret: true, msg: String::from("Ok"), total: total, offset: filter.offset, limit: filter.limit, events: events} ).unwrap())
+          ));
+        }
+      },
+      Err(err) => {
+        resp = Some(Response::with((iron::status::BadRequest,
+           serde_json::to_string(&WebServerDefaultResponse {ret: false, msg: err} ).unwrap())
+        ));
+      }
+    }
+
+    let mut resp_final = resp.unwrap();
+
+    resp_final.headers.set(iron::headers::ContentType(
+      iron::mime::Mime(iron::mime::TopLevel::Application, iron::mime::SubLevel::Json, vec![])
+    ));
+
+    Ok(resp_final)
+  }
+
+  fn route_param(req: &Request, name: &str) -> Option<String> {
+    req.extensions.get::<Router>().and_then(|router| router.find(name)).map(|value| String::from(value))
+  }
+
+  fn parse_hex(value: &str) -> Result<Vec<u8>, String> {
+    let digits: String = value.chars().filter(|c| *c != ':' && *c != '-').collect();
+    if digits.len() == 0 || digits.len() % 2 != 0 {
+      return Err(format!("Invalid card uuid: {}", value));
+    }
+
+    (0..digits.len()).step_by(2)
+      .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_err| format!("Invalid card uuid: {}", value)))
+      .collect()
+  }
+
+  fn nfc_delete(req: &mut Request) -> IronResult<Response> {
+    let uuid = match WebServer::route_param(req, "uuid").map(|uuid| WebServer::parse_hex(&uuid)) {
+      Some(Ok(uuid)) => uuid,
+      Some(Err(err)) => return Ok(WebServer::default_response(iron::status::BadRequest, false, err)),
+      None => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("Card uuid is required")))
+    };
+
+    match system::acontrol_system_nfc_delete(&uuid) {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::NotFound, false, err))
+    }
+  }
+
+  fn fingerprint_delete(req: &mut Request) -> IronResult<Response> {
+    let pos = match WebServer::route_param(req, "pos").map(|pos| pos.parse::<i32>()) {
+      Some(Ok(pos)) => pos,
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("Invalid fingerprint position")))
+    };
+
+    match system::acontrol_system_fingerprint_delete(pos) {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::BadRequest, false, err))
+    }
+  }
+
+  fn fingerprint_enroll_progress(_req: &mut Request) -> IronResult<Response> {
+    let progress = system::acontrol_system_fingerprint_enroll_progress();
+
+    Ok(WebServer::json_response(iron::status::Ok,
+      serde_json::to_string(&WebServerFingerprintEnrollProgressResponse {ret: true, msg: String::from("Ok"), enroll: WebFingerprintEnrollProgress {
+        status: String::from(progress.status.name()),
+        step: progress.step,
+        steps: progress.steps,
+        pos: progress.pos,
+        name: progress.name,
+        message: progress.message
+      }} ).unwrap()))
+  }
+
+  fn fingerprint_cancel_enroll(_req: &mut Request) -> IronResult<Response> {
+    match system::acontrol_system_fingerprint_cancel_enroll() {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::Conflict, false, err))
+    }
+  }
+
+  fn fingerprint_device(_req: &mut Request) -> IronResult<Response> {
+    match system::acontrol_system_fingerprint_device_info() {
+      Ok((module, info)) => Ok(WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerFingerprintDeviceResponse {ret: true, msg: String::from("Ok"), device: WebFingerprintDevice {
+          module: module,
+          firmware_version: format!("{:X}", info.firmware_version),
+          iso_area_max_size: info.iso_area_max_size,
+          serial_number: hex::encode_upper(&info.serial_number),
+          security_level: info.security_level,
+          enrolled: info.enrolled,
+          slots: info.slots
+        }} ).unwrap())),
+      Err(err) => Ok(WebServer::default_response(iron::status::ServiceUnavailable, false, err))
+    }
+  }
+
+  fn fingerprint_backup_list(_req: &mut Request) -> IronResult<Response> {
+    let mut result: Result<Vec<WebFingerprintBackup>, String> = Err(String::from("Persistence module not found"));
+
+    let _ret = system::acontrol_system_get_persist_drv(|drv| {
+      result = drv.fingerprint_template_list().map(|templates| templates.iter().map(|template| WebFingerprintBackup {
+        pos: template.pos, created_at: template.created_at
+      }).collect());
+    });
+
+    match result {
+      Ok(backups) => Ok(WebServer::json_response(iron::status::Ok,
+        serde_json::to_string(&WebServerFingerprintBackupListResponse {ret: true, msg: String::from("Ok"), backups: backups} ).unwrap())),
+      Err(err) => Ok(WebServer::default_response(iron::status::InternalServerError, false, err))
+    }
+  }
+
+  /* Backup and restore report every slot. ret is false when any of them
+   * failed, the others are still done. */
+  fn fingerprint_slots_response(result: Result<Vec<(i32, Result<(), String>)>, String>) -> Response {
+    match result {
+      Ok(results) => {
+        let slots: Vec<WebFingerprintSlotResult> = results.into_iter().map(|(pos, result)| match result {
+          Ok(()) => WebFingerprintSlotResult {pos: pos, ret: true, msg: String::from("Ok")},
+          Err(err) => WebFingerprintSlotResult {pos: pos, ret: false, msg: err}
+        }).collect();
+
+        let failed = slots.iter().filter(|slot| !slot.ret).count();
+        let msg = if failed == 0 { String::from("Ok") } else { format!("{} of {} slots failed", failed, slots.len()) };
+
+        WebServer::json_response(iron::status::Ok,
+          serde_json::to_string(&WebServerFingerprintSlotsResponse {ret: failed == 0, msg: msg, slots: slots} ).unwrap())
+      },
+      Err(err) => WebServer::default_response(iron::status::InternalServerError, false, err)
+    }
+  }
+
+  fn fingerprint_backup(_req: &mut Request) -> IronResult<Response> {
+    Ok(WebServer::fingerprint_slots_response(system::acontrol_system_fingerprint_backup()))
+  }
+
+  fn fingerprint_restore(_req: &mut Request) -> IronResult<Response> {
+    Ok(WebServer::fingerprint_slots_response(system::acontrol_system_fingerprint_restore()))
+  }
+
+  fn bluetooth_delete(req: &mut Request) -> IronResult<Response> {
+    let addr = match WebServer::route_param(req, "addr") {
+      Some(addr) => addr.to_uppercase(),
+      None => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("Bluetooth address is required")))
+    };
+
+    match system::acontrol_system_bluetooth_delete(&addr) {
+      Ok(()) => Ok(WebServer::default_response(iron::status::Ok, true, String::from("Ok"))),
+      Err(err) => Ok(WebServer::default_response(iron::status::NotFound, false, err))
+    }
+  }
+
   fn fingerprint_delete_all(_req: &mut Request) -> IronResult<Response> {
       let params: HashMap<String,String> = HashMap::new();
 
@@ -170,71 +1255,33 @@ impl WebServer {
   }
 
   fn fingerprint_start_enroll(req: &mut Request) -> IronResult<Response> {
-
     let mut params: HashMap<String,String> = HashMap::new();
-    let mut resp: Option<Response> = None;
-    let json_body = req.get::<bodyparser::Json>();
 
     acontrol_system_log!(LogType::Info, "Server Start Enroll");
 
-    match json_body {
-        Ok(Some(json_body)) => {
-
-          if json_body.get("name").is_some() {
-            params.insert(String::from("name"), String::from(json_body["name"].as_str().unwrap()));
-          }
-
-          if json_body.get("pos").is_some() {
-            params.insert(String::from("pos"), String::from(json_body["pos"].as_str().unwrap()));
-          }
-        },
-        Ok(None) => {
-          resp = Some(Response::with((iron::status::BadRequest,
-             serde_json::to_string(&WebServerDefaultResponse {ret: false, msg: String::from("No body. Or body is not a valid json")} ).unwrap())
-          ));
+    match req.get::<bodyparser::Json>() {
+      Ok(Some(json_body)) => {
+        if let Some(name) = json_body.get("name").and_then(|name| name.as_str()) {
+          params.insert(String::from("name"), String::from(name));
         }
-        Err(_err) => {
-          resp = Some(Response::with((iron::status::BadRequest,
-             serde_json::to_string(&WebServerDefaultResponse {ret: false, msg: String::from("No body. Or body is not a valid json")} ).unwrap())
-          ));
-        }
-    }
 
-    if params.contains_key(&String::from("name")) == false && resp.is_none() {
-      resp = Some(Response::with((iron::status::BadRequest,
-         serde_json::to_string(&WebServerDefaultResponse {ret: false, msg: String::from("Name field is required")} ).unwrap())
-      ));
-    }
-
-    if params.contains_key(&String::from("pos")) == false && resp.is_none() {
-      resp = Some(Response::with((iron::status::BadRequest,
-         serde_json::to_string(&WebServerDefaultResponse {ret: false, msg: String::from("Pos field is required")} ).unwrap())
-      ));
-    }
+        if let Some(user_id) = json_body.get("user_id").and_then(|user_id| user_id.as_i64()) {
+          params.insert(String::from("user_id"), user_id.to_string());
+        }
 
-    if resp.is_none() {
-      acontrol_system_log!(LogType::Info,"Calling system start enroll");
-      match system::acontrol_system_fingerprint_start_enroll(params) {
-        Ok(()) => {
-          resp = Some(Response::with((iron::status::Ok,
-             serde_json::to_string(&WebServerDefaultResponse {ret: true, msg: String::from("Ok")} ).unwrap())
-          ));
-        },
-        Err(err) => {
-          resp = Some(Response::with((iron::status::Ok,
-             serde_json::to_string(&WebServerDefaultResponse {ret: false, msg: err} ).unwrap())
-          ));
+        if let Err(err) = WebServer::fingerprint_enroll_params(&json_body, &mut params) {
+          return Ok(WebServer::default_response(iron::status::BadRequest, false, err));
         }
-      }
+      },
+      _ => return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("No body. Or body is not a valid json")))
     }
 
-    let mut final_resp = resp.unwrap();
-
-    final_resp.headers.set(iron::headers::ContentType(
-      iron::mime::Mime(iron::mime::TopLevel::Application, iron::mime::SubLevel::Json, vec![])
-    ));
+    if !params.contains_key("name") {
+      return Ok(WebServer::default_response(iron::status::BadRequest, false, String::from("Name field is required")));
+    }
 
-    Ok(final_resp)
+    acontrol_system_log!(LogType::Info,"Calling system start enroll");
+    Ok(WebServer::fingerprint_enroll_response(params))
   }
 }
 
@@ -249,27 +1296,87 @@ impl Server for WebServer {
     return Box::new(self);
   }
 
+  fn tls(&mut self, cert: &str, key: &str) -> Box<&mut dyn Server> {
+    self.tls = Some((cert.to_string(), key.to_string()));
+    return Box::new(self);
+  }
+
   fn init(&self) -> Result<(), String> {
     acontrol_system_log!(LogType::Info,"{}",self.signature());
 
     let mut router = Router::new();
 
     router.get("/nfc/card", WebServer::nfc_list, "nfc_list");
+    router.get("/nfc/reader", WebServer::nfc_readers, "nfc_readers");
     router.post("/nfc/card/authorize", WebServer::nfc_authorize,"nfc_authorize");
-    router.get("/nfc/card/restore", WebServer::nfc_restore, "nfc_restore");
+    router.post("/nfc/card/restore", WebServer::nfc_restore, "nfc_restore");
+    router.delete("/nfc/card/:uuid", WebServer::nfc_delete, "nfc_delete");
 
     router.post("/fingerprint/enroll",WebServer::fingerprint_start_enroll, "fingerprint_start_enroll");
-    router.get("/fingerprint/delete_all",WebServer::fingerprint_delete_all, "fingerprint_delete_all");
+    router.get("/fingerprint/enroll",WebServer::fingerprint_enroll_progress, "fingerprint_enroll_progress");
+    router.post("/fingerprint/enroll/cancel",WebServer::fingerprint_cancel_enroll, "fingerprint_cancel_enroll");
+    router.post("/fingerprint/delete_all",WebServer::fingerprint_delete_all, "fingerprint_delete_all");
+    router.get("/fingerprint/device",WebServer::fingerprint_device, "fingerprint_device");
+    router.get("/fingerprint/backup",WebServer::fingerprint_backup_list, "fingerprint_backup_list");
+    router.post("/fingerprint/backup",WebServer::fingerprint_backup, "fingerprint_backup");
+    router.post("/fingerprint/restore",WebServer::fingerprint_restore, "fingerprint_restore");
+    router.delete("/fingerprint/:pos",WebServer::fingerprint_delete, "fingerprint_delete");
+
+    router.delete("/bluetooth/:addr", WebServer::bluetooth_delete, "bluetooth_delete");
+
+    router.post("/auth/token", WebServer::auth_token_create, "auth_token_create");
+    router.delete("/auth/token", WebServer::auth_token_revoke, "auth_token_revoke");
+
+    router.get("/admins", WebServer::admins_list, "admins_list");
+    router.post("/admins", WebServer::admin_add, "admin_add");
+    router.delete("/admins/:id", WebServer::admin_delete, "admin_delete");
+
+    router.get("/users", WebServer::users_list, "users_list");
+    router.post("/users", WebServer::user_add, "user_add");
+    router.get("/users/:id", WebServer::user_get, "user_get");
+    router.put("/users/:id", WebServer::user_update, "user_update");
+    router.delete("/users/:id", WebServer::user_delete, "user_delete");
+    router.post("/users/:id/enable", WebServer::user_enable, "user_enable");
+    router.post("/users/:id/disable", WebServer::user_disable, "user_disable");
+    router.post("/users/:id/nfc/authorize", WebServer::user_nfc_authorize, "user_nfc_authorize");
+    router.post("/users/:id/fingerprint/enroll", WebServer::user_fingerprint_enroll, "user_fingerprint_enroll");
+
+    router.get("/groups", WebServer::groups_list, "groups_list");
+    router.post("/groups", WebServer::group_add, "group_add");
+    router.put("/groups/:id", WebServer::group_update, "group_update");
+    router.delete("/groups/:id", WebServer::group_delete, "group_delete");
+
+    router.get("/schedules", WebServer::schedules_list, "schedules_list");
+    router.post("/schedules", WebServer::schedule_add, "schedule_add");
+    router.get("/schedules/:id", WebServer::schedule_get, "schedule_get");
+    router.put("/schedules/:id", WebServer::schedule_update, "schedule_update");
+    router.delete("/schedules/:id", WebServer::schedule_delete, "schedule_delete");
+
+    router.get("/holidays", WebServer::holidays_list, "holidays_list");
+    router.post("/holidays", WebServer::holiday_add, "holiday_add");
+    router.delete("/holidays/:id", WebServer::holiday_delete, "holiday_delete");
+
+    router.put("/credentials/:type/:id", WebServer::credential_validity_update, "credential_validity_update");
+
+    router.get("/events", WebServer::events_list, "events_list");
+
+    let mut chain = Chain::new(router);
+    chain.link_before(AuthMiddleware);
 
-    let chain = Chain::new(router);
+    let addr = format!("{}:{}",self.host,self.port.to_string());
+    let listening = match self.tls {
+      Some((ref cert, ref key)) => Iron::new(chain).https(addr, WebServer::tls_server(cert, key)?),
+      None => Iron::new(chain).http(addr)
+    };
 
-    if let Err(err) = Iron::new(chain).http(format!("{}:{}",self.host,self.port.to_string())) {
+    if let Err(err) = listening {
       return Err(format!("{}(=> {})", "Error initializing webserver",err));
     }
     Ok( () )
   }
 
   fn signature(&self) -> String {
-    return format!("{}{}:{}",String::from("WebServer running: "), self.host, self.port);
+    let scheme = if self.tls.is_some() { "https" } else { "http" };
+    return format!("{}{}://{}:{}",String::from("WebServer running: "), scheme, self.host, self.port);
   }
 }
@@ -26,19 +26,24 @@
  * THE SOFTWARE.
  *
  */
-use crate::nfc::CardType;
+use crate::nfc::{CardInfo, TagEvent};
 use crate::log::{Log, LogType};
 use crate::bt::{Bluetooth, BluetoothDevice};
-use crate::fingerprint::{Fingerprint, FingerprintState, FingerprintData};
-use crate::nfc::{NfcReader};
+use crate::fingerprint::{Fingerprint, FingerprintState, FingerprintData, FingerprintDeviceInfo};
+use crate::nfc::{NfcReader, MifareKeys};
 use crate::audio::{Audio};
-use crate::persist::{Persist};
+use crate::persist::{Persist, User, Schedule, CredentialValidity, CredentialType, AccessDecision, AccessEvent, FingerprintTemplate};
 use crate::display::{Display, Animation, AnimationType, AnimationColor};
+use crate::lock::{Lock, LockState};
+use crate::door::{Door, DoorEvent};
+use crate::card_signature::{CardKeys, CardPayload, CARD_PAYLOAD_BLOCK, CARD_PAYLOAD_BLOCKS};
+use crate::template_vault::TemplateVault;
 
 use std::sync::{Mutex, Arc};
 use std::collections::HashMap;
-
-use std::process::Command;
+use std::time::{Duration, Instant};
+use std::thread;
+use chrono::{Local, Utc, Datelike, Timelike};
 
 #[derive(PartialEq)]
 #[allow(dead_code)]
@@ -56,6 +61,125 @@ pub enum BluetoothSystemState {
   AUTHORIZE,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum AuthPolicy {
+  AnyOne,
+  CardAndFinger,
+  BluetoothAndFinger
+}
+
+impl AuthPolicy {
+  pub fn name(&self) -> &'static str {
+    match *self {
+      AuthPolicy::AnyOne => "any-one",
+      AuthPolicy::CardAndFinger => "card+finger",
+      AuthPolicy::BluetoothAndFinger => "ble+finger",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<AuthPolicy> {
+    match name {
+      "any-one" => Some(AuthPolicy::AnyOne),
+      "card+finger" => Some(AuthPolicy::CardAndFinger),
+      "ble+finger" => Some(AuthPolicy::BluetoothAndFinger),
+      _ => None
+    }
+  }
+
+  /* The credential that opens the second factor window. The second factor is
+   * always a fingerprint. */
+  fn first_factor(&self) -> Option<CredentialType> {
+    match *self {
+      AuthPolicy::AnyOne => None,
+      AuthPolicy::CardAndFinger => Some(CredentialType::Nfc),
+      AuthPolicy::BluetoothAndFinger => Some(CredentialType::Bluetooth),
+    }
+  }
+}
+
+struct PendingFactor {
+  id: u64,
+  credential_type: CredentialType,
+  credential_id: String,
+  reader: String,
+  card: Option<CardInfo>,
+  credential_row: i32,
+  user_id: i32,
+  user: String,
+  expires: Instant,
+}
+
+pub struct MultiFactorState {
+  policy: AuthPolicy,
+  timeout: Duration,
+  pending: Option<PendingFactor>,
+  next_id: u64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FingerprintEnrollStatus {
+  Idle,
+  PlaceFinger,
+  LiftFinger,
+  Enrolled,
+  Failed,
+  Canceled,
+  TimedOut,
+}
+
+impl FingerprintEnrollStatus {
+  pub fn name(&self) -> &'static str {
+    match *self {
+      FingerprintEnrollStatus::Idle => "idle",
+      FingerprintEnrollStatus::PlaceFinger => "place_finger",
+      FingerprintEnrollStatus::LiftFinger => "lift_finger",
+      FingerprintEnrollStatus::Enrolled => "enrolled",
+      FingerprintEnrollStatus::Failed => "failed",
+      FingerprintEnrollStatus::Canceled => "canceled",
+      FingerprintEnrollStatus::TimedOut => "timeout",
+    }
+  }
+
+  pub fn in_progress(&self) -> bool {
+    *self == FingerprintEnrollStatus::PlaceFinger || *self == FingerprintEnrollStatus::LiftFinger
+  }
+}
+
+/* Last known state of the current (or last) enrolment, polled by API
+ * clients since the enrol request itself returns right away. */
+#[derive(Clone)]
+pub struct FingerprintEnrollProgress {
+  pub status: FingerprintEnrollStatus,
+  pub step: u8,
+  pub steps: u8,
+  pub pos: Option<u16>,
+  pub name: Option<String>,
+  pub message: String,
+}
+
+struct CredentialOwner {
+  id: i32,
+  validity: CredentialValidity,
+  user: User,
+}
+
+/* One configured NFC reader. Each one polls on its own thread and keeps its
+ * own enrolment state, so a card can be enrolled on one reader while the
+ * other keeps granting access. */
+struct NfcReaderEntry {
+  name: String,
+  drv: Mutex<Option<Box<dyn NfcReader + Send + Sync>>>,
+  state: Mutex<NFCSystemState>,
+  state_params: Mutex<HashMap<String,String>>,
+}
+
+pub struct DoorSystemState {
+  open: bool,
+  open_id: u64,
+  alarm: bool,
+  unlocked_at: Option<Instant>,
+}
+
 #[macro_export]
 macro_rules! acontrol_system_log {
   ($type:expr, $message:literal $(,$args:expr)*) => {{
@@ -74,17 +198,22 @@ macro_rules! acontrol_system_log {
 pub struct AControlSystem {
   bt_drv: Mutex<Option<Box<dyn Bluetooth + Send + Sync>>>,
   fingerprint_drv: Mutex<Option<Box<dyn Fingerprint + Send + Sync>>>,
-  nfc_drv: Mutex<Option<Box<dyn NfcReader + Send + Sync>>>,
+  nfc_readers: Mutex<Vec<Arc<NfcReaderEntry>>>,
   audio_drv: Mutex<Option<Box<dyn Audio + Send + Sync>>>,
   persist_drv:  Mutex<Option<Box<dyn Persist + Send + Sync>>>,
   display_drv: Mutex<Option<Box<dyn Display + Send + Sync>>>,
+  lock_drv: Mutex<Option<Box<dyn Lock + Send + Sync>>>,
+  door_drv: Mutex<Option<Box<dyn Door + Send + Sync>>>,
   pub log_drv: Arc<Mutex<Option<Box<dyn Log + Send + Sync>>>>,
-  nfc_state: Mutex<NFCSystemState>,
-  nfc_state_params: Mutex<HashMap<String,String>>,
   fingerprint_data: Mutex<FingerprintData>,
   fingerprint_last_state: Mutex<Option<FingerprintState>>,
+  fingerprint_enroll: Mutex<FingerprintEnrollProgress>,
   bt_state: Mutex<BluetoothSystemState>,
   bt_state_params: Mutex<HashMap<String,String>>,
+  door_state: Mutex<DoorSystemState>,
+  mfa_state: Mutex<MultiFactorState>,
+  card_keys: Mutex<Option<CardKeys>>,
+  template_vault: Mutex<Option<TemplateVault>>,
 }
 
 impl AControlSystem {
@@ -94,23 +223,33 @@ lazy_static!{
   pub static ref ACONTROL_SYSTEM: AControlSystem = AControlSystem {
     bt_drv: Mutex::new(Option::None),
     fingerprint_drv: Mutex::new(Option::None),
-    nfc_drv: Mutex::new(Option::None),
+    nfc_readers: Mutex::new(Vec::new()),
     audio_drv: Mutex::new(Option::None),
     persist_drv:  Mutex::new(Option::None),
     display_drv: Mutex::new(Option::None),
+    lock_drv: Mutex::new(Option::None),
+    door_drv: Mutex::new(Option::None),
     log_drv: Arc::new(Mutex::new(Option::None)),
-    nfc_state: Mutex::new(NFCSystemState::READ),
-    nfc_state_params: Mutex::new(HashMap::new()),
     fingerprint_data: Mutex::new(FingerprintData::empty()),
     fingerprint_last_state: Mutex::new(None),
+    fingerprint_enroll: Mutex::new(FingerprintEnrollProgress { status: FingerprintEnrollStatus::Idle, step: 0, steps: FINGERPRINT_ENROLL_STEPS, pos: None, name: None, message: String::new() }),
     bt_state: Mutex::new(BluetoothSystemState::READ),
     bt_state_params: Mutex::new(HashMap::new()),      
+    door_state: Mutex::new(DoorSystemState { open: false, open_id: 0, alarm: false, unlocked_at: None }),
+    mfa_state: Mutex::new(MultiFactorState { policy: AuthPolicy::AnyOne, timeout: *MFA_DEFAULT_TIMEOUT, pending: None, next_id: 0 }),
+    card_keys: Mutex::new(None),
+    template_vault: Mutex::new(None),
   };
   
-  static ref NFC_CARD_SIGNATURE: &'static str = &"ACONTROL_CARD\0\0\0";
-  static ref NFC_CARD_SIGNATURE_BLOCK: u8 = 1;
+  static ref LOCK_OPEN_DURATION: Duration = Duration::from_millis(5000);
+  static ref DOOR_HELD_OPEN_TIMEOUT: Duration = Duration::from_secs(30);
+  static ref DOOR_UNLOCK_GRACE: Duration = Duration::from_secs(5);
+  static ref CREDENTIAL_CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+  static ref MFA_DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
 }
 
+const FINGERPRINT_ENROLL_STEPS: u8 = 3;
+
 pub fn acontrol_system_end() -> bool {
   let asystem = acontrol_system_get();
   acontrol_system_log!(LogType::Info, "Cleaning all suffs");
@@ -120,84 +259,539 @@ pub fn acontrol_system_end() -> bool {
     if let Some(ref mut drv) = **drv_lock {
       if let Err(err) = drv.unload() {
         acontrol_system_log!(LogType::Error, "Error unloading bluetooth device (=> {})", err);
-        return false;  
-      } else {
-        *asystem.bt_drv.lock().unwrap() = Option::None;
+        return false;
       }
     };
+    **drv_lock = Option::None;
   }
 
   if let Ok(ref mut drv_lock) = asystem.audio_drv.lock() {
     if let Some(ref mut drv) = **drv_lock {
       if let Err(err) = drv.unload() {
         acontrol_system_log!(LogType::Error, "Error unloading audio device (=> {})", err);
-        return false;  
-      } else {
-        *asystem.audio_drv.lock().unwrap() = Option::None;
+        return false;
+      }
+    };
+    **drv_lock = Option::None;
+  }
+
+  for reader in acontrol_system_nfc_readers() {
+    if let Ok(ref mut drv_lock) = reader.drv.lock() {
+      if let Some(ref mut drv) = **drv_lock {
+        if let Err(err) = drv.unload() {
+          acontrol_system_log!(LogType::Error, "Error unloading nfc device {} (=> {})", reader.name, err);
+          return false;
+        }
+      };
+      **drv_lock = Option::None;
+    }
+  }
+
+  if let Ok(ref mut drv_lock) = asystem.fingerprint_drv.lock() {
+    if let Some(ref mut drv) = **drv_lock {
+      if let Err(err) = drv.unload() {
+        acontrol_system_log!(LogType::Error, "Error unloading fingerprint device (=> {})", err);
+        return false;
       }
     };
+    **drv_lock = Option::None;
   }
 
-  if let Ok(ref mut drv_lock) = asystem.nfc_drv.lock() {
+  if let Ok(ref mut drv_lock) = asystem.door_drv.lock() {
     if let Some(ref mut drv) = **drv_lock {
       if let Err(err) = drv.unload() {
-        acontrol_system_log!(LogType::Error, "Error unloading nfc device (=> {})", err);
-        return false;  
-      } else {
-        *asystem.nfc_drv.lock().unwrap() = Option::None;
+        acontrol_system_log!(LogType::Error, "Error unloading door device (=> {})", err);
+        return false;
       }
     };
+    **drv_lock = Option::None;
   }
 
-  if let Ok(ref mut drv_lock) = asystem.fingerprint_drv.lock() {
+  if let Ok(ref mut drv_lock) = asystem.lock_drv.lock() {
     if let Some(ref mut drv) = **drv_lock {
       if let Err(err) = drv.unload() {
-        acontrol_system_log!(LogType::Error, "Error unloading fingerprint device (=> {})", err);
-        return false;  
-      } else {
-        *asystem.fingerprint_drv.lock().unwrap() = Option::None;
+        acontrol_system_log!(LogType::Error, "Error unloading lock device (=> {})", err);
+        return false;
       }
     };
+    **drv_lock = Option::None;
   }
 
   if let Ok(ref mut drv_lock) = asystem.persist_drv.lock() {
     if let Some(ref mut drv) = **drv_lock {
       if let Err(err) = drv.unload() {
         acontrol_system_log!(LogType::Error, "Error unloading persistence device (=> {})", err);
-        return false;  
-      } else {
-        *asystem.persist_drv.lock().unwrap() = Option::None;
+        return false;
       }
     };
+    **drv_lock = Option::None;
   }
 
   if let Ok(ref mut drv_lock) = asystem.log_drv.lock() {
     if let Some(ref mut drv) = **drv_lock {
+      /* The log driver is the one locked here, report straight to stderr. */
       if let Err(err) = drv.unload() {
-        acontrol_system_log!(LogType::Error, "Error unloading persistence device (=> {})", err);
-        return false;  
-      } else {
-        *asystem.log_drv.lock().unwrap() = Option::None;
+        eprintln!("Error unloading log device (=> {})", err);
+        return false;
       }
     };
+    **drv_lock = Option::None;
   }
   return true;
 }
 
-pub fn  acontrol_system_set_mifare_keys(key_a: &Vec<u8>, key_b: &Vec<u8>) -> bool {
+pub fn  acontrol_system_set_mifare_keys(keys: MifareKeys) -> bool {
+  let readers = acontrol_system_nfc_readers();
+  if readers.len() == 0 {
+    return false;
+  }
+
+  if keys.is_diversified() {
+    acontrol_system_log!(LogType::Info, "Using per card diversified mifare keys");
+  }
+
+  for reader in readers {
+    if let Ok(ref mut drv_lock) = reader.drv.lock() {
+      if let Some(ref mut drv) = **drv_lock {
+        if let Err(err) = drv.set_auth_keys(keys.clone()) {
+          acontrol_system_log!(LogType::Error, "Error setting mifare key on reader {}: {}", reader.name, err);
+          return false;
+        }
+      };
+    }
+  }
+  return true;
+}
+
+fn acontrol_system_nfc_readers() -> Vec<Arc<NfcReaderEntry>> {
+  acontrol_system_get().nfc_readers.lock().unwrap().clone()
+}
+
+/* No name picks the first configured reader, so single reader setups do not
+ * have to know theirs. */
+fn acontrol_system_nfc_reader(name: Option<&str>) -> Option<Arc<NfcReaderEntry>> {
+  let readers = acontrol_system_get().nfc_readers.lock().unwrap();
+  match name {
+    Some(name) => readers.iter().find(|reader| reader.name == name).cloned(),
+    None => readers.first().cloned()
+  }
+}
+
+pub fn acontrol_system_nfc_reader_names() -> Vec<String> {
+  acontrol_system_nfc_readers().iter().map(|reader| reader.name.clone()).collect()
+}
+
+pub fn acontrol_system_set_card_keys(keys: CardKeys) -> bool {
   let asystem = acontrol_system_get();
-  if let Ok(ref mut drv_lock) = asystem.nfc_drv.lock() {
-    if let Some(ref mut drv) = **drv_lock {
-      if let Err(err) = drv.set_auth_keys(key_a, key_b) {
-        acontrol_system_log!(LogType::Error, "Error setting mifare key: {}", err);
-        return false;
-      }
-      return true;
-    };
+  if let Ok(ref mut card_keys) = asystem.card_keys.lock() {
+    acontrol_system_log!(LogType::Info, "Signing cards with key version {}", keys.current_version());
+    **card_keys = Some(keys);
+    return true;
   }
   return false;
 }
 
+pub fn acontrol_system_set_template_vault(vault: TemplateVault) -> bool {
+  let asystem = acontrol_system_get();
+  if let Ok(ref mut template_vault) = asystem.template_vault.lock() {
+    **template_vault = Some(vault);
+    return true;
+  }
+  return false;
+}
+
+fn acontrol_system_card_sign(uuid: &Vec<u8>, credential_id: i32) -> Result<Vec<u8>, String> {
+  match *acontrol_system_get().card_keys.lock().unwrap() {
+    Some(ref keys) => keys.sign(uuid, credential_id),
+    None => Err(String::from("Card keys not configured"))
+  }
+}
+
+fn acontrol_system_card_verify(uuid: &Vec<u8>, payload: &Vec<u8>) -> Result<CardPayload, String> {
+  match *acontrol_system_get().card_keys.lock().unwrap() {
+    Some(ref keys) => keys.verify(uuid, payload, Utc::now().timestamp()),
+    None => Err(String::from("Card keys not configured"))
+  }
+}
+
+fn acontrol_system_card_outdated(payload: &CardPayload) -> bool {
+  match *acontrol_system_get().card_keys.lock().unwrap() {
+    Some(ref keys) => payload.legacy || payload.key_version != keys.current_version(),
+    None => false
+  }
+}
+
+fn acontrol_system_enroll_feedback(success: bool) {
+  if success {
+    let _ret = acontrol_system_get_audio_drv(|audio|{
+      let _ret = audio.play_new();
+    });
+    let _ret = acontrol_system_get_display_drv( |display|{
+      let _ret = display.show_animation(Animation::Blink,AnimationColor::Green,AnimationType::Success, "Done",3);
+      let _ret = display.wait_animation_ends();
+    });
+  } else {
+    let _ret = acontrol_system_get_audio_drv(|audio|{
+      let _ret = audio.play_error();
+    });
+    let _ret = acontrol_system_get_display_drv( |display|{
+      let _ret = display.show_animation(Animation::Blink,AnimationColor::Red,AnimationType::Error,"Done",3);
+      let _ret = display.wait_animation_ends();
+    });
+  }
+}
+
+fn acontrol_system_lock_open() {
+  if let Ok(ref mut door_state) = acontrol_system_get().door_state.lock() {
+    door_state.unlocked_at = Some(Instant::now());
+  }
+
+  let _ret = acontrol_system_get_lock_drv(|lock| {
+    if let Err(err) = lock.pulse(*LOCK_OPEN_DURATION) {
+      acontrol_system_log!(LogType::Error, "Error opening the door: {}", err);
+    }
+  });
+}
+
+fn acontrol_system_lock_denied() {
+  let _ret = acontrol_system_get_lock_drv(|lock| {
+    if let Err(err) = lock.denied() {
+      acontrol_system_log!(LogType::Error, "Error notifying the lock: {}", err);
+    }
+  });
+}
+
+fn acontrol_system_card_id(uuid: &Vec<u8>) -> String {
+  uuid.iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join("")
+}
+
+fn acontrol_system_audit(credential_type: CredentialType, credential_id: &str, reader: &str, user: &str, decision: AccessDecision, reason: &str, card: Option<&CardInfo>) {
+  let mut event = AccessEvent::new(credential_type, credential_id, reader, user, decision, reason);
+  if let Some(card) = card {
+    event.card_technology = String::from(card.technology.name());
+    event.card_atqa = hex::encode_upper(&card.atqa);
+    event.card_sak = Some(card.sak);
+  }
+  let _ret = acontrol_system_get_persist_drv(|persist| {
+    if let Err(err) = persist.event_add(&event) {
+      acontrol_system_log!(LogType::Error, "Error persisting access event: {}", err);
+    }
+  });
+}
+
+fn acontrol_system_check_user(user: &User) -> Result<(), String> {
+  if !user.enabled {
+    return Err(String::from("User disabled"));
+  }
+
+  let now = Local::now();
+  let mut schedule: Result<Option<Schedule>, String> = Err(String::from("Persistence module not found"));
+  let mut holiday: Result<bool, String> = Ok(false);
+  let _ret = acontrol_system_get_persist_drv(|persist| {
+    schedule = persist.user_schedule(user.id);
+    holiday = persist.holiday_is(&now.format("%Y-%m-%d").to_string());
+  });
+
+  match (schedule, holiday) {
+    (Ok(None), _) => {},
+    (Ok(Some(_)), Ok(true)) => return Err(String::from("Outside schedule (holiday)")),
+    (Ok(Some(schedule)), Ok(false)) => {
+      if !schedule.allows(now.weekday().num_days_from_monday(), now.hour() * 60 + now.minute()) {
+        return Err(String::from("Outside schedule"));
+      }
+    },
+    (Err(err), _) | (_, Err(err)) => {
+      acontrol_system_log!(LogType::Error, "Error checking schedule for user {}: {}", user.name, err);
+      return Err(String::from("Error checking schedule"));
+    }
+  }
+
+  Ok(())
+}
+
+fn acontrol_system_reader_label(reader: &str) -> String {
+  if reader.len() > 0 { format!(" at reader {}", reader) } else { String::new() }
+}
+
+fn acontrol_system_authorize(credential_type: CredentialType, credential_id: &str, reader: &str, owner: Result<CredentialOwner, String>, card: Option<&CardInfo>) {
+  match owner {
+    Ok(owner) => {
+      let check = owner.validity.check(Utc::now().timestamp())
+        .and_then(|_| acontrol_system_check_user(&owner.user));
+
+      match check {
+        Ok(()) => acontrol_system_apply_policy(credential_type, credential_id, reader, &owner, card),
+        Err(reason) => acontrol_system_access_denied(credential_type, credential_id, reader, &owner.user.name, &reason, card)
+      }
+    },
+    Err(reason) => acontrol_system_access_denied(credential_type, credential_id, reader, "", &reason, card)
+  }
+}
+
+/* Credentials the hardware accepted but that are not mapped to any user. Only
+ * an enabled user passing its schedule can open the door, so these are always
+ * denied, whatever the policy. */
+fn acontrol_system_authorize_unregistered(credential_type: CredentialType, credential_id: &str, reader: &str) {
+  acontrol_system_access_denied(credential_type, credential_id, reader, "", "Credential not registered to a user", None);
+}
+
+fn acontrol_system_count_use(credential_type: CredentialType, id: i32) {
+  let _ret = acontrol_system_get_persist_drv(|persist| {
+    if let Err(err) = persist.credential_use(credential_type, id) {
+      acontrol_system_log!(LogType::Error, "Error counting credential use: {}", err);
+    }
+  });
+}
+
+fn acontrol_system_apply_policy(credential_type: CredentialType, credential_id: &str, reader: &str, owner: &CredentialOwner, card: Option<&CardInfo>) {
+  let asystem = acontrol_system_get();
+  let (policy, timeout) = {
+    let mfa_state = asystem.mfa_state.lock().unwrap();
+    (mfa_state.policy, mfa_state.timeout)
+  };
+
+  match policy.first_factor() {
+    None => {
+      acontrol_system_count_use(credential_type, owner.id);
+      acontrol_system_access_granted(credential_type, credential_id, reader, &owner.user.name, card);
+    },
+    Some(first_factor) if first_factor == credential_type => {
+      let pending_id = {
+        let mut mfa_state = asystem.mfa_state.lock().unwrap();
+        mfa_state.next_id = mfa_state.next_id.wrapping_add(1);
+        mfa_state.pending = Some(PendingFactor {
+          id: mfa_state.next_id,
+          credential_type: credential_type,
+          credential_id: String::from(credential_id),
+          reader: String::from(reader),
+          card: card.cloned(),
+          credential_row: owner.id,
+          user_id: owner.user.id,
+          user: owner.user.name.clone(),
+          expires: Instant::now() + timeout,
+        });
+        mfa_state.next_id
+      };
+
+      acontrol_system_log!(LogType::Info, "{} {} from {}{} accepted, waiting for fingerprint", credential_type.name(), credential_id, owner.user.name, acontrol_system_reader_label(reader));
+
+      let _ret = acontrol_system_get_audio_drv(|audio|{
+        let _ret = audio.play_alert();
+      });
+      let _ret = acontrol_system_get_display_drv( |display|{
+        let _ret = display.show_animation(Animation::MaterialSpinner, AnimationColor::Blue, AnimationType::Waiting, "Finger",0);
+      });
+
+      let _handler = thread::spawn(move || {
+        thread::sleep(timeout);
+
+        let expired = {
+          let mut mfa_state = acontrol_system_get().mfa_state.lock().unwrap();
+          match mfa_state.pending {
+            Some(ref pending) if pending.id == pending_id => mfa_state.pending.take(),
+            _ => None
+          }
+        };
+
+        if let Some(pending) = expired {
+          acontrol_system_access_denied(pending.credential_type, &pending.credential_id, &pending.reader, &pending.user, "Second factor timeout", pending.card.as_ref());
+        }
+      });
+    },
+    Some(first_factor) if credential_type == CredentialType::Fingerprint => {
+      let pending = asystem.mfa_state.lock().unwrap().pending.take();
+
+      match pending {
+        Some(ref pending) if pending.expires < Instant::now() => {
+          acontrol_system_access_denied(credential_type, credential_id, &pending.reader, &owner.user.name, "Second factor timeout", pending.card.as_ref());
+        },
+        Some(ref pending) if pending.user_id != owner.user.id => {
+          acontrol_system_access_denied(credential_type, credential_id, &pending.reader, &owner.user.name,
+            &format!("Second factor belongs to a different user than {} {}", pending.credential_type.name(), pending.credential_id), pending.card.as_ref());
+        },
+        Some(pending) => {
+          acontrol_system_count_use(pending.credential_type, pending.credential_row);
+          acontrol_system_count_use(credential_type, owner.id);
+          acontrol_system_access_granted(credential_type, &format!("{}+{}", pending.credential_id, credential_id), &pending.reader, &owner.user.name, pending.card.as_ref());
+        },
+        None => {
+          acontrol_system_access_denied(credential_type, credential_id, reader, &owner.user.name,
+            &format!("Policy {} requires {} first", policy.name(), first_factor.name()), card);
+        }
+      }
+    },
+    Some(_) => {
+      acontrol_system_access_denied(credential_type, credential_id, reader, &owner.user.name,
+        &format!("Credential not allowed by policy {}", policy.name()), card);
+    }
+  }
+}
+
+fn acontrol_system_credential_owner(persist: &mut Box<dyn Persist + Send + Sync>, id: i32, user_id: Option<i32>, validity: CredentialValidity) -> Result<CredentialOwner, String> {
+  match user_id {
+    Some(user_id) => persist.user_find(user_id).map(|user| CredentialOwner { id: id, validity: validity, user: user }),
+    None => Err(String::from("Credential not assigned to a user"))
+  }
+}
+
+fn acontrol_system_credential_cleanup() {
+  let mut result: Result<Vec<crate::persist::Fingerprint>, String> = Err(String::from("Persistence module not found"));
+  let _ret = acontrol_system_get_persist_drv(|persist| {
+    result = persist.credential_cleanup(Utc::now().timestamp());
+  });
+
+  match result {
+    Ok(fingerprints) => {
+      for fingerprint in fingerprints {
+        acontrol_system_log!(LogType::Info, "Expired fingerprint at position {} removed", fingerprint.pos);
+        if let Err(err) = acontrol_system_fingerprint_clear_slot(fingerprint.pos) {
+          acontrol_system_log!(LogType::Error, "Error clearing fingerprint slot {}: {}", fingerprint.pos, err);
+        }
+      }
+    },
+    Err(err) => acontrol_system_log!(LogType::Error, "Error cleaning up expired credentials: {}", err)
+  }
+}
+
+fn acontrol_system_access_granted(credential_type: CredentialType, credential_id: &str, reader: &str, user: &str, card: Option<&CardInfo>) {
+  acontrol_system_log!(LogType::Info, "Access granted: {} {} from {}{}", credential_type.name(), credential_id, user, acontrol_system_reader_label(reader));
+  acontrol_system_audit(credential_type, credential_id, reader, user, AccessDecision::Granted, "", card);
+
+  let _ret = acontrol_system_get_audio_drv(|audio|{
+    let _ret = audio.play_granted();
+  });
+  let _ret = acontrol_system_get_display_drv( |display|{
+    let _ret = display.show_animation(Animation::Blink,AnimationColor::Green,AnimationType::Success, "Done",3);
+    let _ret = display.when_animation_ends( || {
+      let _ret = acontrol_system_get_display_drv( |display|{
+        let _ret = display.show_animation(Animation::MaterialSpinner, AnimationColor::Orange, AnimationType::Waiting, "Waiting",0);
+      });
+    });
+  });
+
+  acontrol_system_lock_open();
+
+  let _ret = acontrol_system_get_display_drv( |display|{
+    let _ret = display.clear_and_stop_animations();
+  });
+}
+
+fn acontrol_system_access_denied(credential_type: CredentialType, credential_id: &str, reader: &str, user: &str, reason: &str, card: Option<&CardInfo>) {
+  acontrol_system_log!(LogType::Info, "Access denied: {} {} from {}{} ({})", credential_type.name(), credential_id, user, acontrol_system_reader_label(reader), reason);
+  acontrol_system_audit(credential_type, credential_id, reader, user, AccessDecision::Denied, reason, card);
+
+  let _ret = acontrol_system_get_audio_drv(|audio|{
+    let _ret = audio.play_denied();
+  });
+  let _ret = acontrol_system_get_display_drv( |display|{
+    let _ret = display.show_animation(Animation::Blink,AnimationColor::Red,AnimationType::Error,"Done",3);
+    let _ret = display.when_animation_ends( || {
+      let _ret = acontrol_system_get_display_drv( |display|{
+        let _ret = display.show_animation(Animation::MaterialSpinner, AnimationColor::Orange, AnimationType::Waiting, "Waiting",0);
+      });
+    });
+  });
+
+  acontrol_system_lock_denied();
+
+  let _ret = acontrol_system_get_display_drv( |display|{
+    let _ret = display.clear_and_stop_animations();
+  });
+}
+
+fn acontrol_system_door_alarm(message: &str) {
+  let asystem = acontrol_system_get();
+
+  if let Ok(ref mut door_state) = asystem.door_state.lock() {
+    door_state.alarm = true;
+  }
+
+  acontrol_system_log!(LogType::Warning, "Door alarm: {}", message);
+  acontrol_system_audit(CredentialType::Door, "contact", "", "", AccessDecision::Alarm, message, None);
+
+  let _ret = acontrol_system_get_audio_drv(|audio|{
+    let _ret = audio.play_error();
+  });
+  let _ret = acontrol_system_get_display_drv( |display|{
+    let _ret = display.show_animation(Animation::BlinkLoop,AnimationColor::Red,AnimationType::Error, message,0);
+  });
+}
+
+fn find_door_event(event: DoorEvent) -> bool {
+  let asystem = acontrol_system_get();
+
+  acontrol_system_log!(LogType::Debug, "Door event: {}", event.name());
+
+  match event {
+    DoorEvent::ExitRequest => {
+      acontrol_system_log!(LogType::Info, "Request to exit button pressed");
+      acontrol_system_audit(CredentialType::ExitButton, "rex", "", "", AccessDecision::Granted, "Request to exit", None);
+
+      let _ret = acontrol_system_get_display_drv(|display|{
+        let _ret = display.show_animation(Animation::Blink,AnimationColor::Green,AnimationType::Success, "Exit",3);
+      });
+
+      acontrol_system_lock_open();
+    },
+    DoorEvent::Opened => {
+      let mut lock_state: Result<LockState, String> = Err(String::from("Lock module not found"));
+      let _ret = acontrol_system_get_lock_drv(|lock| {
+        lock_state = lock.state();
+      });
+
+      /* People often push the door just after the relay released it, that
+       * is still a legitimate entry. */
+      let mut open_id: u64 = 0;
+      let mut recently_unlocked = false;
+      if let Ok(ref mut door_state) = asystem.door_state.lock() {
+        door_state.open = true;
+        door_state.open_id = door_state.open_id.wrapping_add(1);
+        open_id = door_state.open_id;
+        recently_unlocked = door_state.unlocked_at.map(|unlocked_at| unlocked_at.elapsed() < *LOCK_OPEN_DURATION + *DOOR_UNLOCK_GRACE).unwrap_or(false);
+      }
+
+      if recently_unlocked || lock_state == Ok(LockState::Open) {
+        acontrol_system_log!(LogType::Info, "Door opened");
+      } else {
+        acontrol_system_door_alarm("Door forced open");
+      }
+
+      let _handler = thread::spawn(move || {
+        thread::sleep(*DOOR_HELD_OPEN_TIMEOUT);
+
+        let mut held_open = false;
+        if let Ok(ref door_state) = acontrol_system_get().door_state.lock() {
+          held_open = door_state.open && door_state.open_id == open_id && !door_state.alarm;
+        }
+
+        if held_open {
+          acontrol_system_door_alarm("Door held open");
+        }
+      });
+    },
+    DoorEvent::Closed => {
+      let mut alarm = false;
+      if let Ok(ref mut door_state) = asystem.door_state.lock() {
+        alarm = door_state.alarm;
+        door_state.open = false;
+        door_state.alarm = false;
+        door_state.open_id = door_state.open_id.wrapping_add(1);
+      }
+
+      acontrol_system_log!(LogType::Info, "Door closed");
+
+      if alarm {
+        acontrol_system_log!(LogType::Info, "Door alarm cleared");
+        let _ret = acontrol_system_get_display_drv(|display|{
+          let _ret = display.clear_and_stop_animations();
+        });
+      }
+    }
+  }
+
+  return true;
+}
+
 fn find_bt_device(device: BluetoothDevice) -> bool {
   let asystem = acontrol_system_get();
   let mut next_bt_system_state: Option<BluetoothSystemState> = None;
@@ -211,37 +805,29 @@ fn find_bt_device(device: BluetoothDevice) -> bool {
           let _ret = display.show_animation(Animation::MaterialSpinner, AnimationColor::Orange, AnimationType::Waiting, "Waiting",0);
         });
 
-        let query = Command::new("/acontrol/query")
-        .output()
-        .expect("failed to execute child");
-
-        if String::from_utf8_lossy(query.stdout.as_slice()).trim_end().to_lowercase().eq(&String::from("close")) {
-                    
-          let _ret = acontrol_system_get_audio_drv(|audio|{
-            let _ret = audio.play_granted();
-          });
+        let mut lock_state: Result<LockState, String> = Err(String::from("Lock module not found"));
+        let _ret = acontrol_system_get_lock_drv(|lock| {
+          lock_state = lock.state();
+        });
 
-          let _ret = acontrol_system_get_display_drv(|display|{
-            let _ret = display.show_animation(Animation::Blink,AnimationColor::Green,AnimationType::Success, "Done",3);
-            let _ret = display.when_animation_ends( || {
-              let _ret = acontrol_system_get_display_drv( |display| {
-                let _ret = display.show_animation(Animation::MaterialSpinner, AnimationColor::Orange, AnimationType::Waiting, "Waiting",0);
-              });
-            });
+        if let Ok(LockState::Closed) = lock_state {
+          let addr = device.addr.clone();
+          let mut owner: Option<Result<CredentialOwner, String>> = None;
+          let _ret = acontrol_system_get_persist_drv( |persist_drv| {
+            if let Ok(bluetooth) = persist_drv.bluetooth_find(&addr.as_bytes().to_vec()) {
+              owner = Some(acontrol_system_credential_owner(persist_drv, bluetooth.id, bluetooth.user_id, bluetooth.validity));
+            }
           });
 
-          let granted = Command::new("/acontrol/granted")
-          .arg("-f")
-          .output()
-          .expect("failed to execute child");
-
-          let messages = String::from_utf8_lossy(granted.stdout.as_slice());
-          for message in messages.lines() {
-            acontrol_system_log!(LogType::Info, "granted: {}", message);
+          match owner {
+            Some(owner) => acontrol_system_authorize(CredentialType::Bluetooth, &addr, "", owner, None),
+            None => acontrol_system_authorize_unregistered(CredentialType::Bluetooth, &addr, "")
           }
-
         } else {
-          acontrol_system_log!(LogType::Warning, "Device is already open: {}", String::from_utf8_lossy(query.stdout.as_slice()).to_lowercase());
+          match lock_state {
+            Ok(state) => acontrol_system_log!(LogType::Warning, "Device is already open: {}", state.name()),
+            Err(err) => acontrol_system_log!(LogType::Error, "Error querying lock state: {}", err)
+          }
         }
         
         let _ret = acontrol_system_get_display_drv(|display|{
@@ -261,7 +847,15 @@ fn find_bt_device(device: BluetoothDevice) -> bool {
   return true;
 }
 
-fn find_finger(state: &FingerprintState, _value: Option<&str>) -> bool {
+fn acontrol_system_enroll_progress(status: FingerprintEnrollStatus, step: u8, message: &str) {
+  if let Ok(ref mut progress) = acontrol_system_get().fingerprint_enroll.lock() {
+    progress.status = status;
+    progress.step = step;
+    progress.message = String::from(message);
+  }
+}
+
+fn find_finger(state: &FingerprintState, value: Option<&str>) -> bool {
   let asystem = acontrol_system_get();
   if let Ok(ref mut last_state_locked) = asystem.fingerprint_last_state.lock() {
     if last_state_locked.is_none() || last_state_locked.unwrap() != *state {
@@ -298,6 +892,9 @@ fn find_finger(state: &FingerprintState, _value: Option<&str>) -> bool {
           });
         },
         FingerprintState::ERROR => {
+          if asystem.fingerprint_enroll.lock().unwrap().status.in_progress() {
+            acontrol_system_enroll_progress(FingerprintEnrollStatus::Failed, 0, value.unwrap_or("Enrolment failed"));
+          }
           let _ret = acontrol_system_get_audio_drv(|audio|{
             let _ret = audio.play_error();
           });
@@ -306,6 +903,38 @@ fn find_finger(state: &FingerprintState, _value: Option<&str>) -> bool {
             let _ret = display.wait_animation_ends();
           });
         },
+        FingerprintState::ENROLL_PLACE => {
+          let step = value.and_then(|value| value.parse::<u8>().ok()).unwrap_or(1);
+          acontrol_system_log!(LogType::Info, "Enrolment: place finger {}/{}", step, FINGERPRINT_ENROLL_STEPS);
+          acontrol_system_enroll_progress(FingerprintEnrollStatus::PlaceFinger, step, &format!("Place finger {}/{}", step, FINGERPRINT_ENROLL_STEPS));
+          let _ret = acontrol_system_get_audio_drv(|audio|{
+            let _ret = audio.play_alert();
+          });
+          let _ret = acontrol_system_get_display_drv( |display|{
+            let _ret = display.show_animation(Animation::MaterialSpinner, AnimationColor::Orange, AnimationType::Waiting, &format!("Place finger {}/{}", step, FINGERPRINT_ENROLL_STEPS),0);
+          });
+        },
+        FingerprintState::ENROLL_LIFT => {
+          let step = value.and_then(|value| value.parse::<u8>().ok()).unwrap_or(1);
+          acontrol_system_log!(LogType::Info, "Enrolment: capture {}/{} done, lift finger", step, FINGERPRINT_ENROLL_STEPS);
+          acontrol_system_enroll_progress(FingerprintEnrollStatus::LiftFinger, step, "Lift finger");
+          let _ret = acontrol_system_get_audio_drv(|audio|{
+            let _ret = audio.play_success();
+          });
+          let _ret = acontrol_system_get_display_drv( |display|{
+            let _ret = display.show_animation(Animation::BlinkLoop,AnimationColor::Green,AnimationType::Success, "Lift finger",0);
+          });
+        },
+        FingerprintState::ENROLL_TIMEOUT => {
+          acontrol_system_enroll_progress(FingerprintEnrollStatus::TimedOut, 0, "Enrolment timed out");
+          let _ret = acontrol_system_get_audio_drv(|audio|{
+            let _ret = audio.play_error();
+          });
+          let _ret = acontrol_system_get_display_drv( |display|{
+            let _ret = display.show_animation(Animation::Blink,AnimationColor::Red,AnimationType::Error,"Timeout",3);
+            let _ret = display.wait_animation_ends();
+          });
+        },
         FingerprintState::ENROLL => {
           let data_locked = asystem.fingerprint_data.lock().unwrap();
           if let (&Some(ref name), &Some(ref pos)) = (&data_locked.name, &data_locked.pos){
@@ -318,258 +947,213 @@ fn find_finger(state: &FingerprintState, _value: Option<&str>) -> bool {
               let _ret = display.show_animation(Animation::BlinkLoop,AnimationColor::Green,AnimationType::Success, "Done",3);
               let _ret = display.wait_animation_ends();
             });
-            acontrol_system_log!(LogType::Info, "User {} added at position {}", name, pos);
+
+            match acontrol_system_fingerprint_enroll(*pos as i32, name, data_locked.user_id) {
+              Ok(user) => {
+                acontrol_system_log!(LogType::Info, "Fingerprint of {} added at position {}", user, pos);
+                acontrol_system_enroll_progress(FingerprintEnrollStatus::Enrolled, FINGERPRINT_ENROLL_STEPS, "Ok");
+              },
+              Err(err) => {
+                acontrol_system_log!(LogType::Error, "Fingerprint enrolled at position {} but not persisted: {}", pos, err);
+                acontrol_system_enroll_progress(FingerprintEnrollStatus::Failed, FINGERPRINT_ENROLL_STEPS, &err);
+              }
+            }
           }
         },
         FingerprintState::AUTHORIZED => {
-          acontrol_system_log!(LogType::Info, "Fingerprint authorized");
-
-          let _ret = acontrol_system_get_audio_drv(|audio|{
-            let _ret = audio.play_granted();
+          let pos = value.and_then(|value| value.parse::<i32>().ok());
+          let mut owner: Option<Result<CredentialOwner, String>> = None;
+          let _ret = acontrol_system_get_persist_drv( |persist_drv| {
+            if let Some(Ok(fingerprint)) = pos.map(|pos| persist_drv.fingerprint_find(pos)) {
+              owner = Some(acontrol_system_credential_owner(persist_drv, fingerprint.id, fingerprint.user_id, fingerprint.validity));
+            }
           });
 
-          let _ret = acontrol_system_get_display_drv( |display|{
-            let _ret = display.show_animation(Animation::Blink,AnimationColor::Green,AnimationType::Success, "Done",3);
-            let _ret = display.when_animation_ends( || {
-              let _ret = acontrol_system_get_display_drv( |display|{
-                let _ret = display.show_animation(Animation::MaterialSpinner, AnimationColor::Orange, AnimationType::Waiting, "Waiting",0);
-              });
-            });
-          });
+          match owner {
+            Some(owner) => acontrol_system_authorize(CredentialType::Fingerprint, value.unwrap_or(""), "", owner, None),
+            None => acontrol_system_authorize_unregistered(CredentialType::Fingerprint, value.unwrap_or(""), "")
+          }
+        }
+        FingerprintState::NOT_AUTHORIZED => {
+          acontrol_system_access_denied(CredentialType::Fingerprint, value.unwrap_or(""), "", "", "Fingerprint not recognized", None);
+        }
+      }
+    }
+  }
+  return true;
+}
 
-          let granted = Command::new("/acontrol/granted")
-          .arg("-f")
-          .output()
-          .expect("failed to execute child");
+/* Records who the freshly enrolled slot belongs to, so Identify results can
+ * be mapped back to a user. Returns the user name. */
+fn acontrol_system_fingerprint_enroll(pos: i32, name: &str, user_id: Option<i32>) -> Result<String, String> {
+  let mut result: Result<String, String> = Err(String::from("Persistence driver not found"));
 
-          let messages = String::from_utf8_lossy(granted.stdout.as_slice());
-          for message in messages.lines() {
-            acontrol_system_log!(LogType::Info, "granted: {}", message);
-          }
+  let _ret = acontrol_system_get_persist_drv(|persist_drv| {
+    let user = match user_id {
+      Some(user_id) => persist_drv.user_find(user_id),
+      None => persist_drv.user_find_by_name(name).or_else(|_err| persist_drv.user_add(name))
+    };
 
-          let _ret = acontrol_system_get_display_drv( |display|{
-            let _ret = display.clear_and_stop_animations();
-          });
+    result = user.and_then(|user| {
+      /* The slot was free on the sensor, anything still stored for it is stale. */
+      if persist_drv.fingerprint_find(pos).is_ok() {
+        persist_drv.fingerprint_delete(pos)?;
+      }
+      persist_drv.fingerprint_template_delete(pos)?;
+      persist_drv.fingerprint_add(pos, &name.as_bytes().to_vec(), user.id).map(|_| user.name)
+    });
+  });
 
+  result
+}
+
+fn acontrol_system_nfc_enroll(reader: &NfcReaderEntry, nfc_drv: &mut Box<dyn NfcReader + Send + Sync>, uuid: &Vec<u8>) {
+  /* The card row must exist first: its id is part of the signed payload. */
+  let mut enrolled: Result<(i32, bool), String> = Err(String::from("Persistence driver not found"));
+  let _ = acontrol_system_get_persist_drv( |persist_drv| {
+    if let Ok(ref mut params) = reader.state_params.lock() {
+      enrolled = match persist_drv.nfc_find(uuid) {
+        Ok(card) => {
+          acontrol_system_log!(LogType::Warning, "Card already white listed. Signing it again");
+          Ok((card.id, false))
+        },
+        Err(_err) => {
+          let name = params.get("name").cloned().unwrap_or(String::new());
+          let user = match params.get("user_id").and_then(|user_id| user_id.parse::<i32>().ok()) {
+            Some(user_id) => persist_drv.user_find(user_id),
+            None => persist_drv.user_find_by_name(&name).or_else(|_err| persist_drv.user_add(&name))
+          };
+
+          let validity = CredentialValidity {
+            valid_from: params.get("valid_from").and_then(|value| value.parse::<i64>().ok()),
+            valid_until: params.get("valid_until").and_then(|value| value.parse::<i64>().ok()),
+            max_uses: params.get("max_uses").and_then(|value| value.parse::<u32>().ok()),
+            uses: 0
+          };
+
+          user
+            .and_then(|user| persist_drv.nfc_add(uuid, &name.as_bytes().to_vec(), user.id))
+            .and_then(|_| persist_drv.nfc_find(uuid))
+            .and_then(|card| persist_drv.credential_set_validity(CredentialType::Nfc, card.id, &validity).map(|_| (card.id, true)))
         }
-        FingerprintState::NOT_AUTHORIZED => {
-          acontrol_system_log!(LogType::Info, "Fingerprint not authorized");
-          let _ret = acontrol_system_get_audio_drv(|audio|{
-            let _ret = audio.play_denied();
-          });
-          let _ret = acontrol_system_get_display_drv( |display|{
-            let _ret = display.show_animation(Animation::BlinkLoop,AnimationColor::Red,AnimationType::Error,"Done",3);
-            let _ret = display.when_animation_ends( || {
-              let _ret = acontrol_system_get_display_drv( |display|{
-                let _ret = display.show_animation(Animation::MaterialSpinner, AnimationColor::Orange, AnimationType::Waiting, "Waiting",0);
-              });
+      };
+    }
+  });
+
+  match enrolled {
+    Ok((credential_id, added)) => {
+      let written = acontrol_system_card_sign(uuid, credential_id)
+        .and_then(|payload| nfc_drv.write_data(uuid, CARD_PAYLOAD_BLOCK, &payload));
+
+      match written {
+        Ok(_) => {
+          acontrol_system_log!(LogType::Info, "Ok... signature written successfully!");
+          if added {
+            acontrol_system_log!(LogType::Info, "Card successfully added");
+          }
+          acontrol_system_enroll_feedback(true);
+        },
+        Err(err) => {
+          acontrol_system_log!(LogType::Error, "No... we really have a problem here. Can't write either. => ({})", err);
+          if added {
+            let _ = acontrol_system_get_persist_drv( |persist_drv| {
+              if let Err(err) = persist_drv.nfc_delete(uuid) {
+                acontrol_system_log!(LogType::Error, "Error removing unsigned card: {}", err);
+              }
             });
-          });
-
-          let denieded = Command::new("/acontrol/denieded")
-          .arg("-f")
-          .output()
-          .expect("failed to execute child");
-
-          let messages = String::from_utf8_lossy(denieded.stdout.as_slice());
-          for message in messages.lines() {
-            acontrol_system_log!(LogType::Info, "denieded: {}", message);
           }
-
-          let _ret = acontrol_system_get_display_drv( |display|{
-            let _ret = display.clear_and_stop_animations();
-          });
+          acontrol_system_enroll_feedback(false);
         }
       }
+    },
+    Err(err) => {
+      acontrol_system_log!(LogType::Error, "Error persisting card info. Card not authorized! => ({})",err);
+      acontrol_system_enroll_feedback(false);
     }
   }
-  return true;
 }
 
-fn find_tag(_card_type: CardType, uuid: Vec<u8>) -> bool {
-  let asystem = acontrol_system_get();
-    if let Ok(ref mut drv_lock) = asystem.nfc_drv.lock() {
-      if let Some(ref mut nfc_drv) = **drv_lock {
-        let mut next_nfc_system_state: Option<NFCSystemState> = None;
-      if let Ok(ref mut nfc_state) = asystem.nfc_state.lock() {
+fn find_tag(reader: &str, event: TagEvent) -> bool {
+  acontrol_system_log!(LogType::Debug, "NFC event on reader {}: {}", reader, event.name());
+
+  let card = match event {
+    TagEvent::Presented(card) => card,
+    TagEvent::Removed(card) => {
+      acontrol_system_log!(LogType::Info, "Card removed from reader {}: {}", reader, card);
+      return true;
+    }
+  };
+
+  let uuid = card.uid.clone();
+  let entry = match acontrol_system_nfc_reader(Some(reader)) {
+    Some(entry) => entry,
+    None => return false
+  };
+
+  let mut next_nfc_system_state: Option<NFCSystemState> = None;
+  if let Ok(ref mut drv_lock) = entry.drv.lock() {
+    if let Some(ref mut nfc_drv) = **drv_lock {
+      if let Ok(ref mut nfc_state) = entry.state.lock() {
         match **nfc_state {
           NFCSystemState::READ => {
-            let _ = acontrol_system_get_persist_drv( |persist_drv| {
-              match nfc_drv.read_data(&uuid,*NFC_CARD_SIGNATURE_BLOCK,0) {
-                Ok(ref val) => {
-                  if let Ok(value) = String::from_utf8(val.to_vec()) {
-                      if value ==
-                        String::from_utf8(NFC_CARD_SIGNATURE.as_bytes().to_vec()).unwrap() {
-
-                        if let Ok(card) = persist_drv.nfc_find(&uuid) {
-                          acontrol_system_log!(LogType::Info, "Card {:?} from {} authorized!", uuid, String::from_utf8(card.name).unwrap());
-
-                          let _ret = acontrol_system_get_audio_drv(|audio|{
-                            let _ret = audio.play_granted();
-                          });
-                          let _ret = acontrol_system_get_display_drv( |display|{
-                            let _ret = display.show_animation(Animation::Blink,AnimationColor::Green,AnimationType::Success, "Done",3);
-                            let _ret = display.when_animation_ends( || {
-                              let _ret = acontrol_system_get_display_drv( |display|{
-                                let _ret = display.show_animation(Animation::MaterialSpinner, AnimationColor::Orange, AnimationType::Waiting, "Waiting",0);
-                              });
-                            });
-                          });
-                          let granted = Command::new("/acontrol/granted")
-                          .arg("-f")
-                          .output()
-                          .expect("failed to execute child");
-                
-                          let messages = String::from_utf8_lossy(granted.stdout.as_slice());
-                          for message in messages.lines() {
-                            acontrol_system_log!(LogType::Info, "granted: {}", message);
-                          }
-
-                          let _ret = acontrol_system_get_display_drv( |display|{
-                            let _ret = display.clear_and_stop_animations();
-                          });
+            let card_id = acontrol_system_card_id(&uuid);
+            acontrol_system_log!(LogType::Info, "Card presented to reader {}: {}", reader, card);
+            match nfc_drv.read_data(&uuid, CARD_PAYLOAD_BLOCK, CARD_PAYLOAD_BLOCKS - 1) {
+              Ok(ref val) => {
+                match acontrol_system_card_verify(&uuid, val) {
+                  Ok(payload) => {
+                    let mut owner: Result<CredentialOwner, String> = Err(String::from("Card not found"));
+                    let _ret = acontrol_system_get_persist_drv( |persist_drv| {
+                      if let Ok(card) = persist_drv.nfc_find(&uuid) {
+                        owner = if payload.legacy || card.id == payload.credential_id {
+                          acontrol_system_credential_owner(persist_drv, card.id, card.user_id, card.validity)
                         } else {
-                          acontrol_system_log!(LogType::Error, "Card {:?} not found!", uuid);
-
-                          let _ret = acontrol_system_get_audio_drv(|audio|{
-                            let _ret = audio.play_denied();
-                          });
-                          let _ret = acontrol_system_get_display_drv( |display|{
-                            let _ret = display.show_animation(Animation::Blink,AnimationColor::Red,AnimationType::Error,"Done",3);
-                            let _ret = display.when_animation_ends( || {
-                              let _ret = acontrol_system_get_display_drv( |display|{
-                                let _ret = display.show_animation(Animation::MaterialSpinner, AnimationColor::Orange, AnimationType::Waiting, "Waiting",0);
-                              });
-                            });
-                          });
-
-                          let denieded = Command::new("/acontrol/denieded")
-                          .arg("-f")
-                          .output()
-                          .expect("failed to execute child");
-                
-                          let messages = String::from_utf8_lossy(denieded.stdout.as_slice());
-                          for message in messages.lines() {
-                            acontrol_system_log!(LogType::Info, "denieded: {}", message);
-                          }
-
-                          let _ret = acontrol_system_get_display_drv( |display|{
-                            let _ret = display.clear_and_stop_animations();
-                          });
-                        }
-                      } else {
-                        acontrol_system_log!(LogType::Error, "Invalid card signature: {:?} - {:?}",val, NFC_CARD_SIGNATURE.as_bytes().to_vec());
-
-                        let _ret = acontrol_system_get_audio_drv(|audio|{
-                          let _ret = audio.play_denied();
-                        });
-                        let _ret = acontrol_system_get_display_drv( |display|{
-                          let _ret = display.show_animation(Animation::Blink,AnimationColor::Red,AnimationType::Error,"Done",3);
-                          let _ret = display.when_animation_ends( || {
-                            let _ret = acontrol_system_get_display_drv( |display|{
-                              let _ret = display.show_animation(Animation::MaterialSpinner, AnimationColor::Orange, AnimationType::Waiting, "Waiting",0);
-                            });
-                          });
-                        });
-
-                        let denieded = Command::new("/acontrol/denieded")
-                        .arg("-f")
-                        .output()
-                        .expect("failed to execute child");
-              
-                        let messages = String::from_utf8_lossy(denieded.stdout.as_slice());
-                        for message in messages.lines() {
-                          acontrol_system_log!(LogType::Info, "denieded: {}", message);
-                        }
-
-                        let _ret = acontrol_system_get_display_drv( |display|{
-                          let _ret = display.clear_and_stop_animations();
-                        });
+                          Err(String::from("Card signature does not match the enrolled card"))
+                        };
                       }
-                  } else {
-                    acontrol_system_log!(LogType::Error, "Error reading card block: {:X?}", val);
-                  }
-                },
-                Err(err) => {
-                  acontrol_system_log!(LogType::Error, "Error reading card: {}", err);
-
-                  let _ret = acontrol_system_get_audio_drv(|audio|{
-                    let _ret = audio.play_denied();
-                  });
-                  let _ret = acontrol_system_get_display_drv( |display|{
-                    let _ret = display.show_animation(Animation::Blink,AnimationColor::Red,AnimationType::Error,"Done",3);
-                    let _ret = display.when_animation_ends( || {
-                      let _ret = acontrol_system_get_display_drv( |display|{
-                        let _ret = display.show_animation(Animation::MaterialSpinner, AnimationColor::Orange, AnimationType::Waiting, "Waiting",0);
-                      });
                     });
-                  });
 
-                  let denieded = Command::new("/acontrol/denieded")
-                  .arg("-f")
-                  .output()
-                  .expect("failed to execute child");
-        
-                  let messages = String::from_utf8_lossy(denieded.stdout.as_slice());
-                  for message in messages.lines() {
-                    acontrol_system_log!(LogType::Info, "denieded: {}", message);
-                  }
+                    let resign = match owner {
+                      Ok(ref owner) if acontrol_system_card_outdated(&payload) => Some(owner.id),
+                      _ => None
+                    };
+
+                    acontrol_system_authorize(CredentialType::Nfc, &card_id, reader, owner, Some(&card));
+
+                    if let Some(credential_id) = resign {
+                      let written = acontrol_system_card_sign(&uuid, credential_id)
+                        .and_then(|payload| nfc_drv.write_data(&uuid, CARD_PAYLOAD_BLOCK, &payload));
 
-                  let _ret = acontrol_system_get_display_drv( |display|{
-                    let _ret = display.clear_and_stop_animations();
-                  });
+                      match written {
+                        Ok(_) => acontrol_system_log!(LogType::Info, "Card {} signed again with the current key", card_id),
+                        Err(err) => acontrol_system_log!(LogType::Warning, "Error signing card {} again: {}", card_id, err)
+                      }
+                    }
+                  },
+                  Err(reason) => {
+                    acontrol_system_log!(LogType::Error, "Card {} rejected: {}", card_id, reason);
+                    acontrol_system_access_denied(CredentialType::Nfc, &card_id, reader, "", &reason, Some(&card));
+                  }
                 }
-              }              
-            });
+              },
+              Err(err) => {
+                acontrol_system_log!(LogType::Error, "Error reading card: {}", err);
+                acontrol_system_access_denied(CredentialType::Nfc, &card_id, reader, "", "Error reading card", Some(&card));
+              }
+            }
           },
           NFCSystemState::AUTHORIZE => {
             if let Err(err) = nfc_drv.format(&uuid) {
               acontrol_system_log!(LogType::Error, "Error formating. Is this a new card? Let's try to write anyway");
               acontrol_system_log!(LogType::Error, "format return: {}", err);
             }
-  
-            next_nfc_system_state = Some(NFCSystemState::WRITE)
+
+            /* A card left on the reader is not reported again, write it now. */
+            acontrol_system_nfc_enroll(&entry, nfc_drv, &uuid);
+            next_nfc_system_state = Some(NFCSystemState::READ)
           }
           NFCSystemState::WRITE => {
-            if let Err(_err) = nfc_drv.write_data(&uuid, *NFC_CARD_SIGNATURE_BLOCK, &NFC_CARD_SIGNATURE.as_bytes().to_vec()) {
-              acontrol_system_log!(LogType::Error, "No... we really have a problem here. Can't write either.");
-              let _ret = acontrol_system_get_audio_drv(|audio|{
-                let _ret = audio.play_error();
-              });
-              let _ret = acontrol_system_get_display_drv( |display|{
-                let _ret = display.show_animation(Animation::Blink,AnimationColor::Red,AnimationType::Error,"Done",3);
-                let _ret = display.wait_animation_ends();
-              });
-            } else {
-              acontrol_system_log!(LogType::Info, "Ok... signature written successfully!");
-              let _ = acontrol_system_get_persist_drv( |persist_drv| {
-                if let Ok(ref mut params) = asystem.nfc_state_params.lock() {
-                  if let Err(_err) = persist_drv.nfc_find(&uuid) {
-                    if let Err(err) = persist_drv.nfc_add(&uuid, &params[&String::from("name")].as_bytes().to_vec()) {
-                      acontrol_system_log!(LogType::Error, "Error persisting card info. Card not authorized! => ({})",err);
-                      let _ret = acontrol_system_get_audio_drv(|audio|{
-                        let _ret = audio.play_error();
-                      });
-                      let _ret = acontrol_system_get_display_drv( |display|{
-                        let _ret = display.show_animation(Animation::Blink,AnimationColor::Red,AnimationType::Error,"Done",3);
-                        let _ret = display.wait_animation_ends();
-                      });
-                    } else {
-                      acontrol_system_log!(LogType::Info, "Card successfully added");
-                      let _ret = acontrol_system_get_audio_drv(|audio|{
-                        let _ret = audio.play_new();
-                      });
-                      let _ret = acontrol_system_get_display_drv( |display|{
-                        let _ret = display.show_animation(Animation::Blink,AnimationColor::Green,AnimationType::Success, "Done",3);
-                        let _ret = display.wait_animation_ends();
-                      });
-                    }
-                  } else {
-                    acontrol_system_log!(LogType::Warning, "Card already white listed");
-                  }
-                }
-              });
-            }
+            acontrol_system_nfc_enroll(&entry, nfc_drv, &uuid);
             next_nfc_system_state = Some(NFCSystemState::READ);
           },
           NFCSystemState::RESTORE => {
@@ -595,35 +1179,47 @@ fn find_tag(_card_type: CardType, uuid: Vec<u8>) -> bool {
             next_nfc_system_state = Some(NFCSystemState::READ)
           }
         }
-  
-        if let Some(state) = next_nfc_system_state {
-          acontrol_system_set_nfc_state(state,None);
-        }  
       }
     }
   }
+
+  if let Some(state) = next_nfc_system_state {
+    let _ret = acontrol_system_set_nfc_state(Some(reader), state, None);
+  }
   return true;
 }
 
-pub async fn acontrol_system_init(_params: &HashMap<String,String>,
+pub async fn acontrol_system_init(params: &HashMap<String,String>,
         bt_drv: Option<Box<dyn Bluetooth+Sync+Send>>,
         fingerprint_drv: Option<Box<dyn Fingerprint+Sync+Send>>,
-				nfc_drv: Option<Box<dyn NfcReader+Sync+Send>>,
+				nfc_drvs: Vec<(String, Box<dyn NfcReader+Sync+Send>)>,
 				audio_drv: Option<Box<dyn Audio+Sync+Send>>,
 				persist_drv: Option<Box<dyn Persist+Sync+Send>>,
         display_drv: Option<Box<dyn Display+Sync+Send>>,
+        lock_drv: Option<Box<dyn Lock+Sync+Send>>,
+        door_drv: Option<Box<dyn Door+Sync+Send>>,
         log_drv: Option<Box<dyn Log+Sync+Send>>) -> bool {
 
   let mut bt_drv_final = Option::None;
   let mut fingerprint_drv_final = Option::None;
-  let mut nfc_drv_final = Option::None;
   let mut audio_drv_final = Option::None;
   let mut persist_drv_final = Option::None;
   let mut display_drv_final = Option::None;
+  let mut lock_drv_final = Option::None;
+  let mut door_drv_final = Option::None;
   let mut log_drv_final = Option::None;
 
   let asystem = &ACONTROL_SYSTEM;
 
+  if let Ok(ref mut mfa_state) = asystem.mfa_state.lock() {
+    if let Some(policy) = params.get("AUTH_POLICY").and_then(|policy| AuthPolicy::from_name(policy)) {
+      mfa_state.policy = policy;
+    }
+    if let Some(timeout) = params.get("MFA_TIMEOUT").and_then(|timeout| timeout.parse::<u64>().ok()) {
+      mfa_state.timeout = Duration::from_secs(timeout);
+    }
+  }
+
   if let Some(mut drv) = log_drv {
     if let Err(err) = drv.init() {
       eprintln!("Error initializing log module: {}", err);
@@ -633,6 +1229,25 @@ pub async fn acontrol_system_init(_params: &HashMap<String,String>,
   }
   *asystem.log_drv.lock().unwrap() = log_drv_final;
 
+  if let Some(mut drv) = persist_drv {
+    if let Err(err) = drv.init(params) {
+      acontrol_system_log!(LogType::Error, "Error initializing persistence module: {}", err);
+      return false;
+    }
+    persist_drv_final = Some(drv);
+  }
+  *asystem.persist_drv.lock().unwrap() = persist_drv_final;
+
+  if let Ok(ref mfa_state) = asystem.mfa_state.lock() {
+    acontrol_system_log!(LogType::Info, "Access policy: {} (second factor timeout {}s)", mfa_state.policy.name(), mfa_state.timeout.as_secs());
+  }
+
+  let _handler = thread::spawn(|| {
+    loop {
+      acontrol_system_credential_cleanup();
+      thread::sleep(*CREDENTIAL_CLEANUP_INTERVAL);
+    }
+  });
 
   if let Some(mut drv) = bt_drv {
     if let Err(err) = drv.init().await {
@@ -652,14 +1267,18 @@ pub async fn acontrol_system_init(_params: &HashMap<String,String>,
   }
   *asystem.fingerprint_drv.lock().unwrap() = fingerprint_drv_final;
 
-  if let Some(mut drv) = nfc_drv {
+  for (name, mut drv) in nfc_drvs {
     if let Err(err) = drv.init() {
-      acontrol_system_log!(LogType::Error, "Error initializing nfc module: {}", err);
+      acontrol_system_log!(LogType::Error, "Error initializing nfc module {}: {}", name, err);
       return false;
     }
-    nfc_drv_final = Some(drv);
+    asystem.nfc_readers.lock().unwrap().push(Arc::new(NfcReaderEntry {
+      name: name,
+      drv: Mutex::new(Some(drv)),
+      state: Mutex::new(NFCSystemState::READ),
+      state_params: Mutex::new(HashMap::new()),
+    }));
   }
-  *asystem.nfc_drv.lock().unwrap() = nfc_drv_final;
 
   if let Some(mut drv) = audio_drv {
     if let Err(err) = drv.init(){
@@ -679,10 +1298,27 @@ pub async fn acontrol_system_init(_params: &HashMap<String,String>,
   }
   *asystem.display_drv.lock().unwrap() = display_drv_final;
 
-  if let Some(drv) = persist_drv {
-    persist_drv_final = Some(drv);
+  if let Some(mut drv) = lock_drv {
+    if let Err(err) = drv.init() {
+      acontrol_system_log!(LogType::Error, "Error initializing lock module: {}", err);
+      return false;      
+    }
+    lock_drv_final = Some(drv);
   }
-  *asystem.persist_drv.lock().unwrap() = persist_drv_final;
+  *asystem.lock_drv.lock().unwrap() = lock_drv_final;
+
+  if let Some(mut drv) = door_drv {
+    if let Err(err) = drv.init() {
+      acontrol_system_log!(LogType::Error, "Error initializing door module: {}", err);
+      return false;      
+    }
+    match drv.is_open() {
+      Ok(open) => asystem.door_state.lock().unwrap().open = open,
+      Err(err) => acontrol_system_log!(LogType::Warning, "Error reading initial door state: {}", err)
+    }
+    door_drv_final = Some(drv);
+  }
+  *asystem.door_drv.lock().unwrap() = door_drv_final;
 
   if let Ok(ref mut drv_locked) = asystem.bt_drv.lock() {
       if let Some(ref mut drv) = **drv_locked {
@@ -702,10 +1338,21 @@ pub async fn acontrol_system_init(_params: &HashMap<String,String>,
     };
   }
 
-  if let Ok(ref mut drv_locked) = asystem.nfc_drv.lock() {
+  for reader in acontrol_system_nfc_readers() {
+    if let Ok(ref mut drv_locked) = reader.drv.lock() {
+      if let Some(ref mut drv) = **drv_locked {
+        if let Err(err) = drv.find_tag(&reader.name, find_tag) {
+          acontrol_system_log!(LogType::Error, "NFC module {} error: {}", reader.name, err);
+          return false;
+        }
+      };
+    }
+  }
+
+  if let Ok(ref mut drv_locked) = asystem.door_drv.lock() {
     if let Some(ref mut drv) = **drv_locked {
-      if let Err(err) = drv.find_tag(find_tag) {
-        acontrol_system_log!(LogType::Error, "Fingerprint module error: {}", err);
+      if let Err(err) = drv.watch(find_door_event) {
+        acontrol_system_log!(LogType::Error, "Door module error: {}", err);
         return false;    
       }
     };
@@ -743,21 +1390,27 @@ pub fn acontrol_system_set_bluetooth_state(state: BluetoothSystemState, params:
   }
 }
 
-pub fn acontrol_system_set_nfc_state(state: NFCSystemState, params: Option<HashMap<String,String>>) {
-  acontrol_system_log!(LogType::Debug, "Changing NFC System State");
+pub fn acontrol_system_set_nfc_state(reader: Option<&str>, state: NFCSystemState, params: Option<HashMap<String,String>>) -> Result<(), String> {
+  let entry = match acontrol_system_nfc_reader(reader) {
+    Some(entry) => entry,
+    None => return Err(match reader {
+      Some(reader) => format!("NFC reader not found: {}", reader),
+      None => String::from("No NFC reader configured")
+    })
+  };
+
+  acontrol_system_log!(LogType::Debug, "Changing NFC System State of reader {}", entry.name);
 
   {
-    let asystem = acontrol_system_get();
     if let Some(p) = params {
-      if let Ok(ref mut state_params) = asystem.nfc_state_params.lock() {
+      if let Ok(ref mut state_params) = entry.state_params.lock() {
         **state_params = p;
       }
     }  
   }
 
   {
-    let asystem = acontrol_system_get();
-    if let Ok(ref mut nfc_state) = asystem.nfc_state.lock(){
+    if let Ok(ref mut nfc_state) = entry.state.lock(){
       **nfc_state = state;
   
       if **nfc_state == NFCSystemState::AUTHORIZE {
@@ -770,6 +1423,8 @@ pub fn acontrol_system_set_nfc_state(state: NFCSystemState, params: Option<HashM
       }
     }; 
   }
+
+  Ok(())
 }
 
 pub fn acontrol_system_fingerprint_delete_all(_params: HashMap<String,String>) -> Result<(), String> {
@@ -785,39 +1440,286 @@ pub fn acontrol_system_fingerprint_delete_all(_params: HashMap<String,String>) -
   } else {
     return Err(String::from("Fingerprint device not found"));
   }
-  Ok(())
+
+  /* Otherwise the next restore would bring every finger back. */
+  let mut result: Result<(), String> = Err(String::from("Persistence module not found"));
+  let _ret = acontrol_system_get_persist_drv(|persist| {
+    result = persist.fingerprint_delete_all();
+  });
+
+  result
 }
 
 
-pub fn acontrol_system_fingerprint_start_enroll(params: HashMap<String,String>) -> Result<(), String> {
+pub fn acontrol_system_fingerprint_start_enroll(params: HashMap<String,String>) -> Result<u16, String> {
   let asystem = acontrol_system_get();
 
   acontrol_system_log!(LogType::Info, "System Start Enroll");
-  
+
+  let name = match params.get("name") {
+    Some(name) => name.clone(),
+    None => return Err(String::from("Name field is required"))
+  };
+
+  let pos = match params.get("pos").map(|pos| pos.parse::<u16>()) {
+    Some(Ok(pos)) => Some(pos),
+    Some(Err(_)) => return Err(format!("Invalid fingerprint position: {}", params["pos"])),
+    None => None
+  };
+
+  /* Whatever was stored for the old finger goes with it, so a later restore
+   * or identify can not hand the slot back to its previous owner. */
+  if let Some(pos) = pos {
+    if params.get("overwrite").map(|overwrite| overwrite == "true").unwrap_or(false) {
+      let enrolled = match *asystem.fingerprint_drv.lock().unwrap() {
+        Some(ref mut drv) => drv.is_enrolled(pos)?,
+        None => return Err(String::from("Fingerprint device not found"))
+      };
+
+      if enrolled {
+        acontrol_system_log!(LogType::Warning, "Overwriting fingerprint at position {}", pos);
+        acontrol_system_fingerprint_clear_slot(pos as i32)?;
+      }
+    }
+  }
+
   if let Ok(ref mut drv_lock) = asystem.fingerprint_drv.lock() {
     if let Some(ref mut drv) = **drv_lock {
-      let pos = (&params[&String::from("pos")]).parse::<u16>().unwrap();
-      acontrol_system_log!(LogType::Info, "Adding a fingerprint at pos {} to {}", pos, &params[&String::from("name")]);
-
       if let Ok(ref mut data_locked) = asystem.fingerprint_data.lock() {
 
-        //*ACONTROL_SYSTEM.fingerprint_data.lock().unwrap() = FingerprintData::new(pos, &params[&String::from("name")]);
+        data_locked.pos = pos;
+        data_locked.name = Some(name.clone());
+        data_locked.user_id = params.get("user_id").and_then(|user_id| user_id.parse::<i32>().ok());
+
+        /* Set before the driver starts, its first progress report may
+         * come in before start_enroll returns. */
+        *asystem.fingerprint_enroll.lock().unwrap() = FingerprintEnrollProgress { status: FingerprintEnrollStatus::PlaceFinger, step: 1,
+          steps: FINGERPRINT_ENROLL_STEPS, pos: pos, name: Some(name.clone()), message: format!("Place finger 1/{}", FINGERPRINT_ENROLL_STEPS) };
+
+        let pos = match drv.start_enroll(&*data_locked) {
+          Ok(pos) => pos,
+          Err(err) => {
+            acontrol_system_enroll_progress(FingerprintEnrollStatus::Failed, 0, &err);
+            return Err(err);
+          }
+        };
         data_locked.pos = Some(pos);
-        data_locked.name = Some(params[&String::from("name")].clone());
+        asystem.fingerprint_enroll.lock().unwrap().pos = Some(pos);
 
-        //let mut data = *ACONTROL_SYSTEM.fingerprint_data.lock().unwrap();
+        acontrol_system_log!(LogType::Info, "Adding a fingerprint at pos {} to {}", pos, name);
+        return Ok(pos);
+      }
+    }
+  }
 
-        if !drv.start_enroll(&*data_locked) {
-          return Err(String::from("Error starting enrollment"));
-        }
+  Err(String::from("Fingerprint device not found"))
+}
+
+pub fn acontrol_system_fingerprint_cancel_enroll() -> Result<(), String> {
+  let asystem = acontrol_system_get();
+
+  match *asystem.fingerprint_drv.lock().unwrap() {
+    Some(ref mut drv) => drv.cancel_enroll()?,
+    None => return Err(String::from("Fingerprint device not found"))
+  }
+
+  acontrol_system_log!(LogType::Info, "Fingerprint enrolment canceled");
+  acontrol_system_enroll_progress(FingerprintEnrollStatus::Canceled, 0, "Enrolment canceled");
+  Ok(())
+}
+
+pub fn acontrol_system_fingerprint_enroll_progress() -> FingerprintEnrollProgress {
+  acontrol_system_get().fingerprint_enroll.lock().unwrap().clone()
+}
+
+pub fn acontrol_system_fingerprint_device_info() -> Result<(String, FingerprintDeviceInfo), String> {
+  let asystem = acontrol_system_get();
+
+  match *asystem.fingerprint_drv.lock().unwrap() {
+    Some(ref mut drv) => drv.device_info().map(|info| (drv.signature(), info)),
+    None => Err(String::from("Fingerprint device not found"))
+  }
+}
+
+fn acontrol_system_fingerprint_clear_slot(pos: i32) -> Result<(), String> {
+  let asystem = acontrol_system_get();
+
+  /* The sensor first: if it still holds the finger, the row and the backup
+   * are what let us find and retry it. */
+  match *asystem.fingerprint_drv.lock().unwrap() {
+    Some(ref mut drv) => drv.delete(pos as u16)?,
+    None => return Err(String::from("Fingerprint device not found"))
+  }
+
+  /* A revoked finger must not come back with the next restore. */
+  let mut result: Result<(), String> = Err(String::from("Persistence module not found"));
+  let _ret = acontrol_system_get_persist_drv(|persist| {
+    result = persist.fingerprint_template_delete(pos).and_then(|_| match persist.fingerprint_delete(pos) {
+      Err(ref err) if err == "Fingerprint Not Found" => Ok(()),
+      other => other
+    });
+  });
+
+  result
+}
+
+/* Copies the template of every enrolled position into the database. The
+ * result holds one entry per position, so a bad slot does not abort the
+ * whole backup. */
+pub fn acontrol_system_fingerprint_backup() -> Result<Vec<(i32, Result<(), String>)>, String> {
+  let asystem = acontrol_system_get();
+  let mut positions: Result<Vec<i32>, String> = Err(String::from("Persistence module not found"));
+
+  let _ret = acontrol_system_get_persist_drv(|persist| {
+    positions = persist.fingerprint_list().map(|fingerprints| fingerprints.iter().map(|fingerprint| fingerprint.pos).collect());
+  });
+
+  let mut positions = positions?;
+  positions.sort();
+  positions.dedup();
+
+  let mut results: Vec<(i32, Result<(), String>)> = Vec::new();
+
+  for pos in positions {
+    let template = match *asystem.fingerprint_drv.lock().unwrap() {
+      Some(ref mut drv) => drv.export_template(pos as u16),
+      None => return Err(String::from("Fingerprint device not found"))
+    };
+
+    let sealed = template.and_then(|template| match *asystem.template_vault.lock().unwrap() {
+      Some(ref vault) => vault.seal(pos, &template),
+      None => Err(String::from("Fingerprint template key not loaded"))
+    });
+
+    let result = sealed.and_then(|sealed| {
+      let mut result: Result<(), String> = Err(String::from("Persistence module not found"));
+      let _ret = acontrol_system_get_persist_drv(|persist| {
+        result = persist.fingerprint_template_save(&FingerprintTemplate { pos: pos, template: sealed, created_at: Utc::now().timestamp() });
+      });
+      result
+    });
+
+    match result {
+      Ok(()) => acontrol_system_log!(LogType::Info, "Fingerprint template at position {} backed up", pos),
+      Err(ref err) => acontrol_system_log!(LogType::Error, "Error backing up fingerprint template at position {}: {}", pos, err)
+    }
+
+    results.push((pos, result));
+  }
+
+  Ok(results)
+}
+
+/* Writes every stored template back to the sensor, typically a replacement
+ * one. Slots keep their positions so the database mapping stays valid. */
+pub fn acontrol_system_fingerprint_restore() -> Result<Vec<(i32, Result<(), String>)>, String> {
+  let asystem = acontrol_system_get();
+  let mut templates: Result<Vec<FingerprintTemplate>, String> = Err(String::from("Persistence module not found"));
+  let mut positions: Result<Vec<i32>, String> = Err(String::from("Persistence module not found"));
+
+  let _ret = acontrol_system_get_persist_drv(|persist| {
+    templates = persist.fingerprint_template_list();
+    positions = persist.fingerprint_list().map(|fingerprints| fingerprints.iter().map(|fingerprint| fingerprint.pos).collect());
+  });
+
+  let positions = positions?;
+  let mut results: Vec<(i32, Result<(), String>)> = Vec::new();
+
+  for template in templates? {
+    /* A backup left behind by a revoked finger must not enrol it again. */
+    if !positions.contains(&template.pos) {
+      acontrol_system_log!(LogType::Warning, "Skipping fingerprint template at position {}: not registered", template.pos);
+      continue;
+    }
+
+    let plain = match *asystem.template_vault.lock().unwrap() {
+      Some(ref vault) => vault.open(template.pos, &template.template),
+      None => Err(String::from("Fingerprint template key not loaded"))
+    };
+
+    let result = match plain {
+      Ok(plain) => match *asystem.fingerprint_drv.lock().unwrap() {
+        Some(ref mut drv) => drv.import_template(template.pos as u16, &plain),
+        None => return Err(String::from("Fingerprint device not found"))
+      },
+      Err(err) => Err(err)
+    };
+
+    match result {
+      Ok(()) => acontrol_system_log!(LogType::Info, "Fingerprint template at position {} restored", template.pos),
+      Err(ref err) => acontrol_system_log!(LogType::Error, "Error restoring fingerprint template at position {}: {}", template.pos, err)
+    }
+
+    results.push((template.pos, result));
+  }
+
+  Ok(results)
+}
+
+pub fn acontrol_system_user_delete(id: i32) -> Result<(), String> {
+  let mut result: Result<(), String> = Err(String::from("Persistence module not found"));
+  let mut positions: Vec<i32> = Vec::new();
+
+  let _ret = acontrol_system_get_persist_drv(|persist| {
+    if let Ok(fingerprints) = persist.user_fingerprints(id) {
+      positions = fingerprints.iter().map(|fingerprint| fingerprint.pos).collect();
+    }
+    result = persist.user_delete(id);
+  });
+
+  if result.is_ok() {
+    acontrol_system_log!(LogType::Info, "User {} deleted", id);
+    for pos in positions {
+      if let Err(err) = acontrol_system_fingerprint_clear_slot(pos) {
+        acontrol_system_log!(LogType::Error, "Error clearing fingerprint slot {}: {}", pos, err);
       }
     }
-  } else {
-    return Err(String::from("Fingerprint device not found"));
   }
+
+  result
+}
+
+pub fn acontrol_system_nfc_delete(uuid: &Vec<u8>) -> Result<(), String> {
+  let mut result: Result<(), String> = Err(String::from("Persistence module not found"));
+
+  let _ret = acontrol_system_get_persist_drv(|persist| {
+    result = persist.nfc_delete(uuid);
+  });
+
+  if result.is_ok() {
+    acontrol_system_log!(LogType::Info, "Card {} revoked", acontrol_system_card_id(uuid));
+  }
+
+  result
+}
+
+pub fn acontrol_system_fingerprint_delete(pos: i32) -> Result<(), String> {
+  if pos < 0 || pos > u16::MAX as i32 {
+    return Err(format!("Invalid fingerprint position: {}", pos));
+  }
+
+  /* The sensor slot is what actually grants access, clear it even when the
+   * database does not know about this position. */
+  acontrol_system_fingerprint_clear_slot(pos)?;
+
+  acontrol_system_log!(LogType::Info, "Fingerprint at position {} deleted", pos);
   Ok(())
 }
 
+pub fn acontrol_system_bluetooth_delete(addr: &str) -> Result<(), String> {
+  let mut result: Result<(), String> = Err(String::from("Persistence module not found"));
+
+  let _ret = acontrol_system_get_persist_drv(|persist| {
+    result = persist.bluetooth_delete(&addr.as_bytes().to_vec());
+  });
+
+  if result.is_ok() {
+    acontrol_system_log!(LogType::Info, "Bluetooth device {} revoked", addr);
+  }
+
+  result
+}
+
 pub fn acontrol_system_get_persist_drv<F, T>(f: F) -> Result<(),String>
   where F: FnOnce(&mut Box<dyn Persist + Send + Sync>) -> T, {
     let asystem = acontrol_system_get();
@@ -864,6 +1766,20 @@ pub fn acontrol_system_get_audio_drv<F, T>(f:F) -> Result<(),String>
     Ok(())
 }
 
+pub fn acontrol_system_get_lock_drv<F, T>(f:F) -> Result<(),String>
+  where F: FnOnce(&mut Box<dyn Lock + Send + Sync>) -> T {
+    let asystem = acontrol_system_get();
+
+    if let Ok(ref mut drv_locked) = asystem.lock_drv.lock() {
+      if let Some(ref mut drv) = **drv_locked {
+        f(drv);
+      }
+    } else {
+      return Err(String::from("Lock module not found"));
+    }
+    Ok(())
+}
+
 pub fn acontrol_system_get() -> &'static ACONTROL_SYSTEM {
   return &ACONTROL_SYSTEM;
 }
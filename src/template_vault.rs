@@ -0,0 +1,184 @@
+/**
+ * @file   template_vault.rs
+ * @author Otavio Ribeiro
+ * @date   17 Oct 2026
+ * @brief  Encrypted fingerprint template storage
+ *
+ * Copyright (c) 2026 Otávio Ribeiro <otavio.ribeiro@gmail.com>
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use crate::crypto;
+
+use aes::Aes256;
+use aes::cipher::{KeyInit, generic_array::GenericArray};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use rand::RngCore;
+
+/* Sealed template layout:
+ *
+ * | 0       | 1..17 | 17..n-32                  | n-32..n                          |
+ * | format  | IV    | AES-256-CBC, PKCS#7 padded | HMAC-SHA256 (format, pos, IV, ct) | */
+const TEMPLATE_FORMAT: u8 = 1;
+const TEMPLATE_IV_LEN: usize = 16;
+const TEMPLATE_MAC_LEN: usize = 32;
+const BLOCK_SIZE: usize = 16;
+
+const TEMPLATE_KEY_FILE: &str = "template-key";
+const TEMPLATE_KEY_LEN: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/* Encrypts fingerprint templates before they reach the database.
+ *
+ * The slot position is authenticated with the template, so a backup can
+ * only be restored into the slot it was taken from. */
+pub struct TemplateVault {
+  cipher: Aes256,
+  mac_key: Vec<u8>,
+}
+
+impl TemplateVault {
+  /* Uses `secret` (hex, 32 bytes) when given. Otherwise a random secret
+   * is created under `data_path` on first run and reused afterwards. */
+  pub fn load(secret: Option<&String>, data_path: &str) -> Result<TemplateVault, String> {
+    let secret = match secret {
+      Some(secret) => match hex::decode(secret.trim()) {
+        Ok(secret) if secret.len() == TEMPLATE_KEY_LEN => secret,
+        _ => return Err(format!("Fingerprint template key must be {} hex encoded bytes", TEMPLATE_KEY_LEN))
+      },
+      None => crypto::load_or_create_secret(data_path, TEMPLATE_KEY_FILE, TEMPLATE_KEY_LEN, "template key")?
+    };
+
+    let enc_key = TemplateVault::derive(&secret, b"acontrol template enc")?;
+    let mac_key = TemplateVault::derive(&secret, b"acontrol template mac")?;
+
+    Ok(TemplateVault { cipher: Aes256::new(GenericArray::from_slice(&enc_key)), mac_key: mac_key })
+  }
+
+  fn derive(secret: &[u8], label: &[u8]) -> Result<Vec<u8>, String> {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(secret).map_err(|err| format!("{}", err))?;
+    mac.update(label);
+    Ok(mac.finalize().into_bytes().to_vec())
+  }
+
+  fn mac(&self, pos: i32, data: &[u8]) -> Result<HmacSha256, String> {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.mac_key).map_err(|err| format!("{}", err))?;
+    mac.update(&pos.to_be_bytes());
+    mac.update(data);
+    Ok(mac)
+  }
+
+  /* Encrypts the template read from slot `pos`. */
+  pub fn seal(&self, pos: i32, template: &[u8]) -> Result<Vec<u8>, String> {
+    let mut iv = [0u8; TEMPLATE_IV_LEN];
+    rand::thread_rng().fill_bytes(&mut iv);
+
+    let pad = BLOCK_SIZE - template.len() % BLOCK_SIZE;
+    let mut plain = template.to_vec();
+    plain.extend(std::iter::repeat(pad as u8).take(pad));
+
+    let mut sealed: Vec<u8> = Vec::with_capacity(1 + TEMPLATE_IV_LEN + plain.len() + TEMPLATE_MAC_LEN);
+    sealed.push(TEMPLATE_FORMAT);
+    sealed.extend(&iv);
+
+    sealed.extend(crypto::cbc_encrypt(&self.cipher, &mut iv, &plain));
+
+    let tag = self.mac(pos, &sealed)?.finalize().into_bytes();
+    sealed.extend(&tag[..]);
+
+    Ok(sealed)
+  }
+
+  /* Checks and decrypts a template sealed for slot `pos`. */
+  pub fn open(&self, pos: i32, sealed: &[u8]) -> Result<Vec<u8>, String> {
+    let header = 1 + TEMPLATE_IV_LEN;
+
+    if sealed.len() < header + BLOCK_SIZE + TEMPLATE_MAC_LEN || (sealed.len() - header - TEMPLATE_MAC_LEN) % BLOCK_SIZE != 0 {
+      return Err(String::from("Invalid template backup"));
+    }
+
+    if sealed[0] != TEMPLATE_FORMAT {
+      return Err(format!("Unsupported template backup format {}", sealed[0]));
+    }
+
+    let (body, tag) = sealed.split_at(sealed.len() - TEMPLATE_MAC_LEN);
+    if self.mac(pos, body)?.verify_slice(tag).is_err() {
+      return Err(format!("Template backup for position {} failed authentication", pos));
+    }
+
+    let mut iv = [0u8; TEMPLATE_IV_LEN];
+    iv.copy_from_slice(&body[1..header]);
+    let mut plain = crypto::cbc_decrypt(&self.cipher, &mut iv, &body[header..]);
+
+    let pad = *plain.last().unwrap() as usize;
+    if pad == 0 || pad > BLOCK_SIZE || pad > plain.len() {
+      return Err(String::from("Invalid template backup padding"));
+    }
+    plain.truncate(plain.len() - pad);
+
+    Ok(plain)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn vault() -> TemplateVault {
+    let secret = String::from("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+    TemplateVault::load(Some(&secret), "/nonexistent").unwrap()
+  }
+
+  #[test]
+  fn seal_open_round_trip() {
+    let vault = vault();
+
+    for len in [1, 15, 16, 17, 498].iter() {
+      let template: Vec<u8> = (0..*len).map(|i| i as u8).collect();
+      let sealed = vault.seal(7, &template).unwrap();
+
+      assert_eq!(sealed.len(), 1 + TEMPLATE_IV_LEN + (len / BLOCK_SIZE + 1) * BLOCK_SIZE + TEMPLATE_MAC_LEN);
+      assert_eq!(vault.open(7, &sealed).unwrap(), template);
+    }
+  }
+
+  #[test]
+  fn open_rejects_tampered_backup() {
+    let vault = vault();
+    let sealed = vault.seal(7, &[0x55; 498]).unwrap();
+
+    for i in [0, 1, 20, sealed.len() - 1].iter() {
+      let mut tampered = sealed.clone();
+      tampered[*i] ^= 0x01;
+      assert!(vault.open(7, &tampered).is_err());
+    }
+  }
+
+  #[test]
+  fn open_rejects_other_position() {
+    let vault = vault();
+    let sealed = vault.seal(7, &[0x55; 498]).unwrap();
+
+    assert!(vault.open(8, &sealed).is_err());
+  }
+}